@@ -23,6 +23,10 @@ pub use info_handler::DescriptorMap;
 /// These are types generated by tonic_build.
 pub use reflection::server_reflection_server::{ServerReflection, ServerReflectionServer};
 
+/// Re-export all types generated by tonic_build, client included, to
+/// query reflection servers.
+pub use proto_includes::reflection as proto;
+
 use reflection::server_reflection_request::MessageRequest;
 use reflection::server_reflection_response::MessageResponse;
 use reflection::{ServerReflectionRequest, ServerReflectionResponse};
@@ -9,6 +9,20 @@ use std::path::Path;
 use std::process::Command;
 
 pub fn compile<P>(protos: &[P], includes: &[P]) -> Result<FileDescriptorSet>
+where
+    P: AsRef<Path>,
+{
+    let buf = descriptor_set(protos, includes)?;
+    decode(&buf)
+}
+
+/// Decodes a proto encoded descriptor set.
+pub fn decode(buf: &[u8]) -> Result<FileDescriptorSet> {
+    Ok(FileDescriptorSet::decode(buf)?)
+}
+
+/// Runs protoc on `protos`, returning the proto encoded descriptor set.
+pub fn descriptor_set<P>(protos: &[P], includes: &[P]) -> Result<Vec<u8>>
 where
     P: AsRef<Path>,
 {
@@ -41,7 +55,5 @@ where
         ));
     }
 
-    let buf = std::fs::read(descriptor_set)?;
-    let fds = FileDescriptorSet::decode(&*buf)?;
-    Ok(fds)
+    std::fs::read(descriptor_set)
 }
@@ -3,7 +3,9 @@ use std::io::{Error, ErrorKind, Result, Write};
 use std::path::{Path, PathBuf};
 
 mod compile;
-use compile::compile;
+use compile::{compile, decode};
+
+pub use compile::descriptor_set;
 
 mod symbol_map;
 
@@ -28,7 +30,17 @@ where
     P: AsRef<Path>,
 {
     // Get a proto encoded descriptor set
-    let mut descriptor_set = compile(protos, includes)?;
+    generate(compile(protos, includes)?)
+}
+
+/// Same as `compile_protos` but from the proto encoded descriptor set
+/// returned by `descriptor_set`, so protoc runs once for the callers
+/// which need it as well.
+pub fn compile_descriptor_set(buf: &[u8]) -> Result<()> {
+    generate(decode(buf)?)
+}
+
+fn generate(mut descriptor_set: prost_types::FileDescriptorSet) -> Result<()> {
     descriptor_set.file.sort_by(|a, b| a.name().cmp(b.name()));
 
     let mut buf = String::new();
@@ -0,0 +1,263 @@
+// Routes of the `google.api.http` annotations.
+//
+// prost_types drops the extensions of the options, so the descriptors
+// are decoded a second time with the messages below, which only keep
+// the methods and their `google.api.http` option.
+
+use prost::Message;
+use std::io::{Error, ErrorKind, Result, Write};
+use std::path::PathBuf;
+
+/// Static path to file with the generated HTTP routes.
+pub static GENERATED_HTTP_ROUTES_NAME: &str = "mrbig_build_http_routes.rs";
+
+#[derive(Clone, PartialEq, Message)]
+struct FileDescriptorSet {
+    #[prost(message, repeated, tag = "1")]
+    file: Vec<FileDescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct FileDescriptorProto {
+    #[prost(string, tag = "2")]
+    package: String,
+    #[prost(message, repeated, tag = "6")]
+    service: Vec<ServiceDescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ServiceDescriptorProto {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(message, repeated, tag = "2")]
+    method: Vec<MethodDescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct MethodDescriptorProto {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(message, optional, tag = "4")]
+    options: Option<MethodOptions>,
+    #[prost(bool, tag = "5")]
+    client_streaming: bool,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct MethodOptions {
+    #[prost(message, optional, tag = "72295728")]
+    http: Option<HttpRule>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct HttpRule {
+    #[prost(string, tag = "2")]
+    get: String,
+    #[prost(string, tag = "3")]
+    put: String,
+    #[prost(string, tag = "4")]
+    post: String,
+    #[prost(string, tag = "5")]
+    delete: String,
+    #[prost(string, tag = "6")]
+    patch: String,
+    #[prost(message, optional, tag = "8")]
+    custom: Option<CustomHttpPattern>,
+    #[prost(string, tag = "7")]
+    body: String,
+    #[prost(string, tag = "12")]
+    response_body: String,
+    #[prost(message, repeated, tag = "11")]
+    additional_bindings: Vec<HttpRule>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct CustomHttpPattern {
+    #[prost(string, tag = "1")]
+    kind: String,
+    #[prost(string, tag = "2")]
+    path: String,
+}
+
+/// An HTTP route of a method.
+#[derive(Debug, PartialEq)]
+struct Route {
+    verb: String,
+    pattern: String,
+    method: String,
+    body: String,
+    response_body: String,
+}
+
+/// Generates the HTTP routes of the methods annotated with
+/// `google.api.http` in the proto encoded `descriptors`, along with the
+/// descriptors the requests and responses are transcoded with.
+pub(crate) fn compile_routes(descriptors: Vec<u8>) -> Result<()> {
+    let set = FileDescriptorSet::decode(&*descriptors)?;
+    let routes = routes(&set);
+
+    // the descriptors are only needed to transcode the routes
+    let descriptors = match routes.is_empty() {
+        true => vec![],
+        false => descriptors,
+    };
+
+    let routes = routes.iter().map(|route| {
+        let Route {
+            verb,
+            pattern,
+            method,
+            body,
+            response_body,
+        } = route;
+        quote::quote! {
+            HttpRoute {
+                verb: #verb,
+                pattern: #pattern,
+                method: #method,
+                body: #body,
+                response_body: #response_body,
+            }
+        }
+    });
+
+    let code = quote::quote! {
+        /// HTTP routes of the `google.api.http` annotations.
+        pub static ROUTES: &[HttpRoute] = &[#(#routes),*];
+
+        /// Encoded `FileDescriptorSet` of the annotated methods.
+        pub static DESCRIPTORS: &[u8] = &[#(#descriptors),*];
+    };
+
+    let mut out_file: PathBuf = std::env::var_os("OUT_DIR")
+        .ok_or_else(|| Error::new(ErrorKind::Other, "OUT_DIR environment variable is not set"))
+        .map(Into::into)?;
+
+    out_file.push(GENERATED_HTTP_ROUTES_NAME);
+
+    let mut out_file = std::fs::File::create(out_file)?;
+    out_file.write_all(code.to_string().as_bytes())?;
+
+    Ok(())
+}
+
+/// The routes of the annotated methods, streaming requests aside.
+fn routes(set: &FileDescriptorSet) -> Vec<Route> {
+    let mut routes = vec![];
+
+    for file in &set.file {
+        for service in &file.service {
+            for method in &service.method {
+                let rule = match method.options.as_ref().and_then(|o| o.http.as_ref()) {
+                    Some(rule) => rule,
+                    None => continue,
+                };
+
+                let name = match file.package.as_str() {
+                    "" => format!("{}.{}", service.name, method.name),
+                    package => format!("{}.{}.{}", package, service.name, method.name),
+                };
+
+                if method.client_streaming {
+                    println!(
+                        "cargo:warning=`{}` streams its requests, its HTTP routes are skipped",
+                        name
+                    );
+                    continue;
+                }
+
+                let bindings = std::iter::once(rule).chain(rule.additional_bindings.iter());
+                routes.extend(bindings.filter_map(|rule| route(rule, &name)));
+            }
+        }
+    }
+
+    routes
+}
+
+fn route(rule: &HttpRule, method: &str) -> Option<Route> {
+    let (verb, pattern) = match rule {
+        HttpRule { custom: Some(custom), .. } => (custom.kind.to_uppercase(), &custom.path),
+        _ => [
+            ("GET", &rule.get),
+            ("PUT", &rule.put),
+            ("POST", &rule.post),
+            ("DELETE", &rule.delete),
+            ("PATCH", &rule.patch),
+        ]
+        .iter()
+        .find(|(_, pattern)| !pattern.is_empty())
+        .map(|(verb, pattern)| (verb.to_string(), *pattern))?,
+    };
+
+    Some(Route {
+        verb,
+        pattern: pattern.clone(),
+        method: method.to_string(),
+        body: rule.body.clone(),
+        response_body: rule.response_body.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn method(name: &str, http: Option<HttpRule>) -> MethodDescriptorProto {
+        MethodDescriptorProto {
+            name: name.into(),
+            options: Some(MethodOptions { http }),
+            client_streaming: false,
+        }
+    }
+
+    #[test]
+    fn routes_of_annotations() {
+        let rates = HttpRule {
+            get: "/v1/hotels/{hotel_id}/rates".into(),
+            additional_bindings: vec![HttpRule {
+                post: "/v1/rates:search".into(),
+                body: "*".into(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let purge = HttpRule {
+            custom: Some(CustomHttpPattern {
+                kind: "purge".into(),
+                path: "/v1/rates".into(),
+            }),
+            ..Default::default()
+        };
+
+        let set = FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                package: "hotel".into(),
+                service: vec![ServiceDescriptorProto {
+                    name: "Hotel".into(),
+                    method: vec![
+                        method("Rates", Some(rates)),
+                        method("Purge", Some(purge)),
+                        method("Book", None),
+                    ],
+                }],
+            }],
+        };
+
+        let route = |verb: &str, pattern: &str, method: &str, body: &str| Route {
+            verb: verb.into(),
+            pattern: pattern.into(),
+            method: method.into(),
+            body: body.into(),
+            response_body: String::new(),
+        };
+        assert_eq!(
+            routes(&set),
+            vec![
+                route("GET", "/v1/hotels/{hotel_id}/rates", "hotel.Hotel.Rates", ""),
+                route("POST", "/v1/rates:search", "hotel.Hotel.Rates", "*"),
+                route("PURGE", "/v1/rates", "hotel.Hotel.Purge", ""),
+            ]
+        );
+    }
+}
@@ -1,14 +1,21 @@
 use std::io::{Result, Write};
 use std::path::{Path, PathBuf};
 
+#[cfg(feature = "grpc")]
+mod http;
 mod protos;
 
+#[cfg(feature = "grpc")]
+pub use http::GENERATED_HTTP_ROUTES_NAME;
+
 /// Compile the proto files to generate the necessary code
-/// for gRPC servers and reflection server.
+/// for gRPC servers and reflection server, and the HTTP routes
+/// of the methods annotated with `google.api.http`.
 ///
 /// This method uses other compile methods from
 /// `grpc_reflection_build` crate and `tonic_build`,
-/// which depend on `prost_build`.
+/// which depend on `prost_build`. The `google/api/annotations.proto`
+/// and `google/api/http.proto` files can be imported.
 ///
 /// This method relies on OUT_DIR being set. It fails otherwise.
 pub fn compile_protos<P>(protos: &[P], includes: &[P]) -> Result<()>
@@ -33,6 +40,17 @@ where
             file.write_all(contents.as_bytes())?;
             builtin_protos.push(path);
         }
+
+        // only imported by the protos which need them
+        let google_api = builtin_include.join("google").join("api");
+        std::fs::create_dir_all(&google_api)?;
+        for (filename, contents) in &[
+            ("annotations.proto", protos::GOOGLE_API_ANNOTATIONS),
+            ("http.proto", protos::GOOGLE_API_HTTP),
+        ] {
+            let mut file = std::fs::File::create(google_api.join(filename))?;
+            file.write_all(contents.as_bytes())?;
+        }
     }
 
     let protos: Vec<&str> = protos
@@ -51,14 +69,20 @@ where
 
     #[cfg(feature = "grpc")]
     {
+        // the reflection descriptors and the HTTP routes are generated
+        // from the same descriptor set, tonic_build runs protoc on its own
+        let descriptors = grpc_reflection_build::descriptor_set(&protos, &includes)?;
+
         #[cfg(feature = "reflection")]
         {
-            grpc_reflection_build::compile_protos(&protos, &includes)?;
+            grpc_reflection_build::compile_descriptor_set(&descriptors)?;
         }
 
         // tonic_build runs rustfmt in OUT_DIR
         // but we shouldn't count on it(!) @TODO
         tonic_build::configure().compile(&protos, &includes)?;
+
+        http::compile_routes(descriptors)?;
     }
 
     Ok(())
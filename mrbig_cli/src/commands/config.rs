@@ -0,0 +1,144 @@
+// ############################################################################
+// #                                                                          #
+// # mrbig_cli/src/commands/config.rs                                         #
+// #                                                                          #
+// # Handcrafted with love by MrBig Mobsters                                  #
+// # All rights reserved                                                      #
+// #                                                                          #
+// #                                                                          #
+// # Description: Services configuration helpers.                             #
+// ############################################################################
+
+//! Config command.
+//!
+//! This command helps writing services configuration files. Its `encrypt`
+//! subcommand encrypts a sensitive value, such as a password, so that it
+//! can be committed in a config file. The service decrypts it at startup
+//! with the key file set in `service.key_file`.
+//!
+//! # Example
+//!
+//! Here, a new key is generated into `secrets/key` and the password read
+//! from the standard input is encrypted with it:
+//!
+//! ```ignore
+//! > mrbig config encrypt --key-file secrets/key --generate-key
+//! s3cret
+//! enc:aes-gcm:Ck1nmL0dW6x...
+//! ```
+//!
+//! The value printed is then pasted in the config file:
+//!
+//! ```ignore
+//! [db]
+//! password = "enc:aes-gcm:Ck1nmL0dW6x..."
+//! ```
+
+// Import external dependencies
+use mrbig_core::config::cipher::Key;
+use std::io::Read;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+// Import internal dependencies
+use crate::errors::ConfigError as Error;
+use crate::errors::Result;
+
+/// Config subcommands
+#[derive(Debug, StructOpt)]
+pub enum ConfigCommand {
+    /// Encrypt a value to be decrypted by services at startup
+    #[structopt(name = "encrypt")]
+    Encrypt(EncryptCommandOptions),
+}
+
+/// Encrypt command options
+#[derive(Debug, StructOpt)]
+pub struct EncryptCommandOptions {
+    /// File holding the key, as set in `service.key_file`
+    #[structopt(parse(from_os_str), long = "key-file", short = "k")]
+    pub key_file: PathBuf,
+
+    /// Generate a new key into the key file, which must not exist
+    #[structopt(long = "generate-key", short = "g")]
+    pub generate_key: bool,
+
+    /// Value to encrypt, read from the standard input when missing
+    pub value: Option<String>,
+}
+
+/// Executes the config command, passing arguments given at command-line
+pub fn execute(command: ConfigCommand) -> Result<()> {
+    match command {
+        ConfigCommand::Encrypt(options) => encrypt(options),
+    }
+}
+
+fn encrypt(options: EncryptCommandOptions) -> Result<()> {
+    debug!(
+        "Execute config encrypt command for key file {:?}",
+        options.key_file
+    );
+
+    // values given on the command line end up in the shell history,
+    // so they are read from the standard input by default
+    let encrypted = encrypt_from(options, std::io::stdin())?;
+    println!("{}", encrypted);
+    Ok(())
+}
+
+/// Encrypts the value of `options`, or the one read from `input`.
+fn encrypt_from<R: Read>(options: EncryptCommandOptions, mut input: R) -> Result<String> {
+    let key = if options.generate_key {
+        let key = Key::generate();
+        key.write(&options.key_file).map_err(Error::from)?;
+        info!("New key written to {}", options.key_file.display());
+        key
+    } else {
+        Key::read(&options.key_file).map_err(Error::from)?
+    };
+
+    let value = match options.value {
+        Some(value) => value,
+        None => {
+            let mut value = String::new();
+            input.read_to_string(&mut value).map_err(Error::ReadValue)?;
+            value.trim_end_matches(&['\n', '\r'][..]).to_string()
+        }
+    };
+
+    Ok(key.encrypt(&value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mrbig_core::config::cipher::is_encrypted;
+
+    #[test]
+    fn encrypts_values() {
+        let base = std::env::temp_dir().join(format!("mrbig_cli_encrypt_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        std::fs::create_dir_all(&base).unwrap();
+        let key_file = base.join("key");
+        let options = |generate_key, value: Option<&str>| EncryptCommandOptions {
+            key_file: key_file.clone(),
+            generate_key,
+            value: value.map(Into::into),
+        };
+
+        // a new key, then the same one, the value given or read
+        let generated = encrypt_from(options(true, Some("s3cret")), &b""[..]).unwrap();
+        let read = encrypt_from(options(false, None), &b"t0ken\n"[..]).unwrap();
+        assert!(is_encrypted(&generated) && is_encrypted(&read));
+
+        let key = Key::read(&key_file).unwrap();
+        assert_eq!(key.decrypt(&generated).unwrap(), "s3cret");
+        assert_eq!(key.decrypt(&read).unwrap(), "t0ken");
+
+        // keys are not overwritten, and must exist otherwise
+        assert!(encrypt_from(options(true, Some("s3cret")), &b""[..]).is_err());
+        std::fs::remove_dir_all(&base).unwrap();
+        assert!(encrypt_from(options(false, Some("s3cret")), &b""[..]).is_err());
+    }
+}
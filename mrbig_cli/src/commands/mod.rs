@@ -23,10 +23,12 @@ use std::time::{Duration, Instant};
 use structopt::StructOpt;
 
 // Import internal dependencies
+mod config;
 mod login;
 mod scaffold;
 
 use crate::errors::Result;
+use config::ConfigCommand;
 use login::LoginCommandOptions;
 use scaffold::ScaffoldCommandOptions;
 
@@ -70,6 +72,10 @@ pub enum Command {
     /// Login into the development sandbox
     #[structopt(name = "login")]
     Login(LoginCommandOptions),
+
+    /// Help writing services configuration
+    #[structopt(name = "config")]
+    Config(ConfigCommand),
 }
 
 // Public functions implementation
@@ -94,6 +100,10 @@ impl Command {
             Command::Clean => {
                 unimplemented!("Clean command is not yet implemented...");
             }
+
+            Command::Config(command) => {
+                config::execute(command)?;
+            }
         }
 
         // return time elapsed for running the command
@@ -74,3 +74,25 @@ pub enum HatchError {
   #[error("unknown hatch error... ╰U╯☜(◉ɷ◉ )")]
   UnknownHatchError,
 }
+
+/// Config command errors enumeration
+///
+/// Errors triggered when writing services configuration values.
+#[derive(ThisError, Debug)]
+pub enum ConfigError {
+
+  #[error("cannot read the value to encrypt")]
+  ReadValue(#[source] io::Error),
+
+  #[error("{0}")]
+  Key(String),
+}
+
+impl From<mrbig_core::Error> for ConfigError {
+  fn from(error: mrbig_core::Error) -> Self {
+    match error.inner {
+      mrbig_core::error::Inner::Other(message) => ConfigError::Key(message),
+      inner => ConfigError::Key(mrbig_core::Error { inner }.to_string()),
+    }
+  }
+}
@@ -32,7 +32,10 @@ async fn main() -> Result<(), mrbig_core::Error> {
     // New service with default configurations
     let mut service = Micro::default();
 
-    service.init().await?;
+    // Exit after printing the help, version, etc.
+    if service.init().await? == mrbig_core::Init::Exit {
+        return Ok(());
+    }
 
     // Serve the endpoints
     service.run(Contract {}).await?;
@@ -0,0 +1,9 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // compile the grpc.health client used by the testing harness.
+    #[cfg(feature = "testing")]
+    tonic_build::configure()
+        .build_server(false)
+        .compile(&["proto/health.proto"], &["proto"])?;
+
+    Ok(())
+}
@@ -0,0 +1,509 @@
+//! JWT authentication of the gRPC calls.
+//!
+//! When `service.auth` is set, the calls to the services registered with
+//! `#[mrbig_register_grpc]` must carry an `authorization: Bearer <token>`
+//! metadata entry, where the token is a JWT signed with the shared `secret`
+//! (HS256) or with one of the keys of the `jwks` file (RS256). The token
+//! must not be expired, and must be issued by `issuer` to `audience` when
+//! those are set. Calls without a valid token fail with `UNAUTHENTICATED`.
+//!
+//! The handlers get the claims of the token with `Claims::of(&request)`.
+//! tonic does not expose the request extensions to interceptors, so the
+//! claims travel in the `mrbig-claims-bin` metadata entry, which is removed
+//! from the calls of every other service so it cannot be forged.
+//!
+//! Authenticated calls are then checked against the `policies`, which
+//! give the roles or claims required to call a method, or all the methods
+//! of a service, by fully qualified name as in `hotel.Hotel.Rates`. Calls
+//! turned down fail with `PERMISSION_DENIED`. Decisions are logged, and
+//! counted by method in `mrbig_authorization_decisions_total` with the
+//! `telemetry` feature.
+//!
+//! A service opts out with `auth = false` in its `#[mrbig_register_grpc]`
+//! attribute. The health and reflection services are never authenticated.
+// tonic interceptors return their `Status`
+#![allow(clippy::result_large_err)]
+use crate::config;
+use crate::error::Error;
+use crate::interceptor::Interceptors;
+use crate::layer::{self, BoxService, Layers};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::task::Poll;
+use tonic::codegen::http::header::{HeaderName, HeaderValue};
+use tonic::metadata::{MetadataMap, MetadataValue};
+use tonic::{Request, Status};
+use tower::Service;
+
+/// Metadata entry holding the claims of the authenticated calls.
+const CLAIMS: &str = "mrbig-claims-bin";
+
+/// Metadata entry holding the method called, as in `hotel.Hotel.Rates`.
+const METHOD: &str = "mrbig-method";
+
+#[cfg(feature = "telemetry")]
+lazy_static::lazy_static! {
+    static ref DECISIONS: prometheus::IntCounterVec = prometheus::register_int_counter_vec!(
+        "mrbig_authorization_decisions_total",
+        "Total number of authorization decisions per method.",
+        &["method", "decision"]
+    )
+    .unwrap();
+}
+
+/// Claims of the token a call was authenticated with.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Claims(Map<String, Value>);
+
+impl Claims {
+    /// Claims of the token `request` was authenticated with, if any.
+    pub fn of<T>(request: &Request<T>) -> Option<Self> {
+        let bytes = request.metadata().get_bin(CLAIMS)?.to_bytes().ok()?;
+        serde_json::from_slice(&bytes).map(Claims).ok()
+    }
+
+    /// The `sub` claim, identifying the caller.
+    pub fn subject(&self) -> Option<&str> {
+        self.0.get("sub").and_then(Value::as_str)
+    }
+
+    /// The claim called `name`.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.0.get(name)
+    }
+
+    /// Deserializes the claims into `T`, for services with claims of their own.
+    pub fn deserialize<T: serde::de::DeserializeOwned>(&self) -> Result<T, Error> {
+        serde_json::from_value(Value::Object(self.0.clone()))
+            .map_err(|e| Error::new(&format!("unexpected claims: {}", e)))
+    }
+}
+
+/// Keys the tokens are checked against.
+enum Keys {
+    Secret(DecodingKey),
+    Jwks(Vec<(Option<String>, DecodingKey)>),
+}
+
+/// Validates the bearer tokens of the calls against `service.auth`, and
+/// the calls against its policies.
+pub struct Authenticator {
+    keys: Keys,
+    validation: Validation,
+    roles_claim: String,
+    policies: BTreeMap<String, config::Policy>,
+}
+
+impl Authenticator {
+    /// Reads the keys given by `config`.
+    pub fn new(config: &config::Auth) -> Result<Self, Error> {
+        let (keys, algorithm) = match (&config.secret, &config.jwks) {
+            (Some(secret), None) => (
+                Keys::Secret(DecodingKey::from_secret(secret.expose().as_bytes())),
+                Algorithm::HS256,
+            ),
+            (None, Some(path)) => (Keys::Jwks(read_jwks(path)?), Algorithm::RS256),
+            _ => {
+                return Err(Error::new(
+                    "`service.auth` requires either `secret` or `jwks`",
+                ))
+            }
+        };
+
+        let mut validation = Validation::new(algorithm);
+        validation.leeway = config.leeway.as_secs();
+        if let Some(issuer) = &config.issuer {
+            validation.set_issuer(&[issuer]);
+            validation.required_spec_claims.insert("iss".into());
+        }
+        if let Some(audience) = &config.audience {
+            validation.set_audience(&[audience]);
+            validation.required_spec_claims.insert("aud".into());
+        }
+
+        Ok(Authenticator {
+            keys,
+            validation,
+            roles_claim: config.roles_claim.clone(),
+            policies: config.policies.clone(),
+        })
+    }
+
+    /// Claims of the bearer token in `metadata`, if it is valid.
+    pub fn authenticate(&self, metadata: &MetadataMap) -> Result<Claims, Status> {
+        let token = metadata
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| Status::unauthenticated("missing bearer token"))?;
+
+        let invalid = |e: jsonwebtoken::errors::Error| {
+            log::debug!("invalid bearer token: {}", e);
+            Status::unauthenticated(format!("invalid token: {}", e))
+        };
+
+        let key = match &self.keys {
+            Keys::Secret(key) => key,
+            Keys::Jwks(keys) => {
+                let kid = jsonwebtoken::decode_header(token).map_err(invalid)?.kid;
+                find_key(keys, kid.as_deref())
+                    .ok_or_else(|| Status::unauthenticated("invalid token: unknown key"))?
+            }
+        };
+
+        jsonwebtoken::decode::<Map<String, Value>>(token, key, &self.validation)
+            .map(|data| Claims(data.claims))
+            .map_err(invalid)
+    }
+
+    /// Whether the policy of `method`, if any, lets in the caller with `claims`.
+    pub fn authorize(&self, method: &str, claims: &Claims) -> Result<(), Status> {
+        let policy = match self.policy(method) {
+            Some(policy) => policy,
+            None => return Ok(()),
+        };
+
+        let allowed = self.allows(policy, claims);
+        let decision = if allowed { "allowed" } else { "denied" };
+        #[cfg(feature = "telemetry")]
+        DECISIONS.with_label_values(&[method, decision]).inc();
+
+        let level = if allowed {
+            log::Level::Debug
+        } else {
+            log::Level::Info
+        };
+        log::log!(
+            level,
+            "authorization: `{}` {} to {:?}",
+            method,
+            decision,
+            claims.subject()
+        );
+
+        if allowed {
+            Ok(())
+        } else {
+            let message = format!("not allowed to call `{}`", method);
+            Err(Status::permission_denied(message))
+        }
+    }
+
+    // Whether `policy` lets in the caller with `claims`.
+    fn allows(&self, policy: &config::Policy, claims: &Claims) -> bool {
+        let roles = self.roles(claims);
+        let role = policy.roles.is_empty()
+            || policy
+                .roles
+                .iter()
+                .any(|role| roles.contains(&role.as_str()));
+
+        role && policy
+            .claims
+            .iter()
+            .all(|(name, value)| matches!(claims.get(name), Some(claim) if holds(claim, value)))
+    }
+
+    // The policy of the method, or else of its service.
+    fn policy(&self, method: &str) -> Option<&config::Policy> {
+        self.policies.get(method).or_else(|| {
+            let (service, _) = method.rsplit_once('.')?;
+            self.policies.get(service)
+        })
+    }
+
+    // Roles of the caller, from an array or a space separated string.
+    fn roles<'a>(&self, claims: &'a Claims) -> Vec<&'a str> {
+        match claims.get(&self.roles_claim) {
+            Some(Value::String(roles)) => roles.split_whitespace().collect(),
+            Some(Value::Array(roles)) => roles.iter().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Authenticates `request`, checks the policy of the method called, and
+    /// hands the claims to the handler.
+    fn intercept(&self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let claims = self.authenticate(request.metadata())?;
+        if !self.policies.is_empty() {
+            let method = request
+                .metadata()
+                .get(METHOD)
+                .and_then(|method| method.to_str().ok())
+                .ok_or_else(|| Status::permission_denied("unknown method"))?;
+            self.authorize(method, &claims)?;
+        }
+        let json = serde_json::to_vec(&claims.0)
+            .map_err(|e| Status::internal(format!("cannot encode the claims: {}", e)))?;
+        request
+            .metadata_mut()
+            .insert_bin(CLAIMS, MetadataValue::from_bytes(&json));
+        Ok(request)
+    }
+}
+
+impl std::fmt::Debug for Authenticator {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let keys = match &self.keys {
+            Keys::Secret(_) => "secret".to_string(),
+            Keys::Jwks(keys) => format!("jwks({})", keys.len()),
+        };
+        write!(f, "Authenticator({})", keys)
+    }
+}
+
+fn read_jwks(path: &std::path::Path) -> Result<Vec<(Option<String>, DecodingKey)>, Error> {
+    let invalid = |e: &dyn std::fmt::Display| {
+        Error::new(&format!("invalid JWKS file `{}`: {}", path.display(), e))
+    };
+
+    let file = std::fs::File::open(path).map_err(|e| invalid(&e))?;
+    let jwks: JwkSet = serde_json::from_reader(file).map_err(|e| invalid(&e))?;
+    if jwks.keys.is_empty() {
+        return Err(invalid(&"no keys"));
+    }
+
+    jwks.keys
+        .iter()
+        .map(|jwk| {
+            let key = DecodingKey::from_jwk(jwk).map_err(|e| invalid(&e))?;
+            Ok((jwk.common.key_id.clone(), key))
+        })
+        .collect()
+}
+
+// The key with id `kid`, or the only key for tokens without one.
+fn find_key<'a>(
+    keys: &'a [(Option<String>, DecodingKey)],
+    kid: Option<&str>,
+) -> Option<&'a DecodingKey> {
+    match kid {
+        Some(kid) => keys
+            .iter()
+            .find(|(id, _)| id.as_deref() == Some(kid))
+            .map(|(_, key)| key),
+        None if keys.len() == 1 => Some(&keys[0].1),
+        None => None,
+    }
+}
+
+// Whether `claim` is `value`, or an array holding it.
+fn holds(claim: &Value, value: &str) -> bool {
+    match claim {
+        Value::String(claim) => claim == value,
+        Value::Array(claims) => claims.iter().any(|claim| holds(claim, value)),
+        claim => serde_json::from_str::<Value>(value).ok().as_ref() == Some(claim),
+    }
+}
+
+/// Records the method called in the metadata, for the interceptors which
+/// cannot see the path of the request.
+#[derive(Clone, Debug)]
+struct RecordMethod(BoxService);
+
+impl Service<layer::Request> for RecordMethod {
+    type Response = layer::Response;
+    type Error = layer::BoxError;
+    type Future = <BoxService as Service<layer::Request>>::Future;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: layer::Request) -> Self::Future {
+        // "/hotel.Hotel/Rates" is known as "hotel.Hotel.Rates"
+        let method = request
+            .uri()
+            .path()
+            .trim_start_matches('/')
+            .replace('/', ".");
+        let name = HeaderName::from_static(METHOD);
+        match HeaderValue::from_str(&method) {
+            Ok(method) => request.headers_mut().insert(name, method),
+            Err(_) => request.headers_mut().remove(name),
+        };
+        self.0.call(request)
+    }
+}
+
+// Removes the claims a client may have sent itself.
+fn strip(mut request: Request<()>) -> Result<Request<()>, Status> {
+    request.metadata_mut().remove_bin(CLAIMS);
+    Ok(request)
+}
+
+/// Registers the authentication of the `authenticated` services as set by
+/// `auth`, and the removal of forged claims for the `exempt` ones. The
+/// layer recording the methods called is added inside `layers` when there
+/// are policies.
+pub fn push(
+    interceptors: &mut Interceptors,
+    layers: &mut Layers,
+    auth: Option<&config::Auth>,
+    authenticated: &[&str],
+    exempt: &[&str],
+) -> Result<(), Error> {
+    let auth = match auth {
+        Some(auth) => auth,
+        None => {
+            let all = [authenticated, exempt].concat();
+            if !all.is_empty() {
+                interceptors.push(Some(&all), strip);
+            }
+            return Ok(());
+        }
+    };
+
+    for name in auth.policies.keys() {
+        if !authenticated.iter().any(|service| covers(name, service)) {
+            return Err(Error::new(&format!(
+                "policy for `{}`, which is not an authenticated service",
+                name
+            )));
+        }
+    }
+    if !auth.policies.is_empty() {
+        layers.push(tower::layer::layer_fn(RecordMethod));
+    }
+
+    if !authenticated.is_empty() {
+        let authenticator = Arc::new(Authenticator::new(auth)?);
+        interceptors.push(Some(authenticated), move |request| {
+            authenticator.intercept(request)
+        });
+    }
+    if !exempt.is_empty() {
+        interceptors.push(Some(exempt), strip);
+    }
+
+    Ok(())
+}
+
+// Whether the policy `name` is about `service` or one of its methods.
+fn covers(name: &str, service: &str) -> bool {
+    match name.strip_prefix(service) {
+        Some(method) => method.is_empty() || method.starts_with('.'),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{EncodingKey, Header};
+    use serde_json::json;
+
+    fn auth(toml: &str) -> config::Auth {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn bearer(token: &str) -> Request<()> {
+        let mut request = Request::new(());
+        let value = format!("Bearer {}", token).parse().unwrap();
+        request.metadata_mut().insert("authorization", value);
+        request
+    }
+
+    fn token(claims: Value) -> String {
+        let key = EncodingKey::from_secret(b"s3cret");
+        jsonwebtoken::encode(&Header::default(), &claims, &key).unwrap()
+    }
+
+    fn in_an_hour() -> u64 {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap();
+        now.as_secs() + 3600
+    }
+
+    #[test]
+    fn validates_tokens() {
+        let config = auth(
+            r#"
+            secret = "s3cret"
+            issuer = "idp"
+            audience = "greeter"
+            "#,
+        );
+        assert_eq!(config.leeway, std::time::Duration::from_secs(60));
+        let authenticator = Authenticator::new(&config).unwrap();
+
+        let exp = in_an_hour();
+        let valid = json!({"sub": "ada", "iss": "idp", "aud": "greeter", "exp": exp});
+        let authenticated = authenticator.intercept(bearer(&token(valid))).unwrap();
+        let claims = Claims::of(&authenticated).unwrap();
+        assert_eq!(claims.subject(), Some("ada"));
+        assert_eq!(claims.get("iss"), Some(&json!("idp")));
+
+        let invalid = vec![
+            json!({"sub": "ada", "iss": "idp", "aud": "greeter", "exp": 1000}),
+            json!({"sub": "ada", "iss": "other", "aud": "greeter", "exp": exp}),
+            json!({"sub": "ada", "iss": "idp", "exp": exp}),
+            json!({"sub": "ada", "iss": "idp", "aud": "greeter"}),
+        ];
+        for claims in invalid {
+            let status = authenticator.intercept(bearer(&token(claims))).unwrap_err();
+            assert_eq!(status.code(), tonic::Code::Unauthenticated);
+        }
+
+        let status = authenticator.intercept(Request::new(())).unwrap_err();
+        assert_eq!(status.message(), "missing bearer token");
+    }
+
+    #[test]
+    fn authorizes_methods() {
+        let authenticator = Authenticator::new(&auth(
+            r#"
+            secret = "s3cret"
+            [policies."hotel.Hotel"]
+            claims = { tenant = "acme" }
+            [policies."hotel.Hotel.Rates"]
+            roles = ["admin", "auditor"]
+            "#,
+        ))
+        .unwrap();
+
+        let allowed = |claims: Value, method: &str| {
+            let claims = Claims(claims.as_object().unwrap().clone());
+            authenticator.authorize(method, &claims).is_ok()
+        };
+
+        // the method policy wins over the service one
+        assert!(allowed(json!({"roles": ["admin"]}), "hotel.Hotel.Rates"));
+        assert!(allowed(
+            json!({"roles": "user auditor"}),
+            "hotel.Hotel.Rates"
+        ));
+        assert!(!allowed(json!({"roles": ["user"]}), "hotel.Hotel.Rates"));
+        assert!(!allowed(json!({}), "hotel.Hotel.Rates"));
+
+        assert!(allowed(json!({"tenant": "acme"}), "hotel.Hotel.Book"));
+        assert!(allowed(
+            json!({"tenant": ["other", "acme"]}),
+            "hotel.Hotel.Book"
+        ));
+        assert!(!allowed(json!({"tenant": "acme corp"}), "hotel.Hotel.Book"));
+
+        // methods without a policy are open
+        assert!(allowed(json!({}), "helloworld.Greeter.SayHello"));
+
+        let status = authenticator
+            .authorize("hotel.Hotel.Rates", &Claims::default())
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+    }
+
+    #[test]
+    fn strips_forged_claims() {
+        let mut request = Request::new(());
+        let forged = MetadataValue::from_bytes(br#"{"sub":"root"}"#);
+        request.metadata_mut().insert_bin(CLAIMS, forged);
+        assert!(Claims::of(&request).is_some());
+
+        let request = strip(request).unwrap();
+        assert_eq!(Claims::of(&request), None);
+    }
+}
@@ -1,23 +1,204 @@
-use serde_derive::Deserialize;
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
 use std::io::Read;
+use std::net::ToSocketAddrs;
+
+pub mod cipher;
+mod cli;
+mod env;
+mod format;
+mod listen;
+mod profile;
+mod provenance;
+pub mod schema;
+mod secret;
+mod source;
+pub mod units;
+mod validate;
+mod value;
+
+pub use cli::{Cli, Flag};
+pub use env::{ENV_EXTRA, ENV_PREFIX, ENV_PROFILE, ENV_SEPARATOR, ENV_SERVICE};
+pub use format::Format;
+pub use listen::Listen;
+pub use profile::PROFILE_SECTION;
+use provenance::Sources;
+pub use provenance::{Source, REDACTED};
+pub use secret::Secret;
+pub use source::{ConfigSource, DirectorySource, EnvSource, FileSource, HttpSource, Values};
+pub use validate::{Validation, ValidationError};
+use value::Coerce;
 
 const PORT: &str = "port";
 const CONFIG: &str = "config";
+const CONFIG_FORMAT: &str = "config-format";
 const HOSTNAME: &str = "hostname";
 const DEBUG: &str = "debug";
+const PROFILE: &str = "profile";
+const PRINT_CONFIG: &str = "print-config";
+const CONFIG_SCHEMA: &str = "config-schema";
+const HELP: &str = "help";
+const VERSION: &str = "version";
+const SET: &str = "set";
+
+/// Long names of `Mr. Big`'s own flags, which services cannot redeclare.
+pub(crate) const RESERVED_FLAGS: &[&str] = &[
+    PORT,
+    CONFIG,
+    CONFIG_FORMAT,
+    HOSTNAME,
+    DEBUG,
+    PROFILE,
+    PRINT_CONFIG,
+    CONFIG_SCHEMA,
+    SET,
+    HELP,
+    VERSION,
+];
 
 /// gRPC server related configuration parameters.
-#[derive(Clone, Debug, Default, Deserialize)]
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, PartialEq, Serialize)]
 pub struct GrpcServer {
+    /// Maximum number of concurrent requests on each connection.
     pub concurrency_limit_per_connection: Option<usize>,
+    /// Timeout of each request, such as `"30s"`.
+    #[serde(
+        default,
+        with = "units::option_duration",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schemars(with = "Option<units::DurationSchema>")]
     pub timeout: Option<std::time::Duration>,
+    /// Interval of the TCP keepalive probes, such as `"1m"`.
+    #[serde(
+        default,
+        with = "units::option_duration",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schemars(with = "Option<units::DurationSchema>")]
     pub tcp_keepalive: Option<std::time::Duration>,
+    /// Serve the gRPC reflection service.
     #[serde(default = "default_grpc_reflection")]
     pub reflection: bool,
+    /// Serve over TLS, see `Tls`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<Tls>,
+}
+
+/// TLS parameters of the gRPC server. Requires the `tls` feature.
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+pub struct Tls {
+    /// PEM file holding the server certificate chain.
+    pub cert: std::path::PathBuf,
+    /// PEM file holding the server private key.
+    pub key: std::path::PathBuf,
+    /// PEM file holding the certificate authorities which sign the client
+    /// certificates. When set, clients must present one (mutual TLS).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_ca: Option<std::path::PathBuf>,
+    /// Interval at which the files are checked for changes, such as `"1m"`.
+    #[serde(default = "default_tls_reload_interval", with = "units::duration")]
+    #[schemars(with = "units::DurationSchema")]
+    pub reload_interval: std::time::Duration,
+}
+
+/// gRPC-Web parameters of the server. Requires the `web` feature.
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, PartialEq, Serialize)]
+pub struct GrpcWeb {
+    /// Cross-origin calls allowed from browsers.
+    #[serde(default)]
+    pub cors: Cors,
+}
+
+/// Cross-origin resource sharing (CORS) parameters.
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+pub struct Cors {
+    /// Origins allowed to call, such as `"https://app.example.com"`, or
+    /// `"*"` for any. Calls from other origins are left to the browsers
+    /// to turn down.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    /// Request headers allowed on top of the gRPC-Web ones, such as
+    /// `"authorization"`.
+    #[serde(default)]
+    pub allowed_headers: Vec<String>,
+    /// Response headers exposed on top of `grpc-status` and `grpc-message`.
+    #[serde(default)]
+    pub exposed_headers: Vec<String>,
+    /// Allow the calls with credentials, such as cookies.
+    #[serde(default)]
+    pub allow_credentials: bool,
+    /// Time browsers may cache the preflight responses, such as `"10m"`.
+    #[serde(default = "default_cors_max_age", with = "units::duration")]
+    #[schemars(with = "units::DurationSchema")]
+    pub max_age: std::time::Duration,
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Cors {
+            allowed_origins: vec![],
+            allowed_headers: vec![],
+            exposed_headers: vec![],
+            allow_credentials: false,
+            max_age: default_cors_max_age(),
+        }
+    }
+}
+
+/// HTTP/JSON transcoding parameters of the server. Requires the
+/// `transcoding` feature.
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, PartialEq, Serialize)]
+pub struct Transcoding {
+    /// Write the fields left to their default value in the JSON responses.
+    #[serde(default)]
+    pub emit_defaults: bool,
+}
+
+/// JWT authentication of the gRPC calls. Requires the `auth` feature.
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+pub struct Auth {
+    /// Shared secret the HS256 tokens are signed with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<Secret<String>>,
+    /// JWKS file holding the public keys the RS256 tokens are signed with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jwks: Option<std::path::PathBuf>,
+    /// Expected `iss` claim, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    /// Expected `aud` claim, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audience: Option<String>,
+    /// Clock skew tolerated when checking the expiry, such as `"30s"`.
+    #[serde(default = "default_auth_leeway", with = "units::duration")]
+    #[schemars(with = "units::DurationSchema")]
+    pub leeway: std::time::Duration,
+    /// Claim listing the roles of the caller, as an array or a space
+    /// separated string.
+    #[serde(default = "default_roles_claim")]
+    pub roles_claim: String,
+    /// Policies of the methods, such as `"hotel.Hotel.Rates"`, or of all
+    /// the methods of a service, such as `"hotel.Hotel"`. Methods without
+    /// a policy are open to any authenticated caller.
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub policies: std::collections::BTreeMap<String, Policy>,
+}
+
+/// Callers allowed to call a method.
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, PartialEq, Serialize)]
+pub struct Policy {
+    /// Roles allowed, any of them will do.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+    /// Claims required, with their values. A claim holding an array must
+    /// contain the value.
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub claims: std::collections::BTreeMap<String, String>,
 }
 
 /// `Mr. Big` service specific configuration parameters.
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
 pub struct Service {
     /// Port number to bind when serving.
     #[serde(default = "default_port")]
@@ -25,6 +206,15 @@ pub struct Service {
     /// Hostname to bind to when serving.
     #[serde(default = "default_hostname")]
     pub hostname: String,
+    /// Addresses to serve at instead of `hostname` and `port`, such as
+    /// `"tcp://0.0.0.0:3000"` or `"unix:///run/micro.sock"`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schemars(with = "Vec<Listen>")]
+    pub listen: Vec<String>,
+    /// Permissions of the Unix domain sockets, such as `"0660"`.
+    #[serde(default = "default_socket_mode", with = "listen::mode")]
+    #[schemars(with = "listen::ModeSchema")]
+    pub socket_mode: u32,
     /// Enable debug mode which prints verbose messages.
     #[serde(default)]
     pub debug: bool,
@@ -37,20 +227,102 @@ pub struct Service {
     /// gRPC server related configuration.
     #[serde(default)]
     pub grpc_server: GrpcServer,
+    /// Time to keep serving on shutdown once the health is set to
+    /// `NOT_SERVING`, for load balancers to notice, such as `"5s"`.
+    #[serde(default, with = "units::duration")]
+    #[schemars(with = "units::DurationSchema")]
+    pub shutdown_grace: std::time::Duration,
+    /// Time allowed on shutdown to the in-flight requests to complete.
+    #[serde(default = "default_drain_timeout", with = "units::duration")]
+    #[schemars(with = "units::DurationSchema")]
+    pub drain_timeout: std::time::Duration,
+    /// Accept gRPC-Web calls, over HTTP/1.1 as well, if set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grpc_web: Option<GrpcWeb>,
+    /// Serve the HTTP routes of the `google.api.http` annotations, over
+    /// HTTP/1.1 as well, if set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transcoding: Option<Transcoding>,
+    /// JWT authentication of the gRPC calls, none if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<Auth>,
     /// Metrics related configurations.
     #[cfg(feature = "telemetry")]
     #[serde(default)]
     pub metrics: crate::metrics::Config,
+    /// Dotted keys of the values to redact when printing the
+    /// configuration, for instance `db.password`.
+    #[serde(default)]
+    pub secrets: Vec<String>,
+    /// File holding the key which decrypts the `enc:` values.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_file: Option<std::path::PathBuf>,
+}
+
+impl Service {
+    /// Addresses to serve at: those of `listen`, or `hostname` and `port`
+    /// when there are none.
+    pub fn addresses(&self) -> Result<Vec<Listen>, crate::error::Error> {
+        if self.listen.is_empty() {
+            let addr = (self.hostname.as_str(), self.port)
+                .to_socket_addrs()
+                .map_err(|e| format!("cannot resolve `{}`: {}", self.hostname, e))?
+                .next()
+                .ok_or_else(|| format!("`{}` resolves to no address", self.hostname))?;
+            return Ok(vec![Listen::Tcp(addr)]);
+        }
+
+        let addresses = self
+            .listen
+            .iter()
+            .map(|listen| listen.parse())
+            .collect::<Result<_, String>>()?;
+        Ok(addresses)
+    }
 }
 
 /// Config struct used to deserialize all the configuration parameters.
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Deserialize)]
 pub struct Config {
     /// `Mr. Big` specific configuration parameters.
     #[serde(default = "default_service")]
     pub service: Service,
     #[serde(default)]
     raw: toml::value::Table,
+    /// Command line arguments the config was loaded from.
+    #[serde(skip)]
+    args: Vec<String>,
+    /// Format of the config file the config was loaded from.
+    #[serde(skip)]
+    format: Format,
+    /// Origin of the values set while loading.
+    #[serde(skip)]
+    sources: Sources,
+    /// Format requested with `--print-config`, if any.
+    #[serde(skip)]
+    print: Option<Format>,
+    /// Command line description the config was loaded with.
+    #[serde(skip)]
+    cli: Cli,
+    /// Dotted keys of the values which held references to files or
+    /// environment variables, or were encrypted.
+    #[serde(skip)]
+    resolved: Vec<String>,
+}
+
+/// Prints `Mr. Big`'s parameters and the user defined ones, except
+/// the values marked as secret or resolved from references.
+impl std::fmt::Debug for Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut raw = self.raw.clone();
+        provenance::redact(&mut raw, &self.secrets());
+
+        f.debug_struct("Config")
+            .field("service", &self.service)
+            .field("raw", &raw)
+            .field("format", &self.format)
+            .finish_non_exhaustive()
+    }
 }
 
 fn default_service() -> Service {
@@ -64,17 +336,36 @@ fn default_hostname() -> String {
     "0.0.0.0".into()
 }
 
+fn default_socket_mode() -> u32 {
+    0o660
+}
+
 fn default_logger_filters() -> String {
     "h2=warn,hyper=warn,tower_buffer=warn".into()
 }
 
-fn default_grpc_reflection() -> bool {
-    true
+fn default_drain_timeout() -> std::time::Duration {
+    std::time::Duration::from_secs(30)
+}
+
+fn default_tls_reload_interval() -> std::time::Duration {
+    std::time::Duration::from_secs(30)
+}
+
+fn default_cors_max_age() -> std::time::Duration {
+    std::time::Duration::from_secs(600)
+}
+
+fn default_auth_leeway() -> std::time::Duration {
+    std::time::Duration::from_secs(60)
+}
+
+fn default_roles_claim() -> String {
+    "roles".into()
 }
 
-fn usage(program: &str, opts: getopts::Options) -> String {
-    let brief = format!("Usage: {} [options]", program);
-    opts.usage(&brief)
+fn default_grpc_reflection() -> bool {
+    true
 }
 
 impl Default for Config {
@@ -91,84 +382,462 @@ impl Config {
         Config::from_args_vec(std::env::args().collect())
     }
 
+    /// Format of the config file the configuration was loaded from.
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
     /// Try to deserialize into type `T` the configuration values which are not
     /// `Mr. Big` specific. Type `T` must implement `serde::Deserialize`.
-    pub fn try_raw_into<'de, T>(&mut self) -> std::result::Result<T, toml::de::Error>
+    /// On failure, the error names the format and the offending key: it is
+    /// an `Inner::Key` error, holding the `toml::de::Error` this method
+    /// failed with before YAML and JSON were supported.
+    pub fn try_raw_into<'de, T>(&mut self) -> std::result::Result<T, crate::error::Error>
     where
         T: serde::de::Deserialize<'de>,
     {
-        // consume the raw TOML table
-        let value = toml::Value::Table(std::mem::take(&mut self.raw));
-        value.try_into()
+        // the raw table is kept to print the configuration
+        let value = toml::Value::Table(self.raw.clone());
+        deserialize(value, None, self.format)
+    }
+
+    /// Returns where the value at the dotted `key` came from.
+    pub fn source_of(&self, key: &str) -> Source {
+        self.sources.get(key)
+    }
+
+    /// Format requested with the `--print-config` flag, if present.
+    pub fn print_format(&self) -> Option<Format> {
+        self.print
+    }
+
+    /// Writes the effective configuration in the given format, each value
+    /// annotated with its origin. The user defined parameters are printed as
+    /// they were set, see `dump_with` to include their defaults. Values marked
+    /// in `service.secrets`, and the ones set for `Secret` fields of the user
+    /// defined parameters, are redacted.
+    pub fn dump(&self, format: Format) -> std::result::Result<String, crate::error::Error> {
+        self.dump_table(self.raw.clone(), format)
+    }
+
+    /// Same as `dump` but prints the user defined parameters from `extra`,
+    /// so the values left to their defaults are included.
+    pub fn dump_with<E>(
+        &self,
+        extra: &E,
+        format: Format,
+    ) -> std::result::Result<String, crate::error::Error>
+    where
+        E: serde::Serialize,
+    {
+        match toml::Value::try_from(extra).map_err(|e| e.to_string())? {
+            toml::Value::Table(table) => self.dump_table(table, format),
+            _ => Err(crate::error::Error::new(
+                "extra configuration must serialize to a table",
+            )),
+        }
+    }
+
+    fn dump_table(
+        &self,
+        mut table: toml::value::Table,
+        format: Format,
+    ) -> std::result::Result<String, crate::error::Error> {
+        provenance::redact_kind(&mut table, &self.cli.extra);
+
+        let service = toml::Value::try_from(&self.service).map_err(|e| e.to_string())?;
+        table.insert("service".into(), service);
+
+        provenance::redact(&mut table, &self.secrets());
+        provenance::annotate(&table, &self.sources, format)
     }
 
-    fn from_bytes(buffer: Vec<u8>) -> std::result::Result<Config, crate::error::Error> {
-        let mut raw: toml::value::Table = toml::de::from_slice(&buffer)?;
+    /// Dotted keys of the values to redact when printed.
+    fn secrets(&self) -> Vec<String> {
+        let mut secrets = self.service.secrets.clone();
+        secrets.extend(self.resolved.iter().cloned());
+        secrets
+    }
 
+    fn from_table(
+        mut raw: toml::value::Table,
+        format: Format,
+    ) -> std::result::Result<Config, crate::error::Error> {
         let service: Service = match raw.remove("service") {
-            Some(srv) => srv.try_into()?,
+            Some(srv) => deserialize(srv, Some("service"), format)?,
             None => toml::from_str("")?,
         };
 
-        Ok(Config { service, raw })
+        Ok(Config {
+            service,
+            raw,
+            args: vec![],
+            format,
+            sources: Sources::default(),
+            print: None,
+            cli: Cli::default(),
+            resolved: vec![],
+        })
     }
 
-    fn from_file(path: &str) -> std::result::Result<Config, crate::error::Error> {
+    /// Parses the configuration from the contents of a config file
+    /// written in the given format.
+    pub fn from_bytes(
+        buffer: &[u8],
+        format: Format,
+    ) -> std::result::Result<Config, crate::error::Error> {
+        Config::from_table(format.parse(buffer)?, format)
+    }
+
+    fn table_from_file(
+        path: &std::path::Path,
+        format: Format,
+    ) -> std::result::Result<toml::value::Table, crate::error::Error> {
         let mut f = std::fs::File::open(path)?;
         let mut buffer: Vec<u8> = Vec::new();
 
         f.read_to_end(&mut buffer)?;
 
-        Config::from_bytes(buffer)
+        format.parse(&buffer)
     }
 
+    /// Read configuration values from the command line arguments, the
+    /// environment variables and a config TOML file if available.
+    ///
+    /// Values are layered with the following precedence, from lowest
+    /// to highest:
+    /// 1. defaults
+    /// 2. TOML file given by `--config`
+    /// 3. `[profile.<name>]` section of that file, when a profile is
+    ///    selected with `--profile` (or `MRBIG_PROFILE`)
+    /// 4. profile overlay file, `config.<name>.toml` for `config.toml`
+    /// 5. environment variables (see `ENV_PREFIX`), for instance
+    ///    `MRBIG_SERVICE__PORT=8080` or `MRBIG_EXTRA__MY_PORT=39999`
+    /// 6. command line flags
     pub fn from_args_vec(args: Vec<String>) -> std::result::Result<Config, crate::error::Error> {
+        Config::from_args_and_env(args, std::env::vars())
+    }
+
+    /// Same as `from_args_vec` but reads the environment variables from
+    /// `vars` instead of the process environment.
+    pub fn from_args_and_env<I>(
+        args: Vec<String>,
+        vars: I,
+    ) -> std::result::Result<Config, crate::error::Error>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        Config::from_args_with(args, vars, Cli::default())
+    }
+
+    /// Same as `from_args_and_env` but also accepts the extra flags declared
+    /// in `cli`, and lists the user defined parameters in the usage text.
+    ///
+    /// Fails with an `Inner::Exit` error holding the text to print when
+    /// `--help` or `--version` is given.
+    pub fn from_args_with<I>(
+        args: Vec<String>,
+        vars: I,
+        cli: Cli,
+    ) -> std::result::Result<Config, crate::error::Error>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let origin = args.clone();
         let mut args = args;
         let program = args.remove(0);
 
         let mut opts = getopts::Options::new();
-        opts.optopt("c", CONFIG, "set TOML config file name", "NAME");
+        opts.optopt("c", CONFIG, "set config file name", "NAME");
+        opts.optopt(
+            "",
+            CONFIG_FORMAT,
+            "set config file format, guessed from the extension by default",
+            "toml|yaml|json",
+        );
+        opts.optopt("", PROFILE, "set configuration profile", "NAME");
         opts.optopt("p", PORT, "set port to bind server", "PORT");
         opts.optopt("", HOSTNAME, "set hostname to bind server", "HOSTNAME");
         opts.optflagmulti("d", DEBUG, "enable debug");
-        opts.optflag("h", "help", "print this help menu");
+        opts.optflagopt(
+            "",
+            PRINT_CONFIG,
+            "print the effective configuration and exit",
+            "toml|json|yaml",
+        );
+        opts.optflag(
+            "",
+            CONFIG_SCHEMA,
+            "print the JSON Schema of the configuration and exit",
+        );
+        opts.optmulti(
+            "",
+            SET,
+            "set the configuration value at a dotted key, can be repeated",
+            "KEY=VALUE",
+        );
+        opts.optflag("h", HELP, "print this help menu");
+        opts.optflag("V", VERSION, "print version information");
+        cli.register(&mut opts);
         let matches = opts.parse(&args[..])?;
 
-        if matches.opt_present("h") {
-            panic!(usage(&program, opts));
+        if matches.opt_present(HELP) {
+            return Err(crate::error::Error::exit(cli.usage(&program, &opts)));
+        }
+
+        if matches.opt_present(VERSION) {
+            return Err(crate::error::Error::exit(cli.version(&program)));
         }
 
-        // Command line flags override TOML
-        let mut cfg_toml: Config = match matches.opt_str(CONFIG) {
-            Some(path) => Config::from_file(&path)?,
-            None => toml::from_str("")?, // create a default config
+        if matches.opt_present(CONFIG_SCHEMA) {
+            let schema =
+                serde_json::to_string_pretty(&cli.json_schema()).map_err(|e| e.to_string())?;
+            return Err(crate::error::Error::exit(schema));
+        }
+
+        let vars: Vec<(String, String)> = vars.into_iter().collect();
+
+        let profile = matches.opt_str(PROFILE).or_else(|| env::profile(&vars));
+
+        let mut format = match matches.opt_str(CONFIG_FORMAT) {
+            Some(format) => Some(format.parse::<Format>()?),
+            None => None,
         };
 
-        let mut srv = &mut cfg_toml.service;
+        let print = match (
+            matches.opt_present(PRINT_CONFIG),
+            matches.opt_str(PRINT_CONFIG),
+        ) {
+            (true, Some(format)) => Some(format.parse::<Format>()?),
+            (true, None) => Some(Format::Toml),
+            (false, _) => None,
+        };
+
+        let mut sources = Sources::default();
+
+        let mut table = match (matches.opt_str(CONFIG), profile) {
+            (Some(path), profile) => {
+                let path = std::path::Path::new(&path);
+                let format = *format.get_or_insert_with(|| Format::from_path(path));
+                let mut table = Config::table_from_file(path, format)?;
+                sources.record_table(&mut vec![], &table, &Source::File(path.to_path_buf()));
+                // Profile overrides the base file
+                profile::apply(
+                    &mut table,
+                    path,
+                    profile.as_deref(),
+                    |p| Config::table_from_file(p, format),
+                    &mut sources,
+                )?;
+                table
+            }
+            (None, Some(profile)) => {
+                return Err(format!("profile `{}` requires a config file", profile).into());
+            }
+            (None, None) => toml::value::Table::new(), // use the defaults
+        };
+
+        let mut values = Values::new(&mut table, &mut sources);
+
+        // Sources declared by the service override the config file
+        for source in &cli.sources {
+            source.load(&mut values)?;
+        }
+
+        // Environment variables override the other sources
+        EnvSource::from_vars(vars.clone()).load(&mut values)?;
+
+        // Flags declared by the service override environment variables
+        for flag in &cli.flags {
+            let value = match (flag.switch, matches.opt_str(&flag.long)) {
+                (true, _) if matches.opt_present(&flag.long) => toml::Value::Boolean(true),
+                (false, Some(value)) => toml::Value::String(value),
+                _ => continue,
+            };
+            let path: Vec<String> = flag.key.split('.').map(String::from).collect();
+            value::insert_path(&mut table, &path, value);
+            sources.record(&path, Source::Cli(format!("--{}", flag.long)));
+        }
+
+        // Typed flags override the declared ones
+        let cli_flag = |flag: &str| Source::Cli(format!("--{}", flag));
+        let key = |k: &str| vec!["service".to_string(), k.to_string()];
+        let mut set_flag = |flag: &str, k: &str, value: toml::Value| {
+            value::insert_path(&mut table, &key(k), value);
+            sources.record(&key(k), cli_flag(flag));
+        };
 
         if let Some(port) = matches.opt_str(PORT) {
-            srv.port = port
+            let port: u16 = port
                 .parse()
                 .map_err(|e: std::num::ParseIntError| e.to_string())?;
+            set_flag(PORT, "port", toml::Value::Integer(port.into()));
         }
 
         if let Some(hostname) = matches.opt_str(HOSTNAME) {
-            srv.hostname = hostname;
+            set_flag(HOSTNAME, "hostname", toml::Value::String(hostname));
         }
 
         if matches.opt_present(DEBUG) {
-            srv.debug = true;
+            set_flag(DEBUG, "debug", toml::Value::Boolean(true));
             if matches.opt_count(DEBUG) > 1 {
-                srv.trace = true;
+                set_flag(DEBUG, "trace", toml::Value::Boolean(true));
+            }
+        }
+
+        // Generic overrides come last, and have the last word
+        for arg in matches.opt_strs(SET) {
+            let (mut path, value) = cli.set(&arg)?;
+            let source = Source::Cli(format!("--{}", SET));
+            match &value {
+                toml::Value::Table(inner) => sources.record_table(&mut path, inner, &source),
+                _ => sources.record(&path, source),
             }
+            // tables are merged into the existing ones
+            let mut overlay = toml::value::Table::new();
+            value::insert_path(&mut overlay, &path, value);
+            value::merge(&mut table, overlay);
         }
 
+        // References are resolved and values decrypted once every value is known
+        let mut resolved = vec![];
+        secret::resolve(&mut table, &vars, &mut resolved)?;
+        cipher::decrypt(&mut table, &mut resolved)?;
+
+        let mut cfg_toml = Config::from_table(table, format.unwrap_or_default())?;
+
+        cfg_toml.args = origin;
+        cfg_toml.sources = sources;
+        cfg_toml.print = print;
+        cfg_toml.cli = cli;
+        cfg_toml.resolved = resolved;
+
         Ok(cfg_toml)
     }
+
+    /// Reads the configuration again from the same command line arguments,
+    /// config files and environment it was first loaded from.
+    pub fn reload(&self) -> std::result::Result<Config, crate::error::Error> {
+        if self.args.is_empty() {
+            return Err(crate::error::Error::new(
+                "config was not loaded from arguments, cannot reload",
+            ));
+        }
+
+        Config::from_args_with(self.args.clone(), std::env::vars(), self.cli.clone())
+    }
+
+    /// Checks `Mr. Big`'s own parameters (ports, addresses, metrics
+    /// port clashing with the server's) and records the errors in `validation`.
+    pub fn validate(&self, validation: &mut Validation) {
+        validate::service(&self.service, validation);
+    }
+
+    /// Restores in `new` the parameters which cannot change while the server
+    /// is running (port, hostname, gRPC server, gRPC-Web, transcoding,
+    /// authentication and metrics parameters).
+    /// Returns a description of each discarded change.
+    pub fn retain_static(&self, new: &mut Config) -> Vec<String> {
+        fn retain<T>(key: &str, old: &T, new: &mut T, changes: &mut Vec<String>)
+        where
+            T: Clone + PartialEq + std::fmt::Debug,
+        {
+            if old != new {
+                changes.push(format!("{}: {:?} -> {:?}", key, old, new));
+                *new = old.clone();
+            }
+        }
+
+        let (old, new) = (&self.service, &mut new.service);
+        let mut changes = vec![];
+
+        retain("service.port", &old.port, &mut new.port, &mut changes);
+        retain(
+            "service.hostname",
+            &old.hostname,
+            &mut new.hostname,
+            &mut changes,
+        );
+        retain("service.listen", &old.listen, &mut new.listen, &mut changes);
+        retain(
+            "service.socket_mode",
+            &old.socket_mode,
+            &mut new.socket_mode,
+            &mut changes,
+        );
+        retain(
+            "service.shutdown_grace",
+            &old.shutdown_grace,
+            &mut new.shutdown_grace,
+            &mut changes,
+        );
+        retain(
+            "service.drain_timeout",
+            &old.drain_timeout,
+            &mut new.drain_timeout,
+            &mut changes,
+        );
+        retain(
+            "service.grpc_server",
+            &old.grpc_server,
+            &mut new.grpc_server,
+            &mut changes,
+        );
+        retain(
+            "service.grpc_web",
+            &old.grpc_web,
+            &mut new.grpc_web,
+            &mut changes,
+        );
+        retain(
+            "service.transcoding",
+            &old.transcoding,
+            &mut new.transcoding,
+            &mut changes,
+        );
+        retain("service.auth", &old.auth, &mut new.auth, &mut changes);
+        #[cfg(feature = "telemetry")]
+        retain(
+            "service.metrics",
+            &old.metrics,
+            &mut new.metrics,
+            &mut changes,
+        );
+
+        changes
+    }
+}
+
+/// Deserializes `value`, reporting the dotted path of the offending key
+/// (prefixed by `prefix`) and the config file `format` on failure.
+fn deserialize<'de, T>(
+    value: toml::Value,
+    prefix: Option<&str>,
+    format: Format,
+) -> std::result::Result<T, crate::error::Error>
+where
+    T: serde::de::Deserialize<'de>,
+{
+    serde_path_to_error::deserialize(Coerce(value)).map_err(|e| {
+        let path = e.path().to_string();
+        let key = match (prefix, path.as_str()) {
+            (Some(prefix), ".") => prefix.to_string(),
+            (Some(prefix), path) => format!("{}.{}", prefix, path),
+            (None, path) => path.to_string(),
+        };
+
+        crate::error::Error {
+            inner: crate::error::Inner::Key {
+                format,
+                key,
+                error: e.into_inner(),
+            },
+        }
+    })
 }
 
 /// Empty struct to be used as the extra placeholder in Configurable trait.
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct Void {}
 
 pub trait Configurable<'de> {
@@ -180,6 +849,87 @@ pub trait Configurable<'de> {
     type Extra: serde::de::Deserialize<'de>;
     fn set_config_extra(&mut self, _extra: Self::Extra) {}
 
+    /// Checks the user defined parameters once loaded, recording the errors
+    /// in `validation` along with the dotted key of each invalid value.
+    /// Default implementation accepts any value.
+    fn validate_extra(&self, _extra: &Self::Extra, _validation: &mut Validation) {}
+
+    /// Text printed by `--version`.
+    /// Default implementation prints the program name.
+    fn version(&self) -> Option<String> {
+        None
+    }
+
+    /// Extra command line flags, each setting a configuration value.
+    /// Default implementation declares none.
+    fn flags(&self) -> Vec<Flag> {
+        vec![]
+    }
+
+    /// Configuration sources loaded after the config file and before the
+    /// environment variables, in increasing order of priority.
+    /// Default implementation declares none.
+    fn config_sources(&self) -> Vec<std::sync::Arc<dyn ConfigSource>> {
+        vec![]
+    }
+
+    /// JSON Schema of the user defined parameters.
+    /// Default implementation derives it from their shape, see
+    /// `schema::json_schema_of` to carry doc comments and defaults.
+    fn extra_schema(&self) -> Option<serde_json::Value> {
+        None
+    }
+
+    /// Describes the command line: version, extra flags and shape of the
+    /// user defined parameters listed by `--help`.
+    /// Trait definition implements default behavior.
+    /// Should not have to be re-implemented.
+    fn cli(&self) -> Cli {
+        Cli {
+            version: self.version(),
+            flags: self.flags(),
+            extra: schema::describe::<Self::Extra>(),
+            schema: self.extra_schema(),
+            sources: self.config_sources(),
+        }
+    }
+
+    /// JSON Schema of the whole configuration, as printed by `--config-schema`.
+    /// Trait definition implements default behavior.
+    /// Should not have to be re-implemented.
+    fn config_schema(&self) -> serde_json::Value {
+        self.cli().json_schema()
+    }
+
+    /// Validates both `Mr. Big`'s parameters and the user defined ones,
+    /// failing with an `Inner::Validation` error listing every invalid value.
+    /// Trait definition implements default behavior.
+    /// Should not have to be re-implemented.
+    fn validate_config(
+        &self,
+        config: &Config,
+        extra: &Self::Extra,
+    ) -> Result<(), crate::error::Error> {
+        let mut validation = Validation::new();
+        config.validate(&mut validation);
+        self.validate_extra(extra, &mut validation);
+        validation.into_result()
+    }
+
+    /// Writes the effective configuration printed by `--print-config`,
+    /// with the user defined parameters deserialized into `extra`.
+    /// Default implementation prints them as they were set (see
+    /// `Config::dump`), as `extra` may not be serializable, the values of
+    /// its `Secret` fields redacted.
+    fn dump_config(
+        &self,
+        config: &Config,
+        _extra: &Self::Extra,
+        format: Format,
+    ) -> Result<String, crate::error::Error> {
+        config.dump(format)
+    }
+
     /// Allow taking the config itself (or a clone of it).
     /// Default implementation returns a clone.
     fn take_config(&mut self) -> Option<Config> {
@@ -190,8 +940,19 @@ pub trait Configurable<'de> {
     /// Trait definition implements default behavior.
     /// Should not have to be re-implemented.
     fn load_from_args_vec(&mut self, args: Vec<String>) -> Result<(), crate::error::Error> {
-        let mut config = Config::from_args_vec(args)?;
-        self.set_config_extra(config.try_raw_into()?);
+        let mut config = Config::from_args_with(args, std::env::vars(), self.cli())?;
+
+        let extra = config.try_raw_into()?;
+
+        if let Some(format) = config.print_format() {
+            return Err(crate::error::Error::exit(
+                self.dump_config(&config, &extra, format)?,
+            ));
+        }
+
+        self.validate_config(&config, &extra)?;
+
+        self.set_config_extra(extra);
         self.set_config(config);
         Ok(())
     }
@@ -202,6 +963,46 @@ pub trait Configurable<'de> {
     fn load_from_args(&mut self) -> Result<(), crate::error::Error> {
         self.load_from_args_vec(std::env::args().collect())
     }
+
+    /// Called with the new config and extra parameters when the
+    /// configuration is reloaded at runtime (on SIGHUP).
+    /// Default implementation stores both, like when loading.
+    fn on_reload(&mut self, config: Config, extra: Self::Extra) {
+        self.set_config_extra(extra);
+        self.set_config(config);
+    }
+
+    /// Reloads the configuration from the sources `current` was loaded from.
+    /// Parameters which cannot change at runtime are kept and their changes
+    /// are logged, the others are applied and handed to `on_reload`.
+    /// Trait definition implements default behavior.
+    /// Should not have to be re-implemented.
+    fn reload_config(&mut self, current: &Config) -> Result<Config, crate::error::Error> {
+        let config = current.reload()?;
+        self.apply_reload(current, config)
+    }
+
+    /// Applies `config`, read again from the sources `current` was loaded
+    /// from, as `reload_config` does.
+    /// Trait definition implements default behavior.
+    /// Should not have to be re-implemented.
+    fn apply_reload(
+        &mut self,
+        current: &Config,
+        mut config: Config,
+    ) -> Result<Config, crate::error::Error> {
+        for change in current.retain_static(&mut config) {
+            log::warn!("cannot change at runtime, restart to apply: {}", change);
+        }
+
+        let extra = config.try_raw_into()?;
+        self.validate_config(&config, &extra)?;
+
+        crate::reload(&config)?;
+        self.on_reload(config.clone(), extra);
+
+        Ok(config)
+    }
 }
 
 #[cfg(test)]
@@ -262,7 +1063,7 @@ mod tests {
             dur: std::time::Duration,
         }
 
-        let mut cfg = Config::from_bytes(contents.as_bytes().to_vec()).unwrap();
+        let mut cfg = Config::from_bytes(contents.as_bytes(), Format::Toml).unwrap();
 
         #[derive(Deserialize)]
         struct User {
@@ -280,4 +1081,1633 @@ mod tests {
         );
         assert_eq!(user.my_port, 39999);
     }
+
+    #[test]
+    fn env_overlay() {
+        let path = std::env::temp_dir().join("mrbig_test_env_overlay.toml");
+        std::fs::write(
+            &path,
+            r#"
+            greeting = "Hello"
+            [service]
+            port = 8080
+            hostname = "localhost"
+            "#,
+        )
+        .unwrap();
+
+        let vars: Vec<(String, String)> = vec![
+            ("MRBIG_SERVICE__PORT".into(), "9000".into()),
+            ("MRBIG_SERVICE__TRACE".into(), "true".into()),
+            (
+                "MRBIG_SERVICE__GRPC_SERVER__TIMEOUT".into(),
+                "{ secs = 3, nanos = 0 }".into(),
+            ),
+            (
+                "MRBIG_SERVICE__GRPC_SERVER__CONCURRENCY_LIMIT_PER_CONNECTION".into(),
+                "32".into(),
+            ),
+            ("MRBIG_EXTRA__GREETING".into(), "123".into()),
+            ("MRBIG_EXTRA__DB__URL".into(), "postgres://db".into()),
+            ("MRBIG_UNRELATED".into(), "ignored".into()),
+            ("HOME".into(), "/root".into()),
+        ];
+
+        let mut cfg = Config::from_args_and_env(
+            vec![
+                "mrbig".into(),
+                "--config".into(),
+                path.to_str().unwrap().into(),
+                "--hostname".into(),
+                "127.0.0.1".into(),
+            ],
+            vars,
+        )
+        .unwrap();
+
+        #[derive(Deserialize)]
+        struct Db {
+            url: String,
+        }
+
+        #[derive(Deserialize)]
+        struct User {
+            greeting: String,
+            db: Db,
+        }
+
+        let user: User = cfg.try_raw_into().unwrap();
+
+        // environment overrides the file
+        assert_eq!(cfg.service.port, 9000);
+        assert!(cfg.service.trace);
+        // command line overrides the file
+        assert_eq!(cfg.service.hostname, "127.0.0.1");
+        assert_eq!(
+            cfg.service.grpc_server.timeout,
+            Some(std::time::Duration::from_secs(3))
+        );
+        assert_eq!(
+            cfg.service.grpc_server.concurrency_limit_per_connection,
+            Some(32)
+        );
+        // strings are not coerced into numbers when a string is expected
+        assert_eq!(user.greeting, "123");
+        assert_eq!(user.db.url, "postgres://db");
+    }
+
+    #[test]
+    fn env_bad_values() {
+        let args: Vec<String> = vec!["mrbig".into()];
+
+        let vars = vec![("MRBIG_SERVICE__PORT".to_string(), "http".to_string())];
+        assert!(Config::from_args_and_env(args.clone(), vars).is_err());
+
+        let vars = vec![("MRBIG_SERVICE____PORT".to_string(), "80".to_string())];
+        assert!(Config::from_args_and_env(args, vars).is_err());
+    }
+
+    fn profile_fixture(name: &str, base: &str, overlays: &[(&str, &str)]) -> String {
+        let dir = std::env::temp_dir().join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("config.toml");
+        std::fs::write(&path, base).unwrap();
+
+        for (profile, contents) in overlays {
+            std::fs::write(dir.join(format!("config.{}.toml", profile)), contents).unwrap();
+        }
+
+        path.to_str().unwrap().into()
+    }
+
+    #[test]
+    fn profiles() {
+        let path = profile_fixture(
+            "mrbig_test_profiles",
+            r#"
+            greeting = "Hello"
+            [service]
+            port = 8080
+            hostname = "localhost"
+            [service.grpc_server]
+            concurrency_limit_per_connection = 8
+            [profile.dev]
+            greeting = "Hi"
+            [profile.dev.service]
+            debug = true
+            port = 8081
+            [profile.prod.service]
+            port = 80
+            "#,
+            &[
+                ("dev", "[service]\nport = 8082\n"),
+                ("staging", "greeting = \"Hey\"\n"),
+            ],
+        );
+
+        #[derive(Deserialize)]
+        struct User {
+            greeting: String,
+        }
+
+        let load = |profile: Option<&str>, vars: Vec<(String, String)>| {
+            let mut args: Vec<String> = vec!["mrbig".into(), "-c".into(), path.clone()];
+            if let Some(profile) = profile {
+                args.push("--profile".into());
+                args.push(profile.into());
+            }
+            let mut cfg = Config::from_args_and_env(args, vars)?;
+            // the profile section never reaches the user defined parameters
+            assert!(!cfg.raw.contains_key(PROFILE_SECTION));
+            let user: User = cfg.try_raw_into()?;
+            Ok::<_, crate::error::Error>((cfg.service, user.greeting))
+        };
+
+        // no profile, base file only
+        let (srv, greeting) = load(None, vec![]).unwrap();
+        assert_eq!(srv.port, 8080);
+        assert!(!srv.debug);
+        assert_eq!(greeting, "Hello");
+
+        // section and overlay file, overlay file wins
+        let (srv, greeting) = load(Some("dev"), vec![]).unwrap();
+        assert_eq!(srv.port, 8082);
+        assert!(srv.debug);
+        assert_eq!(srv.hostname, "localhost");
+        assert_eq!(srv.grpc_server.concurrency_limit_per_connection, Some(8));
+        assert_eq!(greeting, "Hi");
+
+        // section only
+        let (srv, greeting) = load(Some("prod"), vec![]).unwrap();
+        assert_eq!(srv.port, 80);
+        assert_eq!(greeting, "Hello");
+
+        // overlay file only
+        let (srv, greeting) = load(Some("staging"), vec![]).unwrap();
+        assert_eq!(srv.port, 8080);
+        assert_eq!(greeting, "Hey");
+
+        // profile from the environment, environment overrides the profile
+        let vars = vec![
+            (ENV_PROFILE.to_string(), "prod".to_string()),
+            ("MRBIG_SERVICE__PORT".to_string(), "81".to_string()),
+        ];
+        let (srv, _) = load(None, vars).unwrap();
+        assert_eq!(srv.port, 81);
+
+        // command line profile overrides the environment one
+        let vars = vec![(ENV_PROFILE.to_string(), "prod".to_string())];
+        let (srv, _) = load(Some("dev"), vars).unwrap();
+        assert_eq!(srv.port, 8082);
+
+        // unknown profile
+        assert!(load(Some("qa"), vec![]).is_err());
+
+        // a `profile` which holds no profiles is the user's
+        let path = profile_fixture(
+            "mrbig_test_user_profile",
+            r#"
+            [profile]
+            name = "admin"
+            "#,
+            &[],
+        );
+        #[derive(Deserialize)]
+        struct Account {
+            profile: Profile,
+        }
+        #[derive(Deserialize)]
+        struct Profile {
+            name: String,
+        }
+        let args = vec!["mrbig".to_string(), "-c".into(), path];
+        let mut cfg = Config::from_args_and_env(args, vec![]).unwrap();
+        let account: Account = cfg.try_raw_into().unwrap();
+        assert_eq!(account.profile.name, "admin");
+    }
+
+    #[test]
+    fn profile_without_file() {
+        let args: Vec<String> = vec!["mrbig".into(), "--profile".into(), "dev".into()];
+        assert!(Config::from_args_and_env(args, vec![]).is_err());
+    }
+
+    #[test]
+    fn reload() {
+        let path = std::env::temp_dir().join("mrbig_test_reload.toml");
+        let args: Vec<String> = vec!["mrbig".into(), "-c".into(), path.to_str().unwrap().into()];
+
+        std::fs::write(
+            &path,
+            "greeting = \"Hello\"\n[service]\nport = 8080\nlogger_filters = \"h2=warn\"\n",
+        )
+        .unwrap();
+
+        #[derive(Deserialize)]
+        struct User {
+            greeting: String,
+        }
+
+        #[derive(Default)]
+        struct Micro {
+            config: Option<Config>,
+            greeting: String,
+            reloads: usize,
+        }
+
+        impl Configurable<'_> for Micro {
+            type Extra = User;
+
+            fn get_config(&self) -> Option<&Config> {
+                self.config.as_ref()
+            }
+
+            fn set_config(&mut self, config: Config) {
+                self.config = Some(config);
+            }
+
+            fn set_config_extra(&mut self, extra: Self::Extra) {
+                self.greeting = extra.greeting;
+            }
+
+            fn on_reload(&mut self, config: Config, extra: Self::Extra) {
+                self.reloads += 1;
+                self.set_config_extra(extra);
+                self.set_config(config);
+            }
+        }
+
+        let mut micro = Micro::default();
+        micro.load_from_args_vec(args).unwrap();
+        let current = micro.take_config().unwrap();
+
+        std::fs::write(
+            &path,
+            "greeting = \"Hi\"\n[service]\nport = 9090\nlogger_filters = \"h2=info\"\n",
+        )
+        .unwrap();
+
+        let config = micro.reload_config(&current).unwrap();
+
+        assert_eq!(micro.reloads, 1);
+        assert_eq!(micro.greeting, "Hi");
+        // port cannot change at runtime
+        assert_eq!(config.service.port, 8080);
+        assert_eq!(config.service.logger_filters, "h2=info");
+        assert_eq!(micro.get_config().unwrap().service.port, 8080);
+
+        // a broken file keeps the current configuration
+        std::fs::write(&path, "[service\n").unwrap();
+        assert!(micro.reload_config(&config).is_err());
+        assert_eq!(micro.reloads, 1);
+        assert_eq!(micro.greeting, "Hi");
+
+        // defaults cannot be reloaded
+        assert!(Config::default().reload().is_err());
+    }
+
+    #[test]
+    fn retain_static() {
+        let old = Config::default();
+        let mut new = Config::default();
+
+        new.service.port = 4000;
+        new.service.hostname = "localhost".into();
+        new.service.debug = true;
+        new.service.grpc_server.concurrency_limit_per_connection = Some(1);
+
+        let changes = old.retain_static(&mut new);
+
+        assert_eq!(changes.len(), 3);
+        assert!(changes[0].starts_with("service.port"));
+        assert_eq!(new.service.port, old.service.port);
+        assert_eq!(new.service.hostname, old.service.hostname);
+        assert_eq!(new.service.grpc_server, old.service.grpc_server);
+        assert!(new.service.debug);
+    }
+
+    #[test]
+    fn formats() {
+        let toml = r#"
+        greeting = "Hi"
+        ports = [1, 2]
+        [service]
+        port = 8080
+        debug = true
+        [service.grpc_server.timeout]
+        secs = 2
+        nanos = 0
+        "#;
+        let yaml = r#"
+        greeting: Hi
+        ports: [1, 2]
+        nothing: ~
+        service:
+          port: 8080
+          debug: true
+          grpc_server:
+            timeout:
+              secs: 2
+              nanos: 0
+        "#;
+        let json = r#"{
+            "greeting": "Hi",
+            "ports": [1, 2],
+            "nothing": null,
+            "service": {
+                "port": 8080,
+                "debug": true,
+                "grpc_server": { "timeout": { "secs": 2, "nanos": 0 } }
+            }
+        }"#;
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct User {
+            greeting: String,
+            ports: Vec<u16>,
+            nothing: Option<String>,
+        }
+
+        for (contents, format) in &[
+            (toml, Format::Toml),
+            (yaml, Format::Yaml),
+            (json, Format::Json),
+        ] {
+            let mut cfg = Config::from_bytes(contents.as_bytes(), *format).unwrap();
+            let user: User = cfg.try_raw_into().unwrap();
+
+            assert_eq!(cfg.format(), *format);
+            assert_eq!(cfg.service.port, 8080);
+            assert!(cfg.service.debug);
+            assert_eq!(
+                cfg.service.grpc_server.timeout,
+                Some(std::time::Duration::from_secs(2))
+            );
+            assert_eq!(
+                user,
+                User {
+                    greeting: "Hi".into(),
+                    ports: vec![1, 2],
+                    nothing: None,
+                }
+            );
+        }
+
+        // empty documents use the defaults
+        let cfg = Config::from_bytes(b"", Format::Yaml).unwrap();
+        assert_eq!(cfg.service.port, default_port());
+    }
+
+    #[test]
+    fn format_selection() {
+        let dir = std::env::temp_dir();
+        let yaml = dir.join("mrbig_test_format.yaml");
+        let conf = dir.join("mrbig_test_format.conf");
+        std::fs::write(&yaml, "service:\n  port: 8081\n").unwrap();
+        std::fs::write(&conf, "{\"service\": {\"port\": 8082}}").unwrap();
+
+        let load = |args: &[&str]| {
+            let mut all = vec!["mrbig".to_string()];
+            all.extend(args.iter().map(|a| a.to_string()));
+            Config::from_args_and_env(all, vec![])
+        };
+
+        let cfg = load(&["-c", yaml.to_str().unwrap()]).unwrap();
+        assert_eq!(cfg.format(), Format::Yaml);
+        assert_eq!(cfg.service.port, 8081);
+
+        // unknown extensions are read as TOML
+        assert!(load(&["-c", conf.to_str().unwrap()]).is_err());
+
+        let cfg = load(&["-c", conf.to_str().unwrap(), "--config-format", "json"]).unwrap();
+        assert_eq!(cfg.format(), Format::Json);
+        assert_eq!(cfg.service.port, 8082);
+
+        assert!(load(&["-c", conf.to_str().unwrap(), "--config-format", "ini"]).is_err());
+    }
+
+    #[test]
+    fn format_errors() {
+        let err = Config::from_bytes(
+            b"service:\n  grpc_server:\n    timeout: soon\n",
+            Format::Yaml,
+        )
+        .unwrap_err();
+        match &err.inner {
+            crate::error::Inner::Key { format, key, .. } => {
+                assert_eq!(*format, Format::Yaml);
+                assert_eq!(key, "service.grpc_server.timeout");
+            }
+            e => panic!("unexpected error {:?}", e),
+        }
+        assert!(err
+            .to_string()
+            .starts_with("YAML config error at `service.grpc_server.timeout`"));
+
+        #[derive(Debug, Deserialize)]
+        struct User {
+            #[allow(dead_code)]
+            db: Db,
+        }
+        #[derive(Debug, Deserialize)]
+        struct Db {
+            #[allow(dead_code)]
+            pool: u32,
+        }
+
+        let mut cfg = Config::from_bytes(br#"{"db": {"pool": "many"}}"#, Format::Json).unwrap();
+        match cfg.try_raw_into::<User>().unwrap_err().inner {
+            crate::error::Inner::Key { format, key, .. } => {
+                assert_eq!(format, Format::Json);
+                assert_eq!(key, "db.pool");
+            }
+            e => panic!("unexpected error {:?}", e),
+        }
+
+        match Config::from_bytes(b"{\"a\": [1, null]}", Format::Json)
+            .unwrap_err()
+            .inner
+        {
+            crate::error::Inner::Key { key, .. } => assert_eq!(key, "a.1"),
+            e => panic!("unexpected error {:?}", e),
+        }
+
+        match Config::from_bytes(b"{ not json", Format::Json)
+            .unwrap_err()
+            .inner
+        {
+            crate::error::Inner::Parse { format, .. } => assert_eq!(format, Format::Json),
+            e => panic!("unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn provenance() {
+        let path = profile_fixture(
+            "mrbig_test_provenance",
+            r#"
+            greeting = "Hi"
+            [db]
+            url = "postgres://db"
+            password = "hunter2"
+            [service]
+            hostname = "localhost"
+            secrets = ["db.password"]
+            "#,
+            &[("dev", "[service]\nlogger_filters = \"h2=info\"\n")],
+        );
+        let overlay = path.replace(".toml", ".dev.toml");
+
+        let vars = vec![
+            ("MRBIG_SERVICE__TRACE".to_string(), "true".to_string()),
+            (
+                "MRBIG_EXTRA__DB__URL".to_string(),
+                "postgres://env".to_string(),
+            ),
+        ];
+        let args: Vec<String> = vec![
+            "mrbig".into(),
+            "-c".into(),
+            path.clone(),
+            "--profile".into(),
+            "dev".into(),
+            "--port".into(),
+            "4000".into(),
+            "--print-config=json".into(),
+        ];
+        let cfg = Config::from_args_and_env(args, vars).unwrap();
+
+        let file = Source::File(path.clone().into());
+        assert_eq!(cfg.print_format(), Some(Format::Json));
+        assert_eq!(cfg.source_of("service.port"), Source::Cli("--port".into()));
+        assert_eq!(cfg.source_of("service.hostname"), file);
+        assert_eq!(
+            cfg.source_of("service.logger_filters"),
+            Source::File(overlay.into())
+        );
+        assert_eq!(
+            cfg.source_of("service.trace"),
+            Source::Env("MRBIG_SERVICE__TRACE".into())
+        );
+        assert_eq!(
+            cfg.source_of("db.url"),
+            Source::Env("MRBIG_EXTRA__DB__URL".into())
+        );
+        assert_eq!(cfg.source_of("db.password"), file);
+        assert_eq!(cfg.source_of("service.debug"), Source::Default);
+
+        let dump = cfg.dump(Format::Toml).unwrap();
+        assert!(dump.contains("greeting = \"Hi\" # file "));
+        assert!(dump.contains("url = \"postgres://env\" # env MRBIG_EXTRA__DB__URL"));
+        assert!(dump.contains("password = \"<redacted>\" # file "));
+        assert!(!dump.contains("hunter2"));
+        assert!(dump.contains("\n[service]\n"));
+        assert!(dump.contains("port = 4000 # cli --port"));
+        assert!(dump.contains("debug = false # default"));
+
+        let dump: serde_json::Value =
+            serde_json::from_str(&cfg.dump(Format::Json).unwrap()).unwrap();
+        assert_eq!(dump["service"]["port"]["value"], 4000);
+        assert_eq!(dump["service"]["port"]["source"], "cli --port");
+        assert_eq!(dump["db"]["password"]["value"], REDACTED);
+
+        #[derive(serde_derive::Serialize)]
+        struct User {
+            greeting: String,
+            retries: u32,
+        }
+
+        let user = User {
+            greeting: "Hi".into(),
+            retries: 3,
+        };
+        let dump = cfg.dump_with(&user, Format::Toml).unwrap();
+        assert!(dump.contains("retries = 3 # default"));
+        assert!(!dump.contains("[db]"));
+    }
+
+    #[test]
+    fn humane_units() {
+        use std::time::Duration;
+
+        let contents = r#"
+        poll = "1m30s"
+        limit = "4MiB"
+        [service.grpc_server]
+        timeout = "500ms"
+        tcp_keepalive = 2
+        "#;
+
+        #[derive(Deserialize)]
+        struct User {
+            #[serde(with = "units::duration")]
+            poll: Duration,
+            #[serde(with = "units::size")]
+            limit: u64,
+            #[serde(default, with = "units::option_size")]
+            missing: Option<u64>,
+        }
+
+        let mut cfg = Config::from_bytes(contents.as_bytes(), Format::Toml).unwrap();
+        let user: User = cfg.try_raw_into().unwrap();
+
+        assert_eq!(
+            cfg.service.grpc_server.timeout,
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(
+            cfg.service.grpc_server.tcp_keepalive,
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(user.poll, Duration::from_secs(90));
+        assert_eq!(user.limit, 4 * 1024 * 1024);
+        assert_eq!(user.missing, None);
+
+        // environment variables accept both forms
+        for value in &["1h", "{ secs = 3600, nanos = 0 }", "3600"] {
+            let vars = vec![(
+                "MRBIG_SERVICE__GRPC_SERVER__TIMEOUT".to_string(),
+                value.to_string(),
+            )];
+            let cfg = Config::from_args_and_env(vec!["mrbig".into()], vars).unwrap();
+            assert_eq!(
+                cfg.service.grpc_server.timeout,
+                Some(Duration::from_secs(3600))
+            );
+        }
+
+        let err = Config::from_bytes(
+            b"service:\n  grpc_server:\n    timeout: 2 parsecs\n",
+            Format::Yaml,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("invalid duration `2 parsecs`"));
+
+        // printed back in the humane form
+        let dump = cfg.dump(Format::Toml).unwrap();
+        assert!(dump.contains("timeout = \"500ms\""));
+        assert!(dump.contains("tcp_keepalive = \"2s\""));
+    }
+
+    #[test]
+    fn validation() {
+        let mut validation = Validation::new();
+        Config::default().validate(&mut validation);
+        assert!(validation.errors().is_empty());
+
+        let mut cfg = Config::default();
+        cfg.service.port = 0;
+        cfg.service.hostname = "not a host".into();
+        cfg.service.grpc_server.concurrency_limit_per_connection = Some(0);
+        #[cfg(feature = "telemetry")]
+        {
+            cfg.service.metrics.hostname = "-nowhere".into();
+        }
+
+        let mut validation = Validation::new();
+        cfg.validate(&mut validation);
+        validation.check(false, "db.url", "must be set");
+
+        let keys: Vec<&str> = validation.errors().iter().map(|e| e.key.as_str()).collect();
+        // port 0 is left to the system
+        let mut expected = vec![
+            "service.hostname",
+            "service.grpc_server.concurrency_limit_per_connection",
+        ];
+        #[cfg(feature = "telemetry")]
+        expected.push("service.metrics.hostname");
+        expected.push("db.url");
+        assert_eq!(keys, expected);
+
+        let err = validation.into_result().unwrap_err();
+        let message = err.to_string();
+        assert!(message.starts_with("invalid configuration: `service.hostname` `not a host`"));
+        assert!(message.ends_with("`db.url` must be set"));
+        match err.inner {
+            crate::error::Inner::Validation(errors) => assert_eq!(errors.len(), expected.len()),
+            e => panic!("unexpected error {:?}", e),
+        }
+
+        // DNS names are valid, and resolved when binding
+        for hostname in &["localhost", "svc.internal", "svc.internal.", "::1"] {
+            let mut validation = Validation::new();
+            validation.address("service.hostname", hostname);
+            assert!(validation.errors().is_empty(), "{}", hostname);
+        }
+        for hostname in &["", "-bad-", "a..b", "under_score", "svc.internal:8080"] {
+            let mut validation = Validation::new();
+            validation.address("service.hostname", hostname);
+            assert_eq!(validation.errors().len(), 1, "{}", hostname);
+        }
+    }
+
+    #[cfg(feature = "telemetry")]
+    #[test]
+    fn validation_metrics_clash() {
+        let clash = |hostname: &str, metrics_hostname: &str| {
+            let mut cfg = Config::default();
+            cfg.service.hostname = hostname.into();
+            cfg.service.metrics.hostname = metrics_hostname.into();
+            cfg.service.metrics.port = cfg.service.port;
+
+            let mut validation = Validation::new();
+            cfg.validate(&mut validation);
+            validation
+                .errors()
+                .iter()
+                .any(|e| e.message.starts_with("clashes"))
+        };
+
+        assert!(clash("0.0.0.0", "127.0.0.1"));
+        assert!(clash("127.0.0.1", "127.0.0.1"));
+        assert!(!clash("127.0.0.1", "127.0.0.2"));
+    }
+
+    #[test]
+    fn validation_tls() {
+        let mut cfg = Config::default();
+        let tls: Tls = toml::from_str(
+            r#"
+            cert = "Cargo.toml"
+            key = "no/such/key.pem"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(tls.reload_interval, std::time::Duration::from_secs(30));
+        cfg.service.grpc_server.tls = Some(tls);
+
+        let mut validation = Validation::new();
+        cfg.validate(&mut validation);
+        let mut keys: Vec<&str> = validation.errors().iter().map(|e| e.key.as_str()).collect();
+        if cfg!(not(feature = "tls")) {
+            assert_eq!(keys.remove(0), "service.grpc_server.tls");
+        }
+        assert_eq!(keys, vec!["service.grpc_server.tls.key"]);
+    }
+
+    #[test]
+    fn validation_auth() {
+        let mut cfg = Config::default();
+        let auth: Auth = toml::from_str(
+            r#"
+            secret = "s3cret"
+            jwks = "no/such/jwks.json"
+            leeway = "30s"
+            [policies."hotel.Hotel/Rates"]
+            roles = ["admin"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(auth.leeway, std::time::Duration::from_secs(30));
+        cfg.service.auth = Some(auth);
+
+        let mut validation = Validation::new();
+        cfg.validate(&mut validation);
+        let mut keys: Vec<&str> = validation.errors().iter().map(|e| e.key.as_str()).collect();
+        if cfg!(not(feature = "auth")) {
+            assert_eq!(keys.remove(0), "service.auth");
+        }
+        assert_eq!(
+            keys,
+            vec!["service.auth", "service.auth.jwks", "service.auth.policies"]
+        );
+    }
+
+    #[test]
+    fn validation_grpc_web() {
+        let mut cfg = Config::default();
+        let grpc_web: GrpcWeb = toml::from_str(
+            r#"
+            [cors]
+            allowed_origins = ["*", "app.example.com"]
+            allow_credentials = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(grpc_web.cors.max_age, std::time::Duration::from_secs(600));
+        cfg.service.grpc_web = Some(grpc_web);
+
+        let mut validation = Validation::new();
+        cfg.validate(&mut validation);
+        let mut keys: Vec<&str> = validation.errors().iter().map(|e| e.key.as_str()).collect();
+        if cfg!(not(feature = "web")) {
+            assert_eq!(keys.remove(0), "service.grpc_web");
+        }
+        assert_eq!(
+            keys,
+            vec![
+                "service.grpc_web.cors.allowed_origins",
+                "service.grpc_web.cors.allow_credentials"
+            ]
+        );
+    }
+
+    #[test]
+    fn listen() {
+        let mut service: Service = toml::from_str(
+            r#"
+            listen = ["tcp://0.0.0.0:0", "unix:///run/micro.sock"]
+            socket_mode = "0600"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            service.addresses().unwrap(),
+            vec![
+                Listen::Tcp("0.0.0.0:0".parse().unwrap()),
+                Listen::Unix("/run/micro.sock".into())
+            ]
+        );
+        assert_eq!(service.socket_mode, 0o600);
+        assert!(toml::from_str::<Service>("socket_mode = \"rw\"").is_err());
+
+        let dumped = toml::Value::try_from(&service).unwrap();
+        assert_eq!(dumped["socket_mode"].as_str(), Some("0600"));
+
+        service
+            .listen
+            .extend(vec!["localhost:80".into(), "unix://".into()]);
+        let mut validation = Validation::new();
+        validate::service(&service, &mut validation);
+        let messages: Vec<String> = validation.errors().iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "`service.listen` `localhost:80` is neither a `tcp://` nor a `unix://` address",
+                "`service.listen` `unix://` has no socket path",
+            ]
+        );
+
+        let service = default_service();
+        assert_eq!(service.socket_mode, 0o660);
+        assert_eq!(
+            service.addresses().unwrap(),
+            vec![Listen::Tcp("0.0.0.0:3000".parse().unwrap())]
+        );
+    }
+
+    #[test]
+    fn help_and_version() {
+        #[derive(Deserialize)]
+        #[allow(dead_code)]
+        struct User {
+            greeting: String,
+            db: Db,
+        }
+        #[derive(Deserialize)]
+        #[allow(dead_code)]
+        struct Db {
+            url: String,
+            pool: Option<u32>,
+        }
+
+        let cli = Cli {
+            version: Some("micro 1.2.3".into()),
+            flags: vec![Flag::new("db-url", "db.url", "set the database URL")],
+            extra: schema::describe::<User>(),
+            ..Cli::default()
+        };
+        let exit = |args: &[&str], cli: Cli| {
+            let args = args.iter().map(|a| a.to_string()).collect();
+            match Config::from_args_with(args, vec![], cli).unwrap_err().inner {
+                crate::error::Inner::Exit(output) => output,
+                e => panic!("unexpected error {:?}", e),
+            }
+        };
+
+        let usage = exit(&["micro", "--help"], cli.clone());
+        assert!(usage.starts_with("Usage: micro [options]"));
+        assert!(usage.contains("--print-config"));
+        assert!(usage.contains("--db-url STRING"));
+        assert!(usage.contains("    greeting  string\n"));
+        assert!(usage.contains("    db.pool   integer\n"));
+
+        assert_eq!(exit(&["micro", "-V"], cli.clone()), "micro 1.2.3");
+        assert_eq!(
+            exit(&["/usr/bin/micro", "--version"], Cli::default()),
+            format!("micro (mrbig {})", env!("CARGO_PKG_VERSION"))
+        );
+
+        let err =
+            Config::from_args_with(vec!["micro".into(), "-h".into()], vec![], cli).unwrap_err();
+        assert!(err.is_exit());
+    }
+
+    #[test]
+    fn extra_flags() {
+        let path = std::env::temp_dir().join("mrbig_test_extra_flags.toml");
+        std::fs::write(&path, "[db]\nurl = \"postgres://file\"\n").unwrap();
+
+        let cli = Cli {
+            flags: vec![
+                Flag::new("db-url", "db.url", "set the database URL"),
+                Flag::switch("dry-run", "dry_run", "do not write anything").short('n'),
+                Flag::new("timeout", "service.grpc_server.timeout", "set the timeout"),
+            ],
+            ..Cli::default()
+        };
+        let vars = vec![(
+            "MRBIG_EXTRA__DB__URL".to_string(),
+            "postgres://env".to_string(),
+        )];
+        let args: Vec<String> = vec![
+            "micro".into(),
+            "-c".into(),
+            path.to_str().unwrap().into(),
+            "--db-url".into(),
+            "postgres://cli".into(),
+            "-n".into(),
+            "--timeout".into(),
+            "3s".into(),
+        ];
+
+        #[derive(Deserialize)]
+        struct User {
+            db: Db,
+            #[serde(default)]
+            dry_run: bool,
+        }
+        #[derive(Deserialize)]
+        struct Db {
+            url: String,
+        }
+
+        let mut cfg = Config::from_args_with(args, vars, cli.clone()).unwrap();
+        let user: User = cfg.try_raw_into().unwrap();
+
+        assert_eq!(user.db.url, "postgres://cli");
+        assert!(user.dry_run);
+        assert_eq!(
+            cfg.service.grpc_server.timeout,
+            Some(std::time::Duration::from_secs(3))
+        );
+        assert_eq!(cfg.source_of("db.url"), Source::Cli("--db-url".into()));
+        assert_eq!(cfg.source_of("dry_run"), Source::Cli("--dry-run".into()));
+
+        // reloading accepts the same flags
+        let mut cfg = cfg.reload().unwrap();
+        let user: User = cfg.try_raw_into().unwrap();
+        assert_eq!(user.db.url, "postgres://cli");
+
+        // unknown without the declaration
+        let args = vec!["micro".into(), "--db-url".into(), "x".into()];
+        assert!(Config::from_args_and_env(args, vec![]).is_err());
+    }
+
+    #[test]
+    fn describe() {
+        use schema::{Field, Kind};
+
+        #[derive(Deserialize)]
+        #[allow(dead_code)]
+        enum Mode {
+            Fast,
+            Safe,
+        }
+
+        #[derive(Deserialize)]
+        #[allow(dead_code)]
+        struct User {
+            name: String,
+            ratio: f32,
+            ports: Vec<u16>,
+            labels: std::collections::HashMap<String, String>,
+            mode: Mode,
+            limits: Option<Limits>,
+        }
+
+        #[derive(Deserialize)]
+        #[allow(dead_code)]
+        struct Limits {
+            #[serde(with = "units::duration")]
+            timeout: std::time::Duration,
+            strict: bool,
+        }
+
+        let kind = schema::describe::<User>();
+        let field = |name, kind| Field { name, kind };
+
+        assert_eq!(
+            kind,
+            Kind::Struct(vec![
+                field("name", Kind::String),
+                field("ratio", Kind::Float),
+                field("ports", Kind::Seq(Box::new(Kind::Integer))),
+                field("labels", Kind::Map(Box::new(Kind::String))),
+                field("mode", Kind::Enum(vec!["Fast", "Safe"])),
+                field(
+                    "limits",
+                    Kind::Option(Box::new(Kind::Struct(vec![
+                        field("timeout", Kind::Any),
+                        field("strict", Kind::Bool),
+                    ])))
+                ),
+            ])
+        );
+        assert_eq!(kind.get("limits.strict"), Some(&Kind::Bool));
+        assert_eq!(kind.get("labels.anything"), Some(&Kind::String));
+        assert_eq!(kind.get("nothing"), None);
+
+        let keys: Vec<String> = kind.leaves().into_iter().map(|(k, _)| k).collect();
+        assert_eq!(
+            keys,
+            vec![
+                "name",
+                "ratio",
+                "ports",
+                "labels",
+                "mode",
+                "limits.timeout",
+                "limits.strict"
+            ]
+        );
+
+        assert_eq!(schema::describe::<Void>(), Kind::Struct(vec![]));
+
+        // recursive types are described up to some depth
+        #[derive(Deserialize)]
+        #[allow(dead_code)]
+        struct Node {
+            name: String,
+            child: Option<Box<Node>>,
+            children: Vec<Node>,
+        }
+        let node = schema::describe::<Node>();
+        assert_eq!(node.get("name"), Some(&Kind::String));
+        assert_eq!(node.get("child.child.name"), Some(&Kind::String));
+        assert_eq!(
+            node.get(&format!("{}name", "child.".repeat(20))),
+            Some(&Kind::Any)
+        );
+        let deepest = "child.".repeat(5) + "children";
+        assert!(matches!(node.get(&deepest), Some(Kind::Seq(_))));
+        assert!(matches!(node.get("children"), Some(Kind::Seq(_))));
+        assert_eq!(
+            schema::describe::<Service>().get("grpc_server.reflection"),
+            Some(&Kind::Bool)
+        );
+    }
+
+    #[test]
+    fn param_flags() {
+        #[derive(Deserialize)]
+        #[allow(dead_code)]
+        struct User {
+            port: u16,
+            poll_interval: String,
+            verbose: Option<bool>,
+            db: Db,
+            empty: Void,
+        }
+        #[derive(Deserialize)]
+        #[allow(dead_code)]
+        struct Db {
+            url: String,
+            user: String,
+        }
+
+        let declared = vec![Flag::new("user", "db.user", "set the database user")];
+        let flags = Flag::params(&schema::describe::<User>(), &declared);
+
+        // `port` is taken, `db.user` declared and `empty` holds nothing
+        assert_eq!(
+            flags,
+            vec![
+                Flag::new(
+                    "poll-interval",
+                    "poll_interval",
+                    "set the `poll_interval` parameter"
+                ),
+                Flag::switch("verbose", "verbose", "set the `verbose` parameter"),
+                Flag::new("db-url", "db.url", "set the `db.url` parameter"),
+            ]
+        );
+        assert!(Flag::params(&schema::describe::<Void>(), &[]).is_empty());
+    }
+
+    #[test]
+    fn set_overrides() {
+        #[derive(Deserialize)]
+        struct User {
+            greeting: String,
+            retries: u32,
+            db: Db,
+        }
+        #[derive(Deserialize)]
+        struct Db {
+            url: String,
+            pool: Option<u32>,
+        }
+
+        let args = |sets: &[&str]| {
+            let mut args = vec!["micro".to_string()];
+            for set in sets {
+                args.push("--set".into());
+                args.push(set.to_string());
+            }
+            args
+        };
+        let cli = Cli {
+            extra: schema::describe::<User>(),
+            ..Cli::default()
+        };
+        let vars = vec![("MRBIG_EXTRA__DB__POOL".to_string(), "4".to_string())];
+
+        let mut cfg = Config::from_args_with(
+            args(&[
+                "service.grpc_server.concurrency_limit_per_connection=32",
+                "service.port=9000",
+                "greeting=123",
+                "retries=3",
+                "db={ url = \"postgres://db\" }",
+            ]),
+            vars,
+            cli.clone(),
+        )
+        .unwrap();
+        let user: User = cfg.try_raw_into().unwrap();
+
+        assert_eq!(
+            cfg.service.grpc_server.concurrency_limit_per_connection,
+            Some(32)
+        );
+        assert_eq!(cfg.service.port, 9000);
+        assert_eq!(user.greeting, "123");
+        assert_eq!(user.retries, 3);
+        // tables are merged
+        assert_eq!(user.db.url, "postgres://db");
+        assert_eq!(user.db.pool, Some(4));
+        assert_eq!(cfg.source_of("db.url"), Source::Cli("--set".into()));
+        assert_eq!(
+            cfg.source_of("db.pool"),
+            Source::Env("MRBIG_EXTRA__DB__POOL".into())
+        );
+
+        // overrides win over the typed flags, whatever their order
+        let mut with_flags = args(&["service.port=9000", "service.debug=false"]);
+        with_flags.extend(vec!["--port".into(), "9001".into(), "--debug".into()]);
+        let cfg = Config::from_args_with(with_flags, vec![], cli.clone()).unwrap();
+        assert_eq!(cfg.service.port, 9000);
+        assert!(!cfg.service.debug);
+        assert_eq!(cfg.source_of("service.port"), Source::Cli("--set".into()));
+        let cfg = Config::from_args_with(
+            vec!["micro".into(), "--port".into(), "9001".into()],
+            vec![],
+            cli.clone(),
+        )
+        .unwrap();
+        assert_eq!(cfg.service.port, 9001);
+        assert_eq!(cfg.source_of("service.port"), Source::Cli("--port".into()));
+
+        let error = |sets: &[&str]| match Config::from_args_with(args(sets), vec![], cli.clone())
+            .err()
+            .unwrap()
+            .inner
+        {
+            crate::error::Inner::Other(message) => message,
+            e => panic!("unexpected error {:?}", e),
+        };
+        assert_eq!(
+            error(&["retries=many"]),
+            "`--set retries=many`: invalid value `many`, expected integer for `retries`"
+        );
+        assert_eq!(
+            error(&["service.prot=1"]),
+            "`--set service.prot=1`: unknown configuration key `service.prot`"
+        );
+        assert_eq!(
+            error(&["db.name=x"]),
+            "`--set db.name=x`: unknown configuration key `db.name`"
+        );
+        assert_eq!(
+            error(&["retries"]),
+            "`--set retries` is not of the form KEY=VALUE"
+        );
+        assert!(error(&["db=postgres"]).contains("expected table"));
+
+        // without a schema for the user defined parameters, any key goes
+        #[derive(Deserialize)]
+        struct Section {
+            flag: bool,
+        }
+        let mut cfg = Config::from_args_and_env(args(&["my_section.flag=true"]), vec![]).unwrap();
+        let raw: std::collections::HashMap<String, Section> = cfg.try_raw_into().unwrap();
+        assert!(raw["my_section"].flag);
+
+        // and so without parameters of its own, as with `Void`, the service
+        // reading its sections from the raw table
+        let void = Cli {
+            extra: schema::describe::<Void>(),
+            ..Cli::default()
+        };
+        let sets = args(&["my_section.flag=true", "service.port=9000"]);
+        let mut cfg = Config::from_args_with(sets, vec![], void.clone()).unwrap();
+        assert_eq!(cfg.service.port, 9000);
+        let raw: std::collections::HashMap<String, Section> = cfg.try_raw_into().unwrap();
+        assert!(raw["my_section"].flag);
+        // `Mr. Big`'s own keys are still checked
+        assert!(Config::from_args_with(args(&["service.nope=1"]), vec![], void).is_err());
+    }
+
+    #[test]
+    fn json_schema() {
+        #[derive(Deserialize)]
+        #[allow(dead_code)]
+        struct User {
+            greeting: String,
+            retries: Option<u32>,
+        }
+
+        let cli = Cli {
+            extra: schema::describe::<User>(),
+            ..Cli::default()
+        };
+        let schema = cli.json_schema();
+
+        assert_eq!(schema["$schema"], "http://json-schema.org/draft-07/schema#");
+        assert_eq!(schema["properties"]["greeting"]["type"], "string");
+        assert_eq!(schema["properties"]["retries"]["type"], "integer");
+
+        let service = &schema["properties"]["service"];
+        assert_eq!(service["properties"]["port"]["default"], 3000);
+        assert_eq!(
+            service["properties"]["port"]["description"],
+            "Port number to bind when serving."
+        );
+        let grpc = &service["properties"]["grpc_server"]["properties"];
+        assert_eq!(grpc["reflection"]["default"], true);
+        assert_eq!(
+            grpc["timeout"]["type"],
+            serde_json::json!(["string", "integer", "object", "null"])
+        );
+        #[cfg(feature = "telemetry")]
+        assert_eq!(service["properties"]["metrics"]["default"]["port"], 9090);
+
+        // the user defined parameters may carry their own doc comments
+        #[derive(Deserialize, JsonSchema)]
+        #[allow(dead_code)]
+        struct Documented {
+            /// Greeting sent back.
+            #[serde(default = "hello")]
+            greeting: String,
+        }
+        fn hello() -> String {
+            "Hello".into()
+        }
+
+        let cli = Cli {
+            schema: Some(schema::json_schema_of::<Documented>()),
+            ..Cli::default()
+        };
+        let schema = cli.json_schema();
+        let greeting = &schema["properties"]["greeting"];
+        assert_eq!(greeting["description"], "Greeting sent back.");
+        assert_eq!(greeting["default"], "Hello");
+        assert!(schema["properties"]["service"].is_object());
+
+        // printed by --config-schema
+        let args = vec!["micro".into(), "--config-schema".into()];
+        let output = match Config::from_args_with(args, vec![], cli.clone())
+            .unwrap_err()
+            .inner
+        {
+            crate::error::Inner::Exit(output) => output,
+            e => panic!("unexpected error {:?}", e),
+        };
+        let printed: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(printed, cli.json_schema());
+    }
+
+    /// Serves `response` to a single HTTP request, returning the server URL.
+    fn serve_once(response: String) -> String {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut buffer = [0; 512];
+            while !request.ends_with(b"\r\n\r\n") {
+                let read = stream.read(&mut buffer).unwrap();
+                request.extend_from_slice(&buffer[..read]);
+            }
+            assert!(request.starts_with(b"GET /config"));
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+
+        url
+    }
+
+    #[test]
+    fn config_sources() {
+        use std::sync::Arc;
+
+        let base = std::env::temp_dir().join("mrbig_test_config_sources");
+        let _ = std::fs::remove_dir_all(&base);
+        let (values, secrets) = (base.join("values"), base.join("secrets"));
+        std::fs::create_dir_all(values.join("..data")).unwrap();
+        std::fs::create_dir_all(&secrets).unwrap();
+
+        let file = base.join("config.toml");
+        std::fs::write(
+            &file,
+            "greeting = \"file\"\nretries = 1\n[db]\nurl = \"postgres://file\"\n",
+        )
+        .unwrap();
+        std::fs::write(values.join("greeting"), "dir\n").unwrap();
+        std::fs::write(values.join("service.port"), "4000\n").unwrap();
+        std::fs::write(values.join(".hidden"), "ignored").unwrap();
+        std::fs::write(secrets.join("password"), "s3cret").unwrap();
+
+        let body = r#"{ "greeting": "http", "retries": 2 }"#;
+        let url = serve_once(format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )) + "/config";
+
+        let cli = Cli {
+            sources: vec![
+                Arc::new(FileSource::new(base.join("missing.toml")).optional()),
+                Arc::new(DirectorySource::new(&values)),
+                Arc::new(DirectorySource::new(&secrets).prefix("db")),
+                Arc::new(HttpSource::new(&url)),
+            ],
+            ..Cli::default()
+        };
+        let args = vec!["micro".into(), "-c".into(), file.to_str().unwrap().into()];
+        let vars = vec![("MRBIG_EXTRA__RETRIES".to_string(), "3".to_string())];
+
+        #[derive(Deserialize)]
+        struct User {
+            greeting: String,
+            retries: u32,
+            db: Db,
+        }
+        #[derive(Deserialize)]
+        struct Db {
+            url: String,
+            password: String,
+        }
+
+        let mut cfg = Config::from_args_with(args, vars, cli).unwrap();
+        let user: User = cfg.try_raw_into().unwrap();
+
+        // sources override the file, in order, and the environment overrides them
+        assert_eq!(user.greeting, "http");
+        assert_eq!(user.retries, 3);
+        assert_eq!(user.db.url, "postgres://file");
+        assert_eq!(user.db.password, "s3cret");
+        assert_eq!(cfg.service.port, 4000);
+
+        assert_eq!(cfg.source_of("greeting"), Source::Http(url));
+        assert_eq!(cfg.source_of("db.url"), Source::File(file));
+        assert_eq!(
+            cfg.source_of("db.password"),
+            Source::File(secrets.join("password"))
+        );
+        assert_eq!(
+            cfg.source_of("service.port"),
+            Source::File(values.join("service.port"))
+        );
+        assert_eq!(
+            cfg.source_of("retries"),
+            Source::Env("MRBIG_EXTRA__RETRIES".into())
+        );
+
+        // the format may come from the URL, failures are reported
+        let url = serve_once("HTTP/1.0 200 OK\r\n\r\ngreeting: yaml\n".into()) + "/config.yaml";
+        let load = |source: Arc<dyn ConfigSource>| {
+            let cli = Cli {
+                sources: vec![source],
+                ..Cli::default()
+            };
+            Config::from_args_with(vec!["micro".into()], vec![], cli)
+        };
+        let mut cfg = load(Arc::new(HttpSource::new(&url))).unwrap();
+        let raw: toml::value::Table = cfg.try_raw_into().unwrap();
+        assert_eq!(raw["greeting"].as_str(), Some("yaml"));
+
+        let url = serve_once("HTTP/1.0 404 Not Found\r\n\r\n".into()) + "/config";
+        let err = load(Arc::new(HttpSource::new(&url))).err().unwrap();
+        assert!(format!("{:?}", err).contains("unexpected response `HTTP/1.0 404 Not Found`"));
+
+        // the response is capped in size, and read within the timeout
+        let body = "x".repeat(2048);
+        let url = serve_once(format!("HTTP/1.0 200 OK\r\n\r\n{}", body)) + "/config";
+        let err = load(Arc::new(HttpSource::new(&url).max_size(1024)))
+            .err()
+            .unwrap();
+        assert!(format!("{:?}", err).contains("response larger than 1024 bytes"));
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/config", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            use std::io::Write;
+            let (mut stream, _) = listener.accept().unwrap();
+            // a byte every 50ms never lets a read time out
+            let _ = stream.write_all(b"HTTP/1.0 200 OK\r\n\r\n");
+            for _ in 0..40 {
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                if stream.write_all(b" ").is_err() {
+                    break;
+                }
+            }
+        });
+        let started = std::time::Instant::now();
+        let source = HttpSource::new(&url).timeout(std::time::Duration::from_millis(300));
+        let err = load(Arc::new(source)).err().unwrap();
+        assert!(format!("{:?}", err).contains("timed out"));
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+
+        let err = load(Arc::new(HttpSource::new("https://localhost/config")))
+            .err()
+            .unwrap();
+        assert!(format!("{:?}", err).contains("only http:// URLs are supported"));
+
+        let err = load(Arc::new(FileSource::new(base.join("missing.toml"))))
+            .err()
+            .unwrap();
+        assert!(matches!(err.inner, crate::error::Inner::Io(_)));
+    }
+
+    #[test]
+    fn secret_references() {
+        let base = std::env::temp_dir().join("mrbig_test_secret_references");
+        let _ = std::fs::remove_dir_all(&base);
+        std::fs::create_dir_all(&base).unwrap();
+        let password = base.join("password");
+        std::fs::write(&password, "s3cret\n").unwrap();
+
+        let file = base.join("config.toml");
+        std::fs::write(
+            &file,
+            format!(
+                "token = \"${{env:TOKEN}}\"\nprice = \"$${{amount}}\"\nhosts = [\"a\", \"${{env:HOST}}\"]\n\
+                 [db]\nurl = \"postgres://app:${{file:{}}}@db\"\npassword = \"${{file:{}}}\"\n",
+                password.display(),
+                password.display()
+            ),
+        )
+        .unwrap();
+        let args = vec!["micro".into(), "-c".into(), file.to_str().unwrap().into()];
+        let vars = vec![
+            ("TOKEN".to_string(), "t0ken".to_string()),
+            ("HOST".to_string(), "b".to_string()),
+        ];
+
+        #[derive(Deserialize)]
+        struct User {
+            token: String,
+            price: String,
+            hosts: Vec<String>,
+            db: Db,
+        }
+        #[derive(Debug, Deserialize, Serialize)]
+        struct Db {
+            url: String,
+            password: Secret<String>,
+        }
+
+        let mut cfg = Config::from_args_with(args.clone(), vars.clone(), Cli::default()).unwrap();
+        let debug = format!("{:?}", cfg);
+        let dump = cfg.dump(Format::Toml).unwrap();
+        let user: User = cfg.try_raw_into().unwrap();
+
+        assert_eq!(user.token, "t0ken");
+        assert_eq!(user.price, "${amount}");
+        assert_eq!(user.hosts, vec!["a", "b"]);
+        assert_eq!(user.db.url, "postgres://app:s3cret@db");
+        assert_eq!(user.db.password.expose(), "s3cret");
+
+        // references are resolved in place, the values keep their origin
+        assert_eq!(cfg.source_of("db.password"), Source::File(file.clone()));
+
+        // resolved values are never printed
+        for secret in &["t0ken", "s3cret"] {
+            assert!(!debug.contains(secret));
+            assert!(!dump.contains(secret));
+        }
+        assert!(dump.contains("${amount}"));
+        assert!(format!("{:?}", user.db).contains(REDACTED));
+        let password = cfg.dump_with(&user.db, Format::Toml).unwrap();
+        assert!(password.contains(&format!("password = \"{}\"", REDACTED)));
+
+        // unset variables fail the load
+        let err = Config::from_args_with(args, vec![], Cli::default())
+            .err()
+            .unwrap();
+        match err.inner {
+            crate::error::Inner::Other(msg) => {
+                assert_eq!(msg, "`hosts` references unset environment variable `HOST`")
+            }
+            other => panic!("unexpected error {:?}", other),
+        }
+    }
+
+    #[test]
+    fn secret_fields() {
+        let path = std::env::temp_dir().join("mrbig_test_secret_fields.toml");
+        std::fs::write(
+            &path,
+            "user = \"app\"\npassword = \"hunter2\"\n[db]\npassword = \"s3cret\"\n\
+             [tokens]\nci = \"t0ken\"\n",
+        )
+        .unwrap();
+
+        #[derive(Deserialize)]
+        struct User {
+            #[allow(dead_code)]
+            user: String,
+            #[allow(dead_code)]
+            password: Secret<String>,
+            #[allow(dead_code)]
+            db: Option<Db>,
+            #[allow(dead_code)]
+            tokens: std::collections::HashMap<String, Secret<String>>,
+        }
+        #[derive(Deserialize)]
+        struct Db {
+            #[allow(dead_code)]
+            password: Secret<String>,
+        }
+
+        let kind = schema::describe::<User>();
+        let secret = schema::Kind::Secret(Box::new(schema::Kind::String));
+        assert_eq!(kind.get("password"), Some(&secret));
+        assert_eq!(kind.get("db.password"), Some(&secret));
+        assert_eq!(kind.hint(), "table");
+
+        #[derive(Default)]
+        struct Micro {
+            config: Option<Config>,
+        }
+
+        impl Configurable<'_> for Micro {
+            type Extra = User;
+
+            fn get_config(&self) -> Option<&Config> {
+                self.config.as_ref()
+            }
+
+            fn set_config(&mut self, config: Config) {
+                self.config = Some(config);
+            }
+
+            fn set_config_extra(&mut self, _extra: Self::Extra) {}
+        }
+
+        let args = vec![
+            "micro".to_string(),
+            "-c".into(),
+            path.to_str().unwrap().into(),
+            "--print-config".into(),
+        ];
+        let dump = match Micro::default().load_from_args_vec(args).unwrap_err().inner {
+            crate::error::Inner::Exit(output) => output,
+            e => panic!("unexpected error {:?}", e),
+        };
+
+        // the plain values of `Secret` fields are never printed
+        for secret in &["hunter2", "s3cret", "t0ken"] {
+            assert!(!dump.contains(secret), "{}", dump);
+        }
+        assert!(dump.contains(&format!("password = \"{}\"", REDACTED)));
+        assert!(dump.contains("user = \"app\""));
+
+        // and still set through `--set`
+        let set = vec!["micro".into(), "--set".into(), "password=pa55".into()];
+        let mut cfg = Config::from_args_with(set, vec![], Micro::default().cli()).unwrap();
+        let dump = cfg.dump(Format::Toml).unwrap();
+        assert!(!dump.contains("pa55"));
+        assert_eq!(cfg.try_raw_into::<Db>().unwrap().password.expose(), "pa55");
+    }
+
+    #[test]
+    fn encrypted_values() {
+        use cipher::Key;
+
+        let base = std::env::temp_dir().join("mrbig_test_encrypted_values");
+        let _ = std::fs::remove_dir_all(&base);
+        std::fs::create_dir_all(&base).unwrap();
+
+        let key = Key::generate();
+        let key_file = base.join("key");
+        key.write(&key_file).unwrap();
+        assert!(key.write(&key_file).is_err());
+
+        let file = base.join("config.toml");
+        std::fs::write(
+            &file,
+            format!(
+                "password = \"{}\"\ntokens = [\"plain\", \"{}\"]\n\
+                 [[replicas]]\nhost = \"db1\"\n\
+                 [[replicas]]\nhost = \"db2\"\npassword = \"{}\"\n",
+                key.encrypt("s3cret"),
+                key.encrypt("t0ken"),
+                key.encrypt("r3plica")
+            ),
+        )
+        .unwrap();
+        let load = |vars: Vec<(String, String)>| {
+            let args = vec!["micro".into(), "-c".into(), file.to_str().unwrap().into()];
+            Config::from_args_with(args, vars, Cli::default())
+        };
+        let var = |name: &str, value: &str| vec![(name.to_string(), value.to_string())];
+
+        #[derive(Deserialize)]
+        struct User {
+            password: String,
+            tokens: Vec<String>,
+            replicas: Vec<Replica>,
+        }
+
+        #[derive(Deserialize)]
+        struct Replica {
+            password: Option<String>,
+        }
+
+        let mut cfg = load(var("MRBIG_SERVICE__KEY_FILE", key_file.to_str().unwrap())).unwrap();
+        let dump = cfg.dump(Format::Toml).unwrap();
+        let user: User = cfg.try_raw_into().unwrap();
+
+        assert_eq!(user.password, "s3cret");
+        assert_eq!(user.tokens, vec!["plain", "t0ken"]);
+        assert_eq!(user.replicas[0].password, None);
+        assert_eq!(user.replicas[1].password.as_deref(), Some("r3plica"));
+        assert_eq!(cfg.service.key_file, Some(key_file.clone()));
+        assert!(!dump.contains("s3cret") && !dump.contains("t0ken"));
+        assert!(!dump.contains("r3plica"));
+
+        let message = |err: crate::error::Error| match err.inner {
+            crate::error::Inner::Other(msg) => msg,
+            other => panic!("unexpected error {:?}", other),
+        };
+
+        // the key is required, and must be the right one
+        let err = load(vec![]).err().unwrap();
+        assert_eq!(
+            message(err),
+            "`password` is encrypted but `service.key_file` is not set"
+        );
+
+        let other = base.join("other");
+        Key::generate().write(&other).unwrap();
+        let err = load(var("MRBIG_SERVICE__KEY_FILE", other.to_str().unwrap()))
+            .err()
+            .unwrap();
+        assert_eq!(
+            message(err),
+            "`password` cannot be decrypted, the key is wrong or the value corrupted"
+        );
+
+        std::fs::write(&other, "c2hvcnQ=\n").unwrap();
+        let err = load(var("MRBIG_SERVICE__KEY_FILE", other.to_str().unwrap()))
+            .err()
+            .unwrap();
+        assert!(message(err).ends_with("invalid key length 5, expected 32 bytes"));
+    }
 }
@@ -0,0 +1,218 @@
+//! Encrypted configuration values.
+//!
+//! Values such as `"enc:aes-gcm:KHu1aZ..."` hold a string encrypted with
+//! AES-256-GCM, so config files with sensitive values can be committed.
+//! They are decrypted at load time with the key read from the file at
+//! `service.key_file`, before the configuration is deserialized, and are
+//! redacted when the configuration is printed.
+//!
+//! Keys are generated and values encrypted by `mrbig config encrypt`,
+//! or with `Key`:
+//!
+//! ```
+//! use mrbig_core::config::cipher::Key;
+//!
+//! let key = Key::generate();
+//! let value = key.encrypt("s3cret");
+//! assert!(value.starts_with("enc:aes-gcm:"));
+//! assert_eq!(key.decrypt(&value).unwrap(), "s3cret");
+//! ```
+use aes_gcm::aead::{Aead, KeyInit, OsRng};
+use aes_gcm::{AeadCore, Aes256Gcm, Nonce};
+use std::path::Path;
+use toml::value::{Table, Value};
+
+/// Prefix of the encrypted values.
+pub const PREFIX: &str = "enc:aes-gcm:";
+
+const NONCE_LEN: usize = 12;
+
+/// Whether `value` is an encrypted value.
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(PREFIX)
+}
+
+/// A 256 bits AES key, stored base64 encoded.
+#[derive(Clone)]
+pub struct Key(aes_gcm::Key<Aes256Gcm>);
+
+impl Key {
+    /// Generates a random key.
+    pub fn generate() -> Self {
+        Key(Aes256Gcm::generate_key(&mut OsRng))
+    }
+
+    /// Parses a base64 encoded key.
+    pub fn from_base64(encoded: &str) -> Result<Self, crate::error::Error> {
+        Ok(Self::parse(encoded)?)
+    }
+
+    /// Reads the key stored in the file at `path`.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, crate::error::Error> {
+        let path = path.as_ref();
+        let key = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|encoded| Self::parse(&encoded))
+            .map_err(|e| format!("key file `{}`: {}", path.display(), e))?;
+        Ok(key)
+    }
+
+    fn parse(encoded: &str) -> Result<Self, String> {
+        let bytes =
+            base64::decode(encoded.trim()).map_err(|e| format!("invalid key encoding: {}", e))?;
+        if bytes.len() != 32 {
+            return Err(format!(
+                "invalid key length {}, expected 32 bytes",
+                bytes.len()
+            ));
+        }
+        Ok(Key(*aes_gcm::Key::<Aes256Gcm>::from_slice(&bytes)))
+    }
+
+    /// Stores the key in a new file at `path`, only readable by its owner.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), crate::error::Error> {
+        use std::io::Write;
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        let path = path.as_ref();
+        options
+            .open(path)
+            .and_then(|mut file| writeln!(file, "{}", self.to_base64()))
+            .map_err(|e| format!("key file `{}`: {}", path.display(), e))?;
+        Ok(())
+    }
+
+    /// Base64 encoding of the key.
+    pub fn to_base64(&self) -> String {
+        base64::encode(self.0)
+    }
+
+    /// Encrypts `plaintext` into a value starting with `enc:aes-gcm:`.
+    pub fn encrypt(&self, plaintext: &str) -> String {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = Aes256Gcm::new(&self.0)
+            .encrypt(&nonce, plaintext.as_bytes())
+            .expect("encryption cannot fail");
+
+        let mut envelope = nonce.to_vec();
+        envelope.extend(ciphertext);
+        format!("{}{}", PREFIX, base64::encode(envelope))
+    }
+
+    /// Decrypts a value produced by `encrypt`.
+    pub fn decrypt(&self, value: &str) -> Result<String, String> {
+        let encoded = value
+            .strip_prefix(PREFIX)
+            .ok_or_else(|| format!("is not of the form {}<base64>", PREFIX))?;
+        let envelope =
+            base64::decode(encoded).map_err(|e| format!("is not valid base64: {}", e))?;
+        if envelope.len() < NONCE_LEN {
+            return Err("is too short to be an encrypted value".into());
+        }
+
+        let (nonce, ciphertext) = envelope.split_at(NONCE_LEN);
+        let plaintext = Aes256Gcm::new(&self.0)
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "cannot be decrypted, the key is wrong or the value corrupted")?;
+        String::from_utf8(plaintext).map_err(|_| "does not decrypt to UTF-8 text".into())
+    }
+}
+
+impl std::fmt::Debug for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(super::REDACTED)
+    }
+}
+
+/// Decrypts the encrypted string values of `table` with the key read from
+/// `service.key_file`, which is only required when there are any. The
+/// dotted keys of the decrypted values (or of the arrays holding them) are
+/// added to `decrypted`.
+pub(crate) fn decrypt(
+    table: &mut Table,
+    decrypted: &mut Vec<String>,
+) -> Result<(), crate::error::Error> {
+    let first = match find(table, &mut vec![]) {
+        Some(key) => key,
+        None => return Ok(()),
+    };
+
+    let key_file = table
+        .get("service")
+        .and_then(|service| service.get("key_file"))
+        .and_then(Value::as_str)
+        .ok_or_else(|| format!("`{}` is encrypted but `service.key_file` is not set", first))?;
+    let key = Key::read(key_file)?;
+
+    for (name, value) in table.iter_mut() {
+        decrypt_value(value, &mut vec![name.clone()], &key, decrypted)?;
+    }
+    Ok(())
+}
+
+/// Returns the dotted key of the first encrypted value of `table`.
+fn find(table: &Table, path: &mut Vec<String>) -> Option<String> {
+    for (name, value) in table {
+        path.push(name.clone());
+        let found = find_value(value, path);
+        path.pop();
+
+        if found.is_some() {
+            return found;
+        }
+    }
+    None
+}
+
+fn find_value(value: &Value, path: &mut Vec<String>) -> Option<String> {
+    match value {
+        Value::String(s) if is_encrypted(s) => Some(path.join(".")),
+        Value::Table(inner) => find(inner, path),
+        // items have no key of their own, the array is named
+        Value::Array(items) => items
+            .iter()
+            .find_map(|item| find_value(item, path))
+            .map(|_| path.join(".")),
+        _ => None,
+    }
+}
+
+fn decrypt_value(
+    value: &mut Value,
+    path: &mut Vec<String>,
+    key: &Key,
+    decrypted: &mut Vec<String>,
+) -> Result<(), crate::error::Error> {
+    let fail = |path: &[String], e: String| format!("`{}` {}", path.join("."), e);
+
+    match value {
+        Value::String(s) if is_encrypted(s) => {
+            *s = key.decrypt(s).map_err(|e| fail(path, e))?;
+            decrypted.push(path.join("."));
+        }
+        Value::Table(inner) => {
+            for (name, value) in inner.iter_mut() {
+                path.push(name.clone());
+                decrypt_value(value, path, key, decrypted)?;
+                path.pop();
+            }
+        }
+        Value::Array(items) => {
+            // items have no key of their own, the whole array is recorded
+            let before = decrypted.len();
+            for item in items {
+                decrypt_value(item, path, key, decrypted)?;
+            }
+            if decrypted.len() > before {
+                decrypted.truncate(before);
+                decrypted.push(path.join("."));
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
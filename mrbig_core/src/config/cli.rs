@@ -0,0 +1,211 @@
+//! Command line interface of a service.
+//!
+//! Besides `Mr. Big`'s own flags, services may declare extra flags, each
+//! setting a configuration value, and a version printed by `--version`.
+use super::schema::{describe, Kind};
+use super::source::ConfigSource;
+use std::sync::Arc;
+
+/// A command line flag setting the configuration value at `key`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Flag {
+    /// Long name, without the leading dashes.
+    pub long: String,
+    /// Optional single character name.
+    pub short: Option<char>,
+    /// Dotted key of the configuration value to set, for instance `db.url`
+    /// or `service.grpc_server.timeout`.
+    pub key: String,
+    /// Description printed in the usage text.
+    pub help: String,
+    /// Whether the flag takes no value and sets the key to `true`.
+    pub switch: bool,
+}
+
+impl Flag {
+    /// Flag taking a value, as in `--db-url postgres://db`.
+    pub fn new(long: &str, key: &str, help: &str) -> Self {
+        Flag {
+            long: long.into(),
+            short: None,
+            key: key.into(),
+            help: help.into(),
+            switch: false,
+        }
+    }
+
+    /// Flag taking no value, as in `--dry-run`, which sets the key to `true`.
+    pub fn switch(long: &str, key: &str, help: &str) -> Self {
+        Flag {
+            switch: true,
+            ..Flag::new(long, key, help)
+        }
+    }
+
+    /// Sets the single character name of the flag.
+    pub fn short(mut self, short: char) -> Self {
+        self.short = Some(short);
+        self
+    }
+
+    /// Flags setting each of the user defined parameters described by
+    /// `extra`, named after their dotted key: `--db-url` sets `db.url` and
+    /// `--poll-interval` sets `poll_interval`. Boolean parameters get a
+    /// switch. Parameters already set by one of the `declared` flags, and
+    /// names taken by those or by `Mr. Big`'s own flags, are skipped.
+    pub fn params(extra: &Kind, declared: &[Flag]) -> Vec<Flag> {
+        let mut taken: Vec<String> = super::RESERVED_FLAGS
+            .iter()
+            .map(|name| name.to_string())
+            .chain(declared.iter().map(|flag| flag.long.clone()))
+            .collect();
+
+        let mut flags = vec![];
+        for (key, kind) in extra.leaves() {
+            let long = key.replace(&['.', '_'][..], "-");
+            let skip = key.is_empty()
+                || matches!(kind.unwrap_option(), Kind::Struct(_))
+                || declared.iter().any(|flag| flag.key == key)
+                || taken.contains(&long);
+            if skip {
+                continue;
+            }
+
+            let help = format!("set the `{}` parameter", key);
+            let flag = match kind.unwrap_option() {
+                Kind::Bool => Flag::switch(&long, &key, &help),
+                _ => Flag::new(&long, &key, &help),
+            };
+            taken.push(long);
+            flags.push(flag);
+        }
+
+        flags
+    }
+}
+
+/// Description of a service's command line.
+#[derive(Clone, Debug, Default)]
+pub struct Cli {
+    /// Text printed by `--version`, defaults to the program name.
+    pub version: Option<String>,
+    /// Extra flags declared by the service.
+    pub flags: Vec<Flag>,
+    /// Shape of the user defined parameters, listed in the usage text.
+    pub extra: Kind,
+    /// JSON Schema of the user defined parameters, derived from `extra`
+    /// when not given.
+    pub schema: Option<serde_json::Value>,
+    /// Configuration sources loaded after the config file, in increasing
+    /// order of priority.
+    pub sources: Vec<Arc<dyn ConfigSource>>,
+}
+
+impl Cli {
+    /// Registers the extra flags in `opts`.
+    pub(crate) fn register(&self, opts: &mut getopts::Options) {
+        for flag in &self.flags {
+            let short = flag.short.map(|c| c.to_string()).unwrap_or_default();
+            if flag.switch {
+                opts.optflag(&short, &flag.long, &flag.help);
+            } else {
+                let hint = self.hint(&flag.key).to_ascii_uppercase();
+                opts.optopt(&short, &flag.long, &flag.help, &hint);
+            }
+        }
+    }
+
+    /// Returns the version text, or the program name with `Mr. Big`'s version.
+    pub(crate) fn version(&self, program: &str) -> String {
+        match &self.version {
+            Some(version) => version.clone(),
+            None => format!(
+                "{} (mrbig {})",
+                std::path::Path::new(program)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| program.to_string()),
+                env!("CARGO_PKG_VERSION")
+            ),
+        }
+    }
+
+    /// Builds the usage text, listing the flags and the user defined parameters.
+    pub(crate) fn usage(&self, program: &str, opts: &getopts::Options) -> String {
+        let brief = format!("Usage: {} [options]", program);
+        let mut usage = opts.usage(&brief);
+
+        let params = self.extra.leaves();
+        let params: Vec<_> = params.iter().filter(|(key, _)| !key.is_empty()).collect();
+
+        if !params.is_empty() {
+            let width = params.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+
+            usage.push_str("\nParameters (config file or MRBIG_EXTRA__<KEY> variables):\n");
+            for (key, kind) in params {
+                usage.push_str(&format!(
+                    "    {:width$}  {}\n",
+                    key,
+                    kind.hint(),
+                    width = width
+                ));
+            }
+        }
+
+        usage
+    }
+
+    /// JSON Schema of the whole configuration.
+    pub fn json_schema(&self) -> serde_json::Value {
+        let extra = match &self.schema {
+            Some(schema) => schema.clone(),
+            None => self.extra.json_schema(),
+        };
+        super::schema::json_schema(extra)
+    }
+
+    /// Parses a `--set KEY=VALUE` argument into the path of the value to
+    /// set and the value, converted to the kind expected at that path.
+    pub(crate) fn set(&self, arg: &str) -> Result<(Vec<String>, toml::Value), String> {
+        let (key, value) = match arg.find('=') {
+            Some(idx) => (arg[..idx].trim(), &arg[idx + 1..]),
+            None => return Err(format!("`--set {}` is not of the form KEY=VALUE", arg)),
+        };
+
+        let kind = self
+            .kind(key)
+            .ok_or_else(|| format!("`--set {}`: unknown configuration key `{}`", arg, key))?;
+        let value = super::value::typed(value, &kind)
+            .map_err(|e| format!("`--set {}`: {} for `{}`", arg, e, key))?;
+
+        Ok((key.split('.').map(String::from).collect(), value))
+    }
+
+    /// Kind of the value at the dotted `key`, either one of `Mr. Big`'s
+    /// parameters or a user defined one.
+    fn kind(&self, key: &str) -> Option<Kind> {
+        if key.is_empty() || key.split('.').any(str::is_empty) {
+            return None;
+        }
+
+        match key {
+            "service" => Some(describe::<super::Service>()),
+            key => match key.strip_prefix("service.") {
+                Some(key) => describe::<super::Service>().get(key).cloned(),
+                None => match &self.extra {
+                    // with no parameters of its own, as with `Void`, the
+                    // service reads its sections from the raw table
+                    Kind::Struct(fields) if fields.is_empty() => Some(Kind::Any),
+                    extra => extra.get(key).cloned(),
+                },
+            },
+        }
+    }
+
+    fn hint(&self, key: &str) -> String {
+        match self.kind(key) {
+            Some(Kind::Any) | None => "value".into(),
+            Some(kind) => kind.hint(),
+        }
+    }
+}
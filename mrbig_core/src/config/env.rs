@@ -0,0 +1,54 @@
+//! Environment variables overlay.
+//!
+//! Variables named `MRBIG_SERVICE__<PATH>` override the `service`
+//! section, and variables named `MRBIG_EXTRA__<PATH>` override the
+//! user defined (extra) configuration parameters. Path segments are
+//! separated by a double underscore and matched in lowercase, so
+//! `MRBIG_SERVICE__GRPC_SERVER__TIMEOUT` sets `service.grpc_server.timeout`
+//! and `MRBIG_EXTRA__DB__URL` sets `db.url`.
+/// Prefix of every environment variable read by `Mr. Big`.
+pub const ENV_PREFIX: &str = "MRBIG_";
+/// Separator between the segments of a configuration path.
+pub const ENV_SEPARATOR: &str = "__";
+/// Name of the section holding the user defined parameters.
+pub const ENV_EXTRA: &str = "EXTRA";
+/// Name of the section holding `Mr. Big`'s parameters.
+pub const ENV_SERVICE: &str = "SERVICE";
+/// Variable selecting the configuration profile.
+pub const ENV_PROFILE: &str = "MRBIG_PROFILE";
+
+/// Returns the profile selected in `vars`, if any.
+pub(crate) fn profile(vars: &[(String, String)]) -> Option<String> {
+    vars.iter()
+        .find(|(name, _)| name == ENV_PROFILE)
+        .map(|(_, value)| value.clone())
+}
+
+/// Splits a variable name into its configuration path,
+/// or returns `None` if the variable is not a configuration variable.
+pub(crate) fn path_of(name: &str) -> Option<Result<Vec<String>, String>> {
+    let rest = name.strip_prefix(ENV_PREFIX)?;
+
+    let (section, rest) = match rest.find(ENV_SEPARATOR) {
+        Some(idx) => (&rest[..idx], &rest[idx + ENV_SEPARATOR.len()..]),
+        None => return None,
+    };
+
+    let mut path: Vec<String> = match section {
+        ENV_SERVICE => vec!["service".into()],
+        ENV_EXTRA => vec![],
+        _ => return None,
+    };
+
+    for segment in rest.split(ENV_SEPARATOR) {
+        if segment.is_empty() {
+            return Some(Err(format!(
+                "invalid configuration environment variable `{}`",
+                name
+            )));
+        }
+        path.push(segment.to_ascii_lowercase());
+    }
+
+    Some(Ok(path))
+}
@@ -0,0 +1,204 @@
+//! Config file formats.
+//!
+//! Config files may be written in TOML, YAML or JSON. The format is
+//! picked from the `--config-format` flag or, when not given, from the
+//! file extension (`.yaml`/`.yml` for YAML, `.json` for JSON, anything
+//! else for TOML). Whatever the format, the file is read into the same
+//! tree of TOML values, so `Service` and the user defined parameters
+//! deserialize the same way.
+use serde::de::Error as _;
+use std::path::Path;
+use toml::value::{Table, Value};
+
+/// Format of a config file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Format::Toml => write!(f, "TOML"),
+            Format::Yaml => write!(f, "YAML"),
+            Format::Json => write!(f, "JSON"),
+        }
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = crate::error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "toml" => Ok(Format::Toml),
+            "yaml" | "yml" => Ok(Format::Yaml),
+            "json" => Ok(Format::Json),
+            _ => Err(format!(
+                "unknown config format `{}`, expected one of toml, yaml or json",
+                s
+            )
+            .into()),
+        }
+    }
+}
+
+impl Format {
+    /// Guesses the format from the extension of `path`, defaulting to TOML.
+    pub fn from_path(path: &Path) -> Format {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml") => {
+                Format::Yaml
+            }
+            Some(ext) if ext.eq_ignore_ascii_case("json") => Format::Json,
+            _ => Format::Toml,
+        }
+    }
+
+    /// Parses `buffer` into a table of TOML values.
+    pub(crate) fn parse(self, buffer: &[u8]) -> Result<Table, crate::error::Error> {
+        let parse_error = |error: String| crate::error::Error {
+            inner: crate::error::Inner::Parse {
+                format: self,
+                error,
+            },
+        };
+
+        // an empty file is an empty config, whatever the format
+        if buffer.iter().all(u8::is_ascii_whitespace) {
+            return Ok(Table::new());
+        }
+
+        let value = match self {
+            Format::Toml => {
+                return toml::de::from_slice(buffer).map_err(|e| parse_error(e.to_string()))
+            }
+            Format::Yaml => serde_yaml::from_slice::<serde_yaml::Value>(buffer)
+                .map_err(|e| parse_error(e.to_string()))
+                .and_then(|v| from_yaml(v, &mut vec![]).map_err(|e| self.key_error(e)))?,
+            Format::Json => serde_json::from_slice::<serde_json::Value>(buffer)
+                .map_err(|e| parse_error(e.to_string()))
+                .and_then(|v| from_json(v, &mut vec![]).map_err(|e| self.key_error(e)))?,
+        };
+
+        match value {
+            Some(Value::Table(table)) => Ok(table),
+            None => Ok(Table::new()), // null document
+            Some(other) => Err(parse_error(format!(
+                "expected a map at the top level, found {}",
+                other.type_str()
+            ))),
+        }
+    }
+
+    fn key_error(self, (key, message): (Vec<String>, String)) -> crate::error::Error {
+        crate::error::Error {
+            inner: crate::error::Inner::Key {
+                format: self,
+                key: key.join("."),
+                error: toml::de::Error::custom(message),
+            },
+        }
+    }
+}
+
+/// Path of the offending key and error message of a failed conversion.
+type ConvertError = (Vec<String>, String);
+
+/// Converts a JSON value into a TOML value. Null values have no TOML
+/// counterpart, so they are dropped from maps and `None` is returned.
+fn from_json(
+    value: serde_json::Value,
+    path: &mut Vec<String>,
+) -> Result<Option<Value>, ConvertError> {
+    use serde_json::Value as Json;
+
+    Ok(Some(match value {
+        Json::Null => return Ok(None),
+        Json::Bool(b) => Value::Boolean(b),
+        Json::Number(n) => match (n.as_i64(), n.as_f64()) {
+            (Some(i), _) => Value::Integer(i),
+            (None, Some(f)) if !n.is_u64() => Value::Float(f),
+            _ => return Err((path.clone(), format!("number {} is out of range", n))),
+        },
+        Json::String(s) => Value::String(s),
+        Json::Array(items) => Value::Array(array(items, path, from_json)?),
+        Json::Object(map) => {
+            let mut table = Table::new();
+            for (key, item) in map {
+                path.push(key);
+                if let Some(v) = from_json(item, path)? {
+                    table.insert(path.last().cloned().unwrap_or_default(), v);
+                }
+                path.pop();
+            }
+            Value::Table(table)
+        }
+    }))
+}
+
+/// Converts a YAML value into a TOML value. Null values have no TOML
+/// counterpart, so they are dropped from maps and `None` is returned.
+fn from_yaml(
+    value: serde_yaml::Value,
+    path: &mut Vec<String>,
+) -> Result<Option<Value>, ConvertError> {
+    use serde_yaml::Value as Yaml;
+
+    Ok(Some(match value {
+        Yaml::Null => return Ok(None),
+        Yaml::Bool(b) => Value::Boolean(b),
+        Yaml::Number(n) => match (n.as_i64(), n.as_f64()) {
+            (Some(i), _) => Value::Integer(i),
+            (None, Some(f)) if !n.is_u64() => Value::Float(f),
+            _ => return Err((path.clone(), format!("number {} is out of range", n))),
+        },
+        Yaml::String(s) => Value::String(s),
+        Yaml::Sequence(items) => Value::Array(array(items, path, from_yaml)?),
+        Yaml::Mapping(map) => {
+            let mut table = Table::new();
+            for (key, item) in map {
+                let key = match key {
+                    Yaml::String(s) => s,
+                    Yaml::Bool(b) => b.to_string(),
+                    Yaml::Number(n) => n.to_string(),
+                    _ => return Err((path.clone(), "map keys must be strings".into())),
+                };
+                path.push(key);
+                if let Some(v) = from_yaml(item, path)? {
+                    table.insert(path.last().cloned().unwrap_or_default(), v);
+                }
+                path.pop();
+            }
+            Value::Table(table)
+        }
+    }))
+}
+
+fn array<T, F>(
+    items: Vec<T>,
+    path: &mut Vec<String>,
+    convert: F,
+) -> Result<Vec<Value>, ConvertError>
+where
+    F: Fn(T, &mut Vec<String>) -> Result<Option<Value>, ConvertError>,
+{
+    let mut values = Vec::with_capacity(items.len());
+    for (idx, item) in items.into_iter().enumerate() {
+        path.push(idx.to_string());
+        match convert(item, path)? {
+            Some(v) => values.push(v),
+            None => {
+                return Err((
+                    path.clone(),
+                    "null values are not supported in arrays".into(),
+                ))
+            }
+        }
+        path.pop();
+    }
+    Ok(values)
+}
@@ -0,0 +1,148 @@
+//! Addresses the gRPC server listens at.
+//!
+//! `service.listen` lists them as URLs, TCP addresses such as
+//! `"tcp://0.0.0.0:3000"` or Unix domain sockets such as
+//! `"unix:///run/micro.sock"`, parsed into `Listen` when validated. When
+//! empty, the server listens at `service.hostname` and `service.port`.
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, Schema, SchemaObject, StringValidation};
+use schemars::JsonSchema;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+/// An address to listen at.
+///
+/// ```
+/// use mrbig_core::config::Listen;
+///
+/// let listen: Listen = "unix:///run/micro.sock".parse().unwrap();
+/// assert_eq!(listen, Listen::Unix("/run/micro.sock".into()));
+/// assert_eq!(listen.to_string(), "unix:///run/micro.sock");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Listen {
+    /// A TCP address, `tcp://<ip>:<port>`.
+    Tcp(SocketAddr),
+    /// The path of a Unix domain socket, `unix://<path>`.
+    Unix(PathBuf),
+}
+
+impl std::str::FromStr for Listen {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(addr) = s.strip_prefix("tcp://") {
+            addr.parse()
+                .map(Listen::Tcp)
+                .map_err(|_| format!("`{}` is not a valid address to bind to", addr))
+        } else if let Some(path) = s.strip_prefix("unix://") {
+            if path.is_empty() {
+                return Err(format!("`{}` has no socket path", s));
+            }
+            Ok(Listen::Unix(path.into()))
+        } else {
+            Err(format!(
+                "`{}` is neither a `tcp://` nor a `unix://` address",
+                s
+            ))
+        }
+    }
+}
+
+impl std::fmt::Display for Listen {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Listen::Tcp(addr) => write!(f, "tcp://{}", addr),
+            Listen::Unix(path) => write!(f, "unix://{}", path.display()),
+        }
+    }
+}
+
+impl JsonSchema for Listen {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        "Listen".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            string: Some(Box::new(StringValidation {
+                pattern: Some("^(tcp|unix)://.+$".into()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+/// Serde helpers for permission modes, given as an integer such as
+/// `0o660` or as a string of octal digits such as `"0660"`, which also
+/// fits environment variables.
+pub(crate) mod mode {
+    use serde::de::{self, Visitor};
+    use std::convert::TryFrom;
+
+    struct ModeVisitor;
+
+    impl<'de> Visitor<'de> for ModeVisitor {
+        type Value = u32;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "a permission mode such as \"0660\" or 0o660")
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<u32, E> {
+            u32::try_from(v).map_err(|_| E::invalid_value(de::Unexpected::Unsigned(v), &self))
+        }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<u32, E> {
+            u32::try_from(v).map_err(|_| E::invalid_value(de::Unexpected::Signed(v), &self))
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<u32, E> {
+            let digits = v.strip_prefix("0o").unwrap_or(v);
+            u32::from_str_radix(digits, 8)
+                .map_err(|_| E::invalid_value(de::Unexpected::Str(v), &self))
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<u32, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(ModeVisitor)
+    }
+
+    pub fn serialize<S>(mode: &u32, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(&format_args!("{:04o}", mode))
+    }
+}
+
+/// Stands for the permission modes read by `mode` in JSON Schemas.
+pub(crate) struct ModeSchema;
+
+impl JsonSchema for ModeSchema {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        "Mode".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(vec![InstanceType::String, InstanceType::Integer].into()),
+            ..Default::default()
+        }
+        .into()
+    }
+}
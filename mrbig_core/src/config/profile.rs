@@ -0,0 +1,95 @@
+//! Configuration profiles.
+//!
+//! A profile (for instance `dev`, `staging` or `prod`) is a set of
+//! values deep merged onto the base config file. Profile values may
+//! come from a `[profile.<name>]` section of the base file and from
+//! an overlay file sitting next to it, named after the base file with
+//! the profile name before the extension (`config.toml` gets
+//! `config.<name>.toml`). The overlay file takes precedence over the
+//! section. A `profile` key which is not a table of tables is left to the
+//! user defined parameters.
+use super::provenance::{Source, Sources};
+use super::value::merge;
+use std::path::{Path, PathBuf};
+use toml::value::{Table, Value};
+
+/// Key of the section holding the profiles in the base file.
+pub const PROFILE_SECTION: &str = "profile";
+
+/// Returns the path of the overlay file for a given profile.
+pub(crate) fn overlay_path(base: &Path, profile: &str) -> PathBuf {
+    let stem = base
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let name = match base.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, profile, ext.to_string_lossy()),
+        None => format!("{}.{}", stem, profile),
+    };
+
+    base.with_file_name(name)
+}
+
+/// Applies `profile` onto the `base` table read from the file at `path`.
+///
+/// A `profile` table of tables is the profiles section, always removed
+/// from `base` so it is not mistaken for user defined parameters; any
+/// other `profile` value is left to the user. Fails if the profile is
+/// defined neither in the section nor in an overlay file.
+pub(crate) fn apply<F>(
+    base: &mut Table,
+    path: &Path,
+    profile: Option<&str>,
+    read: F,
+    sources: &mut Sources,
+) -> Result<(), crate::error::Error>
+where
+    F: Fn(&Path) -> Result<Table, crate::error::Error>,
+{
+    let section = matches!(
+        base.get(PROFILE_SECTION),
+        Some(Value::Table(profiles)) if profiles.values().all(Value::is_table)
+    );
+    let mut profiles = match base.remove(PROFILE_SECTION) {
+        Some(Value::Table(profiles)) if section => profiles,
+        Some(value) => {
+            base.insert(PROFILE_SECTION.into(), value);
+            Table::new()
+        }
+        None => Table::new(),
+    };
+
+    let profile = match profile {
+        Some(profile) => profile,
+        None => return Ok(()),
+    };
+
+    let mut found = false;
+
+    if let Some(Value::Table(section)) = profiles.remove(profile) {
+        sources.record_table(&mut vec![], &section, &Source::File(path.to_path_buf()));
+        merge(base, section);
+        found = true;
+    }
+
+    let overlay = overlay_path(path, profile);
+    if overlay.is_file() {
+        let table = read(&overlay)?;
+        sources.record_table(&mut vec![], &table, &Source::File(overlay.clone()));
+        merge(base, table);
+        found = true;
+    }
+
+    if !found {
+        return Err(format!(
+            "profile `{}` not found in `{}` nor in `{}`",
+            profile,
+            path.display(),
+            overlay.display()
+        )
+        .into());
+    }
+
+    Ok(())
+}
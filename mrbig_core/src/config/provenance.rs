@@ -0,0 +1,232 @@
+//! Provenance of the configuration values.
+//!
+//! While the configuration is loaded, the origin of every value is
+//! recorded (default, config file, environment variable, command line
+//! flag or other configuration source), so the effective configuration
+//! can be printed along with where each value came from.
+use super::schema::Kind;
+use super::Format;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use toml::value::{Table, Value};
+
+/// Replacement of the values marked as secret when printed.
+pub const REDACTED: &str = "<redacted>";
+
+/// Origin of a configuration value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    /// Default value, not set anywhere.
+    Default,
+    /// Config file, including profile sections and overlay files.
+    File(PathBuf),
+    /// Environment variable, by name.
+    Env(String),
+    /// Command line flag.
+    Cli(String),
+    /// HTTP endpoint, by URL.
+    Http(String),
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::File(path) => write!(f, "file {}", path.display()),
+            Source::Env(name) => write!(f, "env {}", name),
+            Source::Cli(flag) => write!(f, "cli {}", flag),
+            Source::Http(url) => write!(f, "http {}", url),
+        }
+    }
+}
+
+/// Origins of the values set while loading, by dotted key.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Sources(BTreeMap<String, Source>);
+
+impl Sources {
+    /// Records `source` as the origin of the value at `path`,
+    /// replacing the origins of the values nested under it.
+    pub(crate) fn record(&mut self, path: &[String], source: Source) {
+        let key = path.join(".");
+        let nested = format!("{}.", key);
+
+        self.0.retain(|k, _| !k.starts_with(&nested));
+        self.0.insert(key, source);
+    }
+
+    /// Records `source` as the origin of every value in `table`,
+    /// nested under `path`.
+    pub(crate) fn record_table(&mut self, path: &mut Vec<String>, table: &Table, source: &Source) {
+        for (key, value) in table {
+            path.push(key.clone());
+            match value {
+                Value::Table(inner) => self.record_table(path, inner, source),
+                _ => self.record(path, source.clone()),
+            }
+            path.pop();
+        }
+    }
+
+    /// Returns the origin of the value at `key`, which is the origin
+    /// recorded for the key itself or for its closest parent.
+    pub(crate) fn get(&self, key: &str) -> Source {
+        let mut key = key;
+        loop {
+            if let Some(source) = self.0.get(key) {
+                return source.clone();
+            }
+            match key.rfind('.') {
+                Some(idx) => key = &key[..idx],
+                None => return Source::Default,
+            }
+        }
+    }
+}
+
+/// Replaces by `REDACTED` the values at, or nested under, `secrets`.
+pub(crate) fn redact(table: &mut Table, secrets: &[String]) {
+    for secret in secrets {
+        let path: Vec<&str> = secret.split('.').collect();
+        let (last, parents) = match path.split_last() {
+            Some(split) => split,
+            None => continue,
+        };
+
+        let mut current = Some(&mut *table);
+        for key in parents {
+            current = match current.and_then(|t| t.get_mut(*key)) {
+                Some(Value::Table(inner)) => Some(inner),
+                _ => None,
+            };
+        }
+
+        if let Some(value) = current.and_then(|t| t.get_mut(*last)) {
+            *value = Value::String(REDACTED.into());
+        }
+    }
+}
+
+/// Redacts the values of `table` held by `Secret` fields, as described by
+/// `kind`.
+pub(crate) fn redact_kind(table: &mut Table, kind: &Kind) {
+    fn walk(value: &mut Value, kind: &Kind) {
+        match (kind, value) {
+            (Kind::Secret(_), value) => *value = Value::String(REDACTED.into()),
+            (Kind::Option(inner), value) => walk(value, inner),
+            (Kind::Struct(fields), Value::Table(table)) => {
+                for field in fields {
+                    if let Some(value) = table.get_mut(field.name) {
+                        walk(value, &field.kind);
+                    }
+                }
+            }
+            (Kind::Map(inner), Value::Table(table)) => {
+                table.iter_mut().for_each(|(_, value)| walk(value, inner))
+            }
+            (Kind::Seq(inner), Value::Array(array)) => {
+                array.iter_mut().for_each(|value| walk(value, inner))
+            }
+            _ => {}
+        }
+    }
+
+    let mut value = Value::Table(std::mem::take(table));
+    walk(&mut value, kind);
+    if let Value::Table(redacted) = value {
+        *table = redacted;
+    }
+}
+
+/// Writes `table` in the given format, annotating every value with its origin.
+pub(crate) fn annotate(
+    table: &Table,
+    sources: &Sources,
+    format: Format,
+) -> Result<String, crate::error::Error> {
+    match format {
+        Format::Toml => {
+            let mut out = String::new();
+            write_toml(&mut out, &mut vec![], table, sources);
+            Ok(out)
+        }
+        Format::Json => {
+            let tree = annotated_tree(&mut vec![], table, sources);
+            serde_json::to_string_pretty(&tree).map_err(|e| e.to_string().into())
+        }
+        Format::Yaml => {
+            let tree = annotated_tree(&mut vec![], table, sources);
+            serde_yaml::to_string(&tree).map_err(|e| e.to_string().into())
+        }
+    }
+}
+
+/// Builds a tree where every value is replaced by a
+/// `{ value = .., source = .. }` table.
+fn annotated_tree(path: &mut Vec<String>, table: &Table, sources: &Sources) -> Table {
+    let mut tree = Table::new();
+
+    for (key, value) in table {
+        path.push(key.clone());
+        let annotated = match value {
+            Value::Table(inner) => Value::Table(annotated_tree(path, inner, sources)),
+            value => {
+                let mut leaf = Table::new();
+                leaf.insert("value".into(), value.clone());
+                leaf.insert(
+                    "source".into(),
+                    Value::String(sources.get(&path.join(".")).to_string()),
+                );
+                Value::Table(leaf)
+            }
+        };
+        tree.insert(key.clone(), annotated);
+        path.pop();
+    }
+
+    tree
+}
+
+/// Writes `table` as a TOML document, with the origin of every value
+/// as a trailing comment.
+fn write_toml(out: &mut String, path: &mut Vec<String>, table: &Table, sources: &Sources) {
+    let (tables, values): (Vec<_>, Vec<_>) = table.iter().partition(|(_, v)| v.is_table());
+
+    if !values.is_empty() && !path.is_empty() {
+        let header: Vec<String> = path.iter().map(|k| toml_key(k)).collect();
+        out.push_str(&format!("\n[{}]\n", header.join(".")));
+    }
+
+    for (key, value) in values {
+        path.push(key.clone());
+        out.push_str(&format!(
+            "{} = {} # {}\n",
+            toml_key(key),
+            value,
+            sources.get(&path.join("."))
+        ));
+        path.pop();
+    }
+
+    for (key, value) in tables {
+        if let Value::Table(inner) = value {
+            path.push(key.clone());
+            write_toml(out, path, inner, sources);
+            path.pop();
+        }
+    }
+}
+
+/// Quotes `key` unless it is a bare TOML key.
+fn toml_key(key: &str) -> String {
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+
+    if bare {
+        key.to_string()
+    } else {
+        Value::String(key.to_string()).to_string()
+    }
+}
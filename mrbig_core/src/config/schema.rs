@@ -0,0 +1,529 @@
+//! Description of the configuration types.
+//!
+//! The shape of a type implementing `serde::Deserialize` is discovered
+//! by deserializing it from a probe, which records the fields and the
+//! kinds of values asked for instead of providing real ones. This is
+//! how the user defined parameters, which only implement
+//! `Deserialize`, can be listed in the usage text. The probe stops
+//! looking into the values nested too deep, such as the ones of recursive
+//! types, whose kind is then unknown.
+//!
+//! The configuration can also be described as a JSON Schema, to check
+//! config files before deploying them: `Mr. Big`'s parameters carry their
+//! doc comments and defaults, and so do the user defined ones when they
+//! implement `schemars::JsonSchema`.
+use schemars::gen::SchemaSettings;
+use schemars::JsonSchema;
+use serde::de::{self, DeserializeSeed, IntoDeserializer, Visitor};
+use serde_json::{json, Map, Value};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Kind of a configuration value.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Kind {
+    Bool,
+    Integer,
+    Float,
+    String,
+    /// Optional value.
+    Option(Box<Kind>),
+    /// Sequence of values of the same kind.
+    Seq(Box<Kind>),
+    /// Map with string keys.
+    Map(Box<Kind>),
+    /// Struct with named fields.
+    Struct(Vec<Field>),
+    /// One of the given variants.
+    Enum(Vec<&'static str>),
+    /// Value held by a `Secret`, redacted when printed.
+    Secret(Box<Kind>),
+    /// Value of unknown kind, such as a type deserialized by hand.
+    #[default]
+    Any,
+}
+
+/// Named field of a struct.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Field {
+    pub name: &'static str,
+    pub kind: Kind,
+}
+
+impl Kind {
+    /// Short description of the kind, as shown in the usage text.
+    pub fn hint(&self) -> String {
+        match self {
+            Kind::Bool => "bool".into(),
+            Kind::Integer => "integer".into(),
+            Kind::Float => "float".into(),
+            Kind::String => "string".into(),
+            Kind::Option(inner) | Kind::Secret(inner) => inner.hint(),
+            Kind::Seq(inner) => format!("[{}]", inner.hint()),
+            Kind::Map(inner) => format!("{{key = {}}}", inner.hint()),
+            Kind::Struct(_) => "table".into(),
+            Kind::Enum(variants) => variants.join("|"),
+            Kind::Any => "value".into(),
+        }
+    }
+
+    /// Returns the kind of the value at the dotted `key`, looking into
+    /// nested structs (optional or not). Any key nested in a value of
+    /// unknown kind is of unknown kind too.
+    pub fn get(&self, key: &str) -> Option<&Kind> {
+        let mut kind = self;
+        for segment in key.split('.') {
+            kind = match kind.unwrap_option() {
+                Kind::Struct(fields) => &fields.iter().find(|f| f.name == segment)?.kind,
+                Kind::Map(inner) => inner,
+                Kind::Any => return Some(kind),
+                _ => return None,
+            };
+        }
+        Some(kind)
+    }
+
+    /// Lists the dotted keys of the values held by nested structs,
+    /// along with their kinds.
+    pub fn leaves(&self) -> Vec<(String, &Kind)> {
+        fn walk<'a>(
+            kind: &'a Kind,
+            path: &mut Vec<&'static str>,
+            out: &mut Vec<(String, &'a Kind)>,
+        ) {
+            match kind.unwrap_option() {
+                Kind::Struct(fields) if !fields.is_empty() || path.is_empty() => {
+                    for field in fields {
+                        path.push(field.name);
+                        walk(&field.kind, path, out);
+                        path.pop();
+                    }
+                }
+                _ => out.push((path.join("."), kind)),
+            }
+        }
+
+        let mut out = vec![];
+        walk(self, &mut vec![], &mut out);
+        out
+    }
+
+    /// JSON Schema of the values of this kind. Struct fields are not
+    /// listed as required, since whether they have a default is unknown.
+    pub fn json_schema(&self) -> Value {
+        match self {
+            Kind::Bool => json!({ "type": "boolean" }),
+            Kind::Integer => json!({ "type": "integer" }),
+            Kind::Float => json!({ "type": "number" }),
+            Kind::String => json!({ "type": "string" }),
+            Kind::Option(inner) | Kind::Secret(inner) => inner.json_schema(),
+            Kind::Seq(inner) => json!({ "type": "array", "items": inner.json_schema() }),
+            Kind::Map(inner) => json!({
+                "type": "object",
+                "additionalProperties": inner.json_schema(),
+            }),
+            Kind::Struct(fields) => {
+                let properties: Map<String, Value> = fields
+                    .iter()
+                    .map(|f| (f.name.to_string(), f.kind.json_schema()))
+                    .collect();
+                json!({ "type": "object", "properties": properties })
+            }
+            Kind::Enum(variants) => json!({ "enum": variants }),
+            Kind::Any => json!({}),
+        }
+    }
+
+    /// Kind of the value held, looking through options and secrets.
+    pub(crate) fn unwrap_option(&self) -> &Kind {
+        match self {
+            Kind::Option(inner) | Kind::Secret(inner) => inner.unwrap_option(),
+            kind => kind,
+        }
+    }
+}
+
+/// Describes the shape of type `T`.
+pub fn describe<'de, T>() -> Kind
+where
+    T: de::Deserialize<'de>,
+{
+    let slot = Slot::default();
+    // the probe may fail on types it cannot drive to the end (such as
+    // types validating their values), what was recorded is kept
+    let _ = T::deserialize(Probe::new(slot.clone(), 0));
+    slot.take()
+}
+
+/// JSON Schema of type `T`, carrying the doc comments and defaults
+/// of its fields. Nested types are inlined.
+pub fn json_schema_of<T: JsonSchema>() -> Value {
+    let settings = SchemaSettings::draft07().with(|s| s.inline_subschemas = true);
+    let schema = settings.into_generator().into_root_schema_for::<T>();
+    serde_json::to_value(schema).unwrap_or_default()
+}
+
+/// JSON Schema of the whole configuration: the user defined parameters,
+/// described by the `extra` schema, along with `Mr. Big`'s own parameters
+/// under `service`.
+pub fn json_schema(extra: Value) -> Value {
+    // anything but a table of parameters is replaced by an open table
+    let mut root = match extra {
+        Value::Object(root) if root.get("type") == Some(&json!("object")) => root,
+        _ => Map::new(),
+    };
+
+    let mut service = json_schema_of::<super::Service>();
+    if let Some(service) = service.as_object_mut() {
+        service.remove("$schema");
+    }
+
+    root.insert("$schema".into(), json!(DRAFT_07));
+    root.insert("type".into(), json!("object"));
+    if let Value::Object(properties) = root.entry("properties").or_insert_with(|| json!({})) {
+        properties.insert("service".into(), service);
+    }
+
+    Value::Object(root)
+}
+
+const DRAFT_07: &str = "http://json-schema.org/draft-07/schema#";
+
+type Slot = Rc<RefCell<Kind>>;
+type Error = de::value::Error;
+
+/// Nesting of the values the probe looks into, past which the options,
+/// sequences and maps are left empty, of unknown kind. This bounds the
+/// description of recursive types.
+const MAX_DEPTH: usize = 12;
+
+/// A deserializer recording the kind of value asked for in its slot.
+struct Probe {
+    slot: Slot,
+    depth: usize,
+}
+
+impl Probe {
+    fn new(slot: Slot, depth: usize) -> Self {
+        Probe { slot, depth }
+    }
+
+    fn record(&self, kind: Kind) {
+        *self.slot.borrow_mut() = kind;
+    }
+
+    /// Depth of the values nested in this one, if not too deep, in which
+    /// case this one is of unknown kind.
+    fn nested(&self) -> Option<usize> {
+        match self.depth < MAX_DEPTH {
+            true => Some(self.depth + 1),
+            false => {
+                self.record(Kind::Any);
+                None
+            }
+        }
+    }
+}
+
+macro_rules! probe_scalar {
+    ($method:ident, $kind:expr, $visit:ident, $value:expr) => {
+        fn $method<V>(self, visitor: V) -> Result<V::Value, Error>
+        where
+            V: Visitor<'de>,
+        {
+            self.record($kind);
+            visitor.$visit($value)
+        }
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Probe {
+    type Error = Error;
+
+    probe_scalar!(deserialize_any, Kind::Any, visit_u64, 0);
+    probe_scalar!(deserialize_bool, Kind::Bool, visit_bool, false);
+    probe_scalar!(deserialize_i8, Kind::Integer, visit_i64, 0);
+    probe_scalar!(deserialize_i16, Kind::Integer, visit_i64, 0);
+    probe_scalar!(deserialize_i32, Kind::Integer, visit_i64, 0);
+    probe_scalar!(deserialize_i64, Kind::Integer, visit_i64, 0);
+    probe_scalar!(deserialize_u8, Kind::Integer, visit_u64, 0);
+    probe_scalar!(deserialize_u16, Kind::Integer, visit_u64, 0);
+    probe_scalar!(deserialize_u32, Kind::Integer, visit_u64, 0);
+    probe_scalar!(deserialize_u64, Kind::Integer, visit_u64, 0);
+    probe_scalar!(deserialize_f32, Kind::Float, visit_f64, 0.0);
+    probe_scalar!(deserialize_f64, Kind::Float, visit_f64, 0.0);
+    probe_scalar!(deserialize_char, Kind::String, visit_char, 'a');
+    probe_scalar!(deserialize_str, Kind::String, visit_str, "");
+    probe_scalar!(deserialize_string, Kind::String, visit_str, "");
+    probe_scalar!(deserialize_bytes, Kind::String, visit_bytes, &[]);
+    probe_scalar!(deserialize_byte_buf, Kind::String, visit_bytes, &[]);
+    probe_scalar!(deserialize_identifier, Kind::String, visit_str, "");
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        let depth = match self.nested() {
+            Some(depth) => depth,
+            None => return visitor.visit_none(),
+        };
+        let inner = Slot::default();
+        let result = visitor.visit_some(Probe::new(inner.clone(), depth));
+        self.record(Kind::Option(Box::new(inner.take())));
+        result
+    }
+
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        if name != super::secret::SECRET {
+            return visitor.visit_newtype_struct(self);
+        }
+
+        let inner = Slot::default();
+        let result = visitor.visit_newtype_struct(Probe::new(inner.clone(), self.depth));
+        self.record(Kind::Secret(Box::new(inner.take())));
+        result
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        let depth = match self.nested() {
+            Some(depth) => depth,
+            None => return visitor.visit_seq(ProbeSeq(vec![], self.depth)),
+        };
+        let inner = Slot::default();
+        let result = visitor.visit_seq(ProbeSeq(vec![inner.clone()], depth));
+        self.record(Kind::Seq(Box::new(inner.take())));
+        result
+    }
+
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        let depth = match self.nested() {
+            Some(depth) => depth,
+            None => return visitor.visit_seq(ProbeSeq(vec![], self.depth)),
+        };
+        let slots: Vec<Slot> = (0..len).map(|_| Slot::default()).collect();
+        let result = visitor.visit_seq(ProbeSeq(slots.iter().rev().cloned().collect(), depth));
+        let first = slots.first().map(|s| s.take()).unwrap_or_default();
+        self.record(Kind::Seq(Box::new(first)));
+        result
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        let depth = match self.nested() {
+            Some(depth) => depth,
+            None => return visitor.visit_map(ProbeMap::empty(self.depth)),
+        };
+        let inner = Slot::default();
+        let result = visitor.visit_map(ProbeMap {
+            keys: vec![""],
+            slots: vec![inner.clone()],
+            idx: 0,
+            depth,
+        });
+        self.record(Kind::Map(Box::new(inner.take())));
+        result
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        // a recursive type goes through options, sequences or maps, which
+        // stop deep enough as they can be left empty
+        let depth = self.depth + 1;
+        let slots: Vec<Slot> = fields.iter().map(|_| Slot::default()).collect();
+        let result = visitor.visit_map(ProbeMap {
+            keys: fields.to_vec(),
+            slots: slots.clone(),
+            idx: 0,
+            depth,
+        });
+        self.record(Kind::Struct(
+            fields
+                .iter()
+                .zip(slots)
+                .map(|(name, slot)| Field {
+                    name,
+                    kind: slot.take(),
+                })
+                .collect(),
+        ));
+        result
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.record(Kind::Enum(variants.to_vec()));
+        let variant = variants.first().copied().unwrap_or_default();
+        visitor.visit_enum(ProbeEnum(variant, self.depth))
+    }
+}
+
+/// Sequence of probes, popped from the back, at the given depth.
+struct ProbeSeq(Vec<Slot>, usize);
+
+impl<'de> de::SeqAccess<'de> for ProbeSeq {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        match self.0.pop() {
+            Some(slot) => seed.deserialize(Probe::new(slot, self.1)).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Map of probes, one per key.
+struct ProbeMap {
+    keys: Vec<&'static str>,
+    slots: Vec<Slot>,
+    idx: usize,
+    depth: usize,
+}
+
+impl ProbeMap {
+    fn empty(depth: usize) -> Self {
+        ProbeMap {
+            keys: vec![],
+            slots: vec![],
+            idx: 0,
+            depth,
+        }
+    }
+}
+
+impl<'de> de::MapAccess<'de> for ProbeMap {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.keys.get(self.idx) {
+            Some(key) => seed.deserialize((*key).into_deserializer()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let slot = self.slots[self.idx].clone();
+        self.idx += 1;
+        seed.deserialize(Probe::new(slot, self.depth))
+    }
+}
+
+/// Enum probe, picking the first variant, at the given depth.
+struct ProbeEnum(&'static str, usize);
+
+impl<'de> de::EnumAccess<'de> for ProbeEnum {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self), Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(self.0.into_deserializer())?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for ProbeEnum {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        seed.deserialize(Probe::new(Slot::default(), self.1))
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_tuple(Probe::new(Slot::default(), self.1), len, visitor)
+    }
+
+    fn struct_variant<V>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        let probe = Probe::new(Slot::default(), self.1);
+        de::Deserializer::deserialize_struct(probe, "", fields, visitor)
+    }
+}
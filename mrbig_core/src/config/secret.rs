@@ -0,0 +1,248 @@
+//! Secret configuration values.
+//!
+//! Any string value may reference a file or an environment variable,
+//! as in `"${file:/run/secrets/db}"` or `"postgres://app:${env:DB_PASS}@db"`.
+//! References are resolved once every source is merged, and the values
+//! holding them are redacted when the configuration is printed. Write
+//! `$${` for a literal `${`.
+//!
+//! The `Secret` wrapper keeps a value out of the logs: it is printed
+//! as `<redacted>` by `Debug` and serialized as such. The config dump
+//! redacts the values set for `Secret` fields as well, whether the user
+//! defined parameters are printed as set or serialized.
+use super::provenance::REDACTED;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+use serde::de::{self, IntoDeserializer, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::marker::PhantomData;
+use toml::value::{Table, Value};
+
+/// A value which is never printed, such as a password.
+///
+/// ```
+/// use mrbig_core::config::Secret;
+///
+/// #[derive(serde_derive::Deserialize)]
+/// struct Db {
+///     url: String,
+///     password: Secret<String>,
+/// }
+///
+/// let db: Db = toml::from_str("url = \"postgres://db\"\npassword = \"s3cret\"").unwrap();
+/// assert_eq!(db.password.expose(), "s3cret");
+/// assert_eq!(format!("{:?}", db.password), "<redacted>");
+/// ```
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Secret<T>(T);
+
+impl<T> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Secret(value)
+    }
+
+    /// Returns the secret value, to be used but not printed.
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+
+    /// Returns the secret value, consuming the wrapper.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Secret(value)
+    }
+}
+
+impl<T> std::fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+/// Name of the newtype struct a `Secret` deserializes as, which tells
+/// the description of the configuration types which values are secret.
+pub(crate) const SECRET: &str = "$mrbig::Secret";
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Secret<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_newtype_struct(SECRET, SecretVisitor(PhantomData))
+    }
+}
+
+/// Deserializes the value of a `Secret`, from formats handing newtype
+/// structs to the visitor as well as from the ones handing their content.
+struct SecretVisitor<T>(PhantomData<T>);
+
+macro_rules! visit_value {
+    ($method:ident, $ty:ty) => {
+        fn $method<E: de::Error>(self, v: $ty) -> Result<Self::Value, E> {
+            T::deserialize(v.into_deserializer()).map(Secret)
+        }
+    };
+}
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for SecretVisitor<T> {
+    type Value = Secret<T>;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a secret value")
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        T::deserialize(deserializer).map(Secret)
+    }
+
+    visit_value!(visit_bool, bool);
+    visit_value!(visit_i64, i64);
+    visit_value!(visit_u64, u64);
+    visit_value!(visit_f64, f64);
+    visit_value!(visit_str, &str);
+    visit_value!(visit_string, String);
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+        T::deserialize(SeqAccessDeserializer::new(seq)).map(Secret)
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        T::deserialize(MapAccessDeserializer::new(map)).map(Secret)
+    }
+}
+
+impl<T> Serialize for Secret<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for Secret<T> {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        T::schema_name()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        T::json_schema(gen)
+    }
+}
+
+/// Resolves the references found in the string values of `table`,
+/// reading variables from `vars`. The dotted keys of the values holding
+/// references (or of the arrays holding them) are added to `resolved`.
+pub(crate) fn resolve(
+    table: &mut Table,
+    vars: &[(String, String)],
+    resolved: &mut Vec<String>,
+) -> Result<(), crate::error::Error> {
+    for (key, value) in table.iter_mut() {
+        resolve_value(value, &mut vec![key.clone()], vars, resolved)?;
+    }
+    Ok(())
+}
+
+fn resolve_value(
+    value: &mut Value,
+    path: &mut Vec<String>,
+    vars: &[(String, String)],
+    resolved: &mut Vec<String>,
+) -> Result<(), crate::error::Error> {
+    match value {
+        Value::String(s) if s.contains("${") => {
+            let (interpolated, references) =
+                interpolate(s, vars).map_err(|e| format!("`{}` {}", path.join("."), e))?;
+            *s = interpolated;
+            if references {
+                resolved.push(path.join("."));
+            }
+        }
+        Value::Table(inner) => {
+            for (key, value) in inner.iter_mut() {
+                path.push(key.clone());
+                resolve_value(value, path, vars, resolved)?;
+                path.pop();
+            }
+        }
+        Value::Array(items) => {
+            // items have no key of their own, the whole array is recorded
+            let before = resolved.len();
+            for item in items {
+                resolve_value(item, path, vars, resolved)?;
+            }
+            if resolved.len() > before {
+                resolved.truncate(before);
+                resolved.push(path.join("."));
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Replaces the references in `s` by their values, also returning
+/// whether there were any.
+fn interpolate(s: &str, vars: &[(String, String)]) -> Result<(String, bool), String> {
+    let mut out = String::new();
+    let mut rest = s;
+    let mut references = false;
+
+    while let Some(idx) = rest.find("${") {
+        let after = &rest[idx + 2..];
+
+        if rest[..idx].ends_with('$') {
+            // escaped
+            out.push_str(&rest[..idx - 1]);
+            out.push_str("${");
+            rest = after;
+            continue;
+        }
+        out.push_str(&rest[..idx]);
+
+        if !after.starts_with("file:") && !after.starts_with("env:") {
+            // not a reference
+            out.push_str("${");
+            rest = after;
+            continue;
+        }
+
+        let end = after
+            .find('}')
+            .ok_or_else(|| format!("has an unterminated reference in `{}`", s))?;
+        let reference = &after[..end];
+        rest = &after[end + 1..];
+        references = true;
+
+        if let Some(path) = reference.strip_prefix("file:") {
+            let content = std::fs::read_to_string(path)
+                .map_err(|e| format!("references `{}`, which cannot be read: {}", path, e))?;
+            out.push_str(content.trim_end_matches(&['\n', '\r'][..]));
+        } else if let Some(name) = reference.strip_prefix("env:") {
+            let value = vars
+                .iter()
+                .find(|(var, _)| var == name)
+                .map(|(_, value)| value)
+                .ok_or_else(|| format!("references unset environment variable `{}`", name))?;
+            out.push_str(value);
+        }
+    }
+    out.push_str(rest);
+
+    Ok((out, references))
+}
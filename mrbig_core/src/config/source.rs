@@ -0,0 +1,370 @@
+//! Pluggable configuration sources.
+//!
+//! Besides the config file given by `--config`, the environment variables
+//! and the command line, a service can read its configuration from other
+//! sources: extra files, a directory of mounted files holding one value
+//! each (as Kubernetes mounts secrets and config maps), or an HTTP
+//! endpoint. Sources are loaded in order, each one overriding the values
+//! of the ones before it.
+use super::env;
+use super::provenance::{Source, Sources};
+use super::value::{insert_path, merge};
+use super::Format;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use toml::value::{Table, Value};
+
+/// A source of configuration values.
+pub trait ConfigSource: std::fmt::Debug + Send + Sync {
+    /// Reads the configuration values into `values`, along with their origin.
+    fn load(&self, values: &mut Values) -> Result<(), crate::error::Error>;
+}
+
+/// The configuration values read so far, which sources override.
+pub struct Values<'a> {
+    table: &'a mut Table,
+    sources: &'a mut Sources,
+}
+
+impl<'a> Values<'a> {
+    pub(crate) fn new(table: &'a mut Table, sources: &'a mut Sources) -> Self {
+        Values { table, sources }
+    }
+
+    /// Sets the value at the dotted `key`, for instance `db.url`.
+    pub fn set(&mut self, key: &str, value: Value, source: Source) {
+        let path: Vec<String> = key.split('.').map(String::from).collect();
+        self.set_path(&path, value, source);
+    }
+
+    /// Sets the value at `path`, merging tables into the existing ones.
+    pub fn set_path(&mut self, path: &[String], value: Value, source: Source) {
+        match &value {
+            Value::Table(inner) => self
+                .sources
+                .record_table(&mut path.to_vec(), inner, &source),
+            _ => self.sources.record(path, source),
+        }
+
+        let mut overlay = Table::new();
+        insert_path(&mut overlay, path, value);
+        merge(self.table, overlay);
+    }
+
+    /// Deep merges `table` onto the values.
+    pub fn merge(&mut self, table: Table, source: Source) {
+        self.sources.record_table(&mut vec![], &table, &source);
+        merge(self.table, table);
+    }
+}
+
+/// Config file, in any of the supported formats.
+#[derive(Clone, Debug)]
+pub struct FileSource {
+    path: PathBuf,
+    format: Option<Format>,
+    required: bool,
+}
+
+impl FileSource {
+    /// File at `path`, whose format is guessed from the extension.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        FileSource {
+            path: path.into(),
+            format: None,
+            required: true,
+        }
+    }
+
+    /// Sets the format of the file.
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// Skips the file when it does not exist, instead of failing.
+    pub fn optional(mut self) -> Self {
+        self.required = false;
+        self
+    }
+}
+
+impl ConfigSource for FileSource {
+    fn load(&self, values: &mut Values) -> Result<(), crate::error::Error> {
+        if !self.required && !self.path.exists() {
+            return Ok(());
+        }
+
+        let format = self.format.unwrap_or_else(|| Format::from_path(&self.path));
+        let table = format.parse(&std::fs::read(&self.path)?)?;
+        values.merge(table, Source::File(self.path.clone()));
+        Ok(())
+    }
+}
+
+/// Directory holding one file per value, named after the value's dotted
+/// key, such as `db.password`. Hidden files are skipped, as are the
+/// entries Kubernetes adds to mounted volumes.
+#[derive(Clone, Debug)]
+pub struct DirectorySource {
+    path: PathBuf,
+    prefix: Option<String>,
+}
+
+impl DirectorySource {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        DirectorySource {
+            path: path.into(),
+            prefix: None,
+        }
+    }
+
+    /// Nests the values under the dotted `prefix`, so the file `password`
+    /// sets `db.password` with prefix `db`.
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+}
+
+impl ConfigSource for DirectorySource {
+    fn load(&self, values: &mut Values) -> Result<(), crate::error::Error> {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(&self.path)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| !is_hidden(path) && path.is_file())
+            .collect();
+        files.sort();
+
+        for file in files {
+            let name = file.file_name().unwrap_or_default().to_string_lossy();
+            let key = match &self.prefix {
+                Some(prefix) => format!("{}.{}", prefix, name),
+                None => name.into_owned(),
+            };
+
+            // values are kept as strings and coerced when deserialized
+            let content = std::fs::read_to_string(&file)?;
+            let value = content.trim_end_matches(&['\n', '\r'][..]);
+            values.set(
+                &key,
+                Value::String(value.into()),
+                Source::File(file.clone()),
+            );
+        }
+
+        Ok(())
+    }
+}
+
+fn is_hidden(path: &Path) -> bool {
+    match path.file_name() {
+        Some(name) => name.to_string_lossy().starts_with('.'),
+        None => true,
+    }
+}
+
+/// Environment variables named `MRBIG_SERVICE__<PATH>` and
+/// `MRBIG_EXTRA__<PATH>`, as described in the `env` module.
+#[derive(Clone, Debug, Default)]
+pub struct EnvSource {
+    vars: Option<Vec<(String, String)>>,
+}
+
+impl EnvSource {
+    /// Variables of the process, read when loaded.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The given variables instead of the process ones.
+    pub fn from_vars<I>(vars: I) -> Self
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        EnvSource {
+            vars: Some(vars.into_iter().collect()),
+        }
+    }
+}
+
+impl ConfigSource for EnvSource {
+    fn load(&self, values: &mut Values) -> Result<(), crate::error::Error> {
+        let vars = match &self.vars {
+            Some(vars) => vars.clone(),
+            None => std::env::vars().collect(),
+        };
+
+        for (name, value) in vars {
+            if let Some(path) = env::path_of(&name) {
+                values.set_path(&path?, Value::String(value), Source::Env(name));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Document served over plain HTTP, in any of the supported formats.
+///
+/// The format is given, or picked from the `Content-Type` of the
+/// response, or guessed from the extension of the URL path.
+#[derive(Clone, Debug)]
+pub struct HttpSource {
+    url: String,
+    format: Option<Format>,
+    timeout: Duration,
+    max_size: usize,
+}
+
+impl HttpSource {
+    /// Document at `url`, such as `http://config:8500/v1/micro.json`.
+    pub fn new(url: &str) -> Self {
+        HttpSource {
+            url: url.into(),
+            format: None,
+            timeout: Duration::from_secs(5),
+            max_size: 1 << 20,
+        }
+    }
+
+    /// Sets the format of the document.
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// Sets the time allowed to connect, send the request and read the
+    /// whole response, 5 seconds by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the largest response accepted, headers included, in bytes,
+    /// 1 MiB by default.
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Performs the GET request, returning the content type and the body.
+    fn get(&self) -> Result<(Option<String>, Vec<u8>), String> {
+        let fail = |e: &dyn std::fmt::Display| format!("http source `{}`: {}", self.url, e);
+        let deadline = Instant::now() + self.timeout;
+        // the time left before the deadline, which every step shares
+        let remaining = || {
+            deadline
+                .checked_duration_since(Instant::now())
+                .filter(|left| *left > Duration::from_millis(0))
+                .ok_or_else(|| fail(&"timed out"))
+        };
+
+        let rest = self
+            .url
+            .strip_prefix("http://")
+            .ok_or_else(|| fail(&"only http:// URLs are supported"))?;
+        let (authority, path) = match rest.find('/') {
+            Some(idx) => (&rest[..idx], &rest[idx..]),
+            None => (rest, "/"),
+        };
+        let address = if authority.contains(':') {
+            authority.to_string()
+        } else {
+            format!("{}:80", authority)
+        };
+
+        let addr = address
+            .to_socket_addrs()
+            .map_err(|e| fail(&e))?
+            .next()
+            .ok_or_else(|| fail(&"no address to connect to"))?;
+        let mut stream = TcpStream::connect_timeout(&addr, remaining()?).map_err(|e| fail(&e))?;
+        stream
+            .set_write_timeout(Some(remaining()?))
+            .map_err(|e| fail(&e))?;
+
+        // HTTP/1.0 keeps the response plain: no chunks, closed when done
+        write!(
+            stream,
+            "GET {} HTTP/1.0\r\nHost: {}\r\nAccept: application/json, application/yaml, \
+             application/toml\r\nConnection: close\r\n\r\n",
+            path, authority
+        )
+        .map_err(|e| fail(&e))?;
+
+        let mut response = vec![];
+        let mut buffer = [0; 8192];
+        loop {
+            stream
+                .set_read_timeout(Some(remaining()?))
+                .map_err(|e| fail(&e))?;
+            let read = match stream.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                // as the read timeout is reported, depending on the platform
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    return Err(fail(&"timed out"))
+                }
+                Err(e) => return Err(fail(&e)),
+            };
+            if response.len() + read > self.max_size {
+                return Err(fail(&format!(
+                    "response larger than {} bytes",
+                    self.max_size
+                )));
+            }
+            response.extend_from_slice(&buffer[..read]);
+        }
+
+        let split = response
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .ok_or_else(|| fail(&"malformed response"))?;
+        let head = String::from_utf8_lossy(&response[..split]);
+        let mut lines = head.lines();
+
+        let status = lines.next().unwrap_or_default();
+        if status.split_whitespace().nth(1) != Some("200") {
+            return Err(fail(&format!("unexpected response `{}`", status)));
+        }
+
+        let content_type = lines.find_map(|line| {
+            let (name, value) = line.split_at(line.find(':')?);
+            if name.eq_ignore_ascii_case("content-type") {
+                Some(value[1..].trim().to_ascii_lowercase())
+            } else {
+                None
+            }
+        });
+
+        Ok((content_type, response[split + 4..].to_vec()))
+    }
+}
+
+impl ConfigSource for HttpSource {
+    fn load(&self, values: &mut Values) -> Result<(), crate::error::Error> {
+        let (content_type, body) = self.get()?;
+
+        let format =
+            self.format
+                .unwrap_or_else(|| match content_type.as_deref().unwrap_or_default() {
+                    t if t.contains("json") => Format::Json,
+                    t if t.contains("yaml") => Format::Yaml,
+                    t if t.contains("toml") => Format::Toml,
+                    _ => {
+                        Format::from_path(Path::new(self.url.split('?').next().unwrap_or_default()))
+                    }
+                });
+
+        values.merge(format.parse(&body)?, Source::Http(self.url.clone()));
+        Ok(())
+    }
+}
@@ -0,0 +1,370 @@
+//! Human friendly durations and byte sizes.
+//!
+//! Durations are written as a sequence of integers followed by a unit,
+//! such as `"2s"`, `"500ms"` or `"1m30s"` (units are `ns`, `us`, `ms`,
+//! `s`, `m`, `h` and `d`). A bare number is a number of seconds, and the
+//! `{ secs = 2, nanos = 0 }` table of `std::time::Duration` is accepted
+//! too. Byte sizes are written as an integer followed by an optional
+//! unit, such as `"512"`, `"64KB"` or `"4MiB"` (`B`, `KB`, `MB`, `GB`,
+//! `TB` are powers of 1000, `KiB`, `MiB`, `GiB`, `TiB` powers of 1024).
+//!
+//! Use the modules below with serde's `with` attribute on your own
+//! configuration fields, and `DurationSchema` or `SizeSchema` with
+//! schemars' `with` attribute to describe them in JSON Schemas:
+//!
+//! ```
+//! #[derive(serde_derive::Deserialize)]
+//! struct Extra {
+//!     #[serde(with = "mrbig_core::config::units::duration")]
+//!     poll_interval: std::time::Duration,
+//!     #[serde(default, with = "mrbig_core::config::units::option_size")]
+//!     max_message_size: Option<u64>,
+//! }
+//! ```
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, Schema, SchemaObject};
+use schemars::JsonSchema;
+use serde::de::{self, Visitor};
+use std::convert::TryFrom;
+use std::time::Duration;
+
+const DURATION_UNITS: &[(&str, u64)] = &[
+    ("d", 86_400_000_000_000),
+    ("h", 3_600_000_000_000),
+    ("m", 60_000_000_000),
+    ("s", 1_000_000_000),
+    ("ms", 1_000_000),
+    ("us", 1_000),
+    ("µs", 1_000),
+    ("ns", 1),
+];
+
+const SIZE_UNITS: &[(&str, u64)] = &[
+    ("b", 1),
+    ("kb", 1_000),
+    ("mb", 1_000_000),
+    ("gb", 1_000_000_000),
+    ("tb", 1_000_000_000_000),
+    ("kib", 1 << 10),
+    ("mib", 1 << 20),
+    ("gib", 1 << 30),
+    ("tib", 1 << 40),
+];
+
+/// Splits `s` into (number, unit) pairs.
+fn components(s: &str) -> Option<Vec<(u64, &str)>> {
+    let mut rest = s.trim();
+    let mut parts = vec![];
+
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        if digits == 0 {
+            return None;
+        }
+        let number = rest[..digits].parse().ok()?;
+        rest = rest[digits..].trim_start();
+
+        let unit = rest
+            .find(|c: char| c.is_ascii_digit() || c.is_whitespace())
+            .unwrap_or(rest.len());
+        parts.push((number, &rest[..unit]));
+        rest = rest[unit..].trim_start();
+    }
+
+    Some(parts)
+}
+
+/// Parses a duration such as `"1m30s"`, or a number of seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration `{}`, expected for instance `1m30s`", s);
+
+    let parts = components(s)
+        .filter(|p| !p.is_empty())
+        .ok_or_else(invalid)?;
+
+    if let [(secs, "")] = parts[..] {
+        return Ok(Duration::from_secs(secs));
+    }
+
+    let mut nanos: u128 = 0;
+    for (number, unit) in parts {
+        let factor = DURATION_UNITS
+            .iter()
+            .find(|(name, _)| *name == unit)
+            .map(|(_, factor)| *factor)
+            .ok_or_else(invalid)?;
+        nanos += u128::from(number) * u128::from(factor);
+    }
+
+    let secs = u64::try_from(nanos / 1_000_000_000).map_err(|_| invalid())?;
+    Ok(Duration::new(secs, (nanos % 1_000_000_000) as u32))
+}
+
+/// Formats a duration the way `parse_duration` reads it, such as `"1m30s"`.
+pub fn format_duration(duration: &Duration) -> String {
+    let mut nanos = duration.as_nanos();
+    if nanos == 0 {
+        return "0s".into();
+    }
+
+    let mut out = String::new();
+    for (unit, factor) in DURATION_UNITS.iter().filter(|(unit, _)| *unit != "µs") {
+        let factor = u128::from(*factor);
+        if nanos >= factor {
+            out.push_str(&format!("{}{}", nanos / factor, unit));
+            nanos %= factor;
+        }
+    }
+    out
+}
+
+/// Parses a byte size such as `"4MiB"`, or a number of bytes.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let invalid = || format!("invalid size `{}`, expected for instance `4MiB`", s);
+
+    match components(s).as_deref() {
+        Some([(bytes, "")]) => Ok(*bytes),
+        Some([(number, unit)]) => {
+            let unit = unit.to_ascii_lowercase();
+            let factor = SIZE_UNITS
+                .iter()
+                .find(|(name, _)| *name == unit)
+                .map(|(_, factor)| *factor)
+                .ok_or_else(invalid)?;
+            number.checked_mul(factor).ok_or_else(invalid)
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Formats a byte size with the largest binary unit dividing it, such as `"4MiB"`.
+pub fn format_size(bytes: u64) -> String {
+    for (unit, shift) in &[("TiB", 40), ("GiB", 30), ("MiB", 20), ("KiB", 10)] {
+        if bytes != 0 && bytes.trailing_zeros() >= *shift {
+            return format!("{}{}", bytes >> shift, unit);
+        }
+    }
+    bytes.to_string()
+}
+
+struct DurationVisitor;
+
+impl<'de> Visitor<'de> for DurationVisitor {
+    type Value = Duration;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "a duration such as \"1m30s\", a number of seconds or a table"
+        )
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Duration, E> {
+        Ok(Duration::from_secs(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Duration, E> {
+        u64::try_from(v)
+            .map(Duration::from_secs)
+            .map_err(|_| E::invalid_value(de::Unexpected::Signed(v), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Duration, E> {
+        match parse_duration(v) {
+            Ok(duration) => Ok(duration),
+            // table given inline, as from environment variables
+            Err(e) => match super::value::parse_inline(v) {
+                Some(table @ toml::Value::Table(_)) => {
+                    serde::Deserialize::deserialize(super::value::Coerce(table)).map_err(E::custom)
+                }
+                _ => Err(E::custom(e)),
+            },
+        }
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<Duration, A::Error> {
+        serde::Deserialize::deserialize(de::value::MapAccessDeserializer::new(map))
+    }
+}
+
+struct SizeVisitor;
+
+impl<'de> Visitor<'de> for SizeVisitor {
+    type Value = u64;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "a size such as \"4MiB\" or a number of bytes")
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<u64, E> {
+        Ok(v)
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<u64, E> {
+        u64::try_from(v).map_err(|_| E::invalid_value(de::Unexpected::Signed(v), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<u64, E> {
+        parse_size(v).map_err(E::custom)
+    }
+}
+
+/// Stands for the durations read by `duration` in JSON Schemas, as in
+/// `#[schemars(with = "units::DurationSchema")]`.
+pub struct DurationSchema;
+
+impl JsonSchema for DurationSchema {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        "Duration".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        human_schema(vec![
+            InstanceType::String,
+            InstanceType::Integer,
+            InstanceType::Object,
+        ])
+    }
+}
+
+/// Stands for the byte sizes read by `size` in JSON Schemas, as in
+/// `#[schemars(with = "units::SizeSchema")]`.
+pub struct SizeSchema;
+
+impl JsonSchema for SizeSchema {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        "Size".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        human_schema(vec![InstanceType::String, InstanceType::Integer])
+    }
+}
+
+fn human_schema(types: Vec<InstanceType>) -> Schema {
+    SchemaObject {
+        instance_type: Some(types.into()),
+        ..Default::default()
+    }
+    .into()
+}
+
+/// Serde helpers for `std::time::Duration` fields.
+pub mod duration {
+    use std::time::Duration;
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(super::DurationVisitor)
+    }
+
+    pub fn serialize<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&super::format_duration(duration))
+    }
+}
+
+/// Serde helpers for `Option<std::time::Duration>` fields.
+pub mod option_duration {
+    use std::time::Duration;
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        super::duration::deserialize(deserializer).map(Some)
+    }
+
+    pub fn serialize<S>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match duration {
+            Some(duration) => super::duration::serialize(duration, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+}
+
+/// Serde helpers for byte size (`u64`) fields.
+pub mod size {
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<u64, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(super::SizeVisitor)
+    }
+
+    pub fn serialize<S>(bytes: &u64, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&super::format_size(*bytes))
+    }
+}
+
+/// Serde helpers for `Option<u64>` byte size fields.
+pub mod option_size {
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        super::size::deserialize(deserializer).map(Some)
+    }
+
+    pub fn serialize<S>(bytes: &Option<u64>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match bytes {
+            Some(bytes) => super::size::serialize(bytes, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(
+            parse_duration("1d 2h").unwrap(),
+            Duration::from_secs(93_600)
+        );
+        assert_eq!(parse_duration("250us").unwrap(), Duration::from_micros(250));
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("5 fortnights").is_err());
+        assert_eq!(
+            format_duration(&Duration::from_millis(90_500)),
+            "1m30s500ms"
+        );
+        assert_eq!(format_duration(&Duration::default()), "0s");
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("64KB").unwrap(), 64_000);
+        assert_eq!(parse_size("1gib").unwrap(), 1 << 30);
+        assert!(parse_size("4MiB 2KiB").is_err());
+        assert!(parse_size("99999999999TiB").is_err());
+        assert_eq!(format_size(4 << 20), "4MiB");
+        assert_eq!(format_size(1000), "1000");
+    }
+}
@@ -0,0 +1,264 @@
+//! Validation of the loaded configuration.
+//!
+//! Once loaded, the configuration goes through a validation pass which
+//! collects every invalid value along with its dotted key, so all the
+//! problems are reported at once instead of failing at bind time.
+use std::net::IpAddr;
+use std::path::Path;
+
+/// An invalid configuration value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationError {
+    /// Dotted path of the invalid value, for instance `service.port`.
+    pub key: String,
+    /// What is wrong with the value.
+    pub message: String,
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "`{}` {}", self.key, self.message)
+    }
+}
+
+/// Collects the validation errors of a configuration.
+#[derive(Debug, Default)]
+pub struct Validation {
+    errors: Vec<ValidationError>,
+}
+
+impl Validation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that the value at `key` is invalid.
+    pub fn error<M: Into<String>>(&mut self, key: &str, message: M) {
+        self.errors.push(ValidationError {
+            key: key.into(),
+            message: message.into(),
+        });
+    }
+
+    /// Records an error for `key` unless `valid` holds.
+    pub fn check<M: Into<String>>(&mut self, valid: bool, key: &str, message: M) {
+        if !valid {
+            self.error(key, message);
+        }
+    }
+
+    /// Checks that `port` is a port number a server can bind.
+    pub fn port(&mut self, key: &str, port: u16) {
+        self.check(port != 0, key, "must be a port between 1 and 65535");
+    }
+
+    /// Checks that `hostname` is an IP address or a DNS name, such as
+    /// `localhost` or `svc.internal`. Names are resolved when binding, not
+    /// here, where only their syntax is checked.
+    pub fn address(&mut self, key: &str, hostname: &str) {
+        if !is_hostname(hostname) {
+            self.error(
+                key,
+                format!("`{}` is not a valid address to bind to", hostname),
+            );
+        }
+    }
+
+    /// Checks that `path` is a file the service can read.
+    pub fn file(&mut self, key: &str, path: &Path) {
+        if let Err(e) = std::fs::File::open(path) {
+            self.error(key, format!("cannot read `{}`: {}", path.display(), e));
+        }
+    }
+
+    /// Errors collected so far.
+    pub fn errors(&self) -> &[ValidationError] {
+        &self.errors
+    }
+
+    /// Fails with an `Inner::Validation` error if any error was collected.
+    pub fn into_result(self) -> Result<(), crate::error::Error> {
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+
+        Err(crate::error::Error {
+            inner: crate::error::Inner::Validation(self.errors),
+        })
+    }
+}
+
+/// Whether `hostname` is an IP address or a DNS name made of labels of
+/// letters, digits and hyphens, a trailing dot allowed.
+fn is_hostname(hostname: &str) -> bool {
+    if hostname.parse::<IpAddr>().is_ok() {
+        return true;
+    }
+
+    let name = hostname.strip_suffix('.').unwrap_or(hostname);
+    !name.is_empty()
+        && name.len() <= 253
+        && name.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// Validates `Mr. Big`'s own parameters.
+///
+/// The servers may bind port 0, leaving the port to the system.
+pub(crate) fn service(service: &super::Service, validation: &mut Validation) {
+    validation.address("service.hostname", &service.hostname);
+
+    let listen: Vec<super::Listen> = service
+        .listen
+        .iter()
+        .filter_map(|listen| match listen.parse() {
+            Ok(listen) => Some(listen),
+            Err(e) => {
+                validation.error("service.listen", e);
+                None
+            }
+        })
+        .collect();
+    for listen in &listen {
+        if let super::Listen::Unix(_) = listen {
+            validation.check(
+                cfg!(unix),
+                "service.listen",
+                format!("`{}`: Unix domain sockets are not supported here", listen),
+            );
+        }
+    }
+    validation.check(
+        service.socket_mode <= 0o777,
+        "service.socket_mode",
+        "must be a permission mode, such as \"0660\"",
+    );
+
+    validation.check(
+        service.grpc_server.concurrency_limit_per_connection != Some(0),
+        "service.grpc_server.concurrency_limit_per_connection",
+        "must be greater than 0",
+    );
+
+    if let Some(tls) = &service.grpc_server.tls {
+        let key = "service.grpc_server.tls";
+        validation.check(
+            cfg!(feature = "tls"),
+            key,
+            "requires the `tls` feature of mrbig_core",
+        );
+        validation.file(&format!("{}.cert", key), &tls.cert);
+        validation.file(&format!("{}.key", key), &tls.key);
+        if let Some(client_ca) = &tls.client_ca {
+            validation.file(&format!("{}.client_ca", key), client_ca);
+        }
+        validation.check(
+            tls.reload_interval > std::time::Duration::from_secs(0),
+            &format!("{}.reload_interval", key),
+            "must be greater than 0",
+        );
+    }
+
+    if let Some(grpc_web) = &service.grpc_web {
+        let key = "service.grpc_web";
+        validation.check(
+            cfg!(feature = "web"),
+            key,
+            "requires the `web` feature of mrbig_core",
+        );
+
+        let cors = &grpc_web.cors;
+        for origin in &cors.allowed_origins {
+            validation.check(
+                origin == "*" || origin.starts_with("http://") || origin.starts_with("https://"),
+                &format!("{}.cors.allowed_origins", key),
+                format!("`{}` is not an origin, such as `https://app.example.com`", origin),
+            );
+        }
+        validation.check(
+            !(cors.allow_credentials && cors.allowed_origins.iter().any(|o| o == "*")),
+            &format!("{}.cors.allow_credentials", key),
+            "cannot be set when any origin is allowed",
+        );
+    }
+
+    if service.transcoding.is_some() {
+        validation.check(
+            cfg!(feature = "transcoding"),
+            "service.transcoding",
+            "requires the `transcoding` feature of mrbig_core",
+        );
+    }
+
+    if let Some(auth) = &service.auth {
+        let key = "service.auth";
+        validation.check(
+            cfg!(feature = "auth"),
+            key,
+            "requires the `auth` feature of mrbig_core",
+        );
+        validation.check(
+            auth.secret.is_some() != auth.jwks.is_some(),
+            key,
+            "requires either `secret` or `jwks`",
+        );
+        if let Some(secret) = &auth.secret {
+            validation.check(
+                !secret.expose().is_empty(),
+                &format!("{}.secret", key),
+                "must not be empty",
+            );
+        }
+        if let Some(jwks) = &auth.jwks {
+            validation.file(&format!("{}.jwks", key), jwks);
+        }
+        for name in auth.policies.keys() {
+            validation.check(
+                !name.contains('/') && name.contains('.'),
+                &format!("{}.policies", key),
+                format!(
+                    "`{}` is not a fully qualified name, such as `hotel.Hotel.Rates`",
+                    name
+                ),
+            );
+        }
+    }
+
+    #[cfg(feature = "telemetry")]
+    {
+        let metrics = &service.metrics;
+
+        validation.address("service.metrics.hostname", &metrics.hostname);
+
+        let same_host = |a: &str, b: &str| {
+            let any = |h: &str| h == "0.0.0.0" || h == "::";
+            a == b || any(a) || any(b)
+        };
+        // a port left to the system cannot clash
+        if service.listen.is_empty() && service.port != 0 && metrics.port != 0 {
+            validation.check(
+                metrics.port != service.port || !same_host(&metrics.hostname, &service.hostname),
+                "service.metrics.port",
+                format!("clashes with `service.port` ({})", service.port),
+            );
+        }
+        for listen in &listen {
+            match listen {
+                super::Listen::Tcp(addr) if addr.port() != 0 && metrics.port != 0 => {
+                    let host = addr.ip().to_string();
+                    validation.check(
+                        metrics.port != addr.port() || !same_host(&metrics.hostname, &host),
+                        "service.metrics.port",
+                        format!("clashes with `service.listen` ({})", listen),
+                    );
+                }
+                _ => {}
+            }
+        }
+    }
+}
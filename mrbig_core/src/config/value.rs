@@ -0,0 +1,238 @@
+//! Helpers to work on the raw configuration tree before it is
+//! deserialized into `Service` and the user defined `Extra` type.
+use super::schema::Kind;
+use serde::de::{self, IntoDeserializer, Visitor};
+use toml::value::{Table, Value};
+
+/// Deep merges `overlay` onto `base`.
+///
+/// Tables are merged key by key recursively, any other value
+/// in `overlay` replaces the one in `base`.
+pub(crate) fn merge(base: &mut Table, overlay: Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(inner)), Value::Table(other)) => merge(inner, other),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Inserts `value` in `table` at the location given by `path`,
+/// creating the intermediate tables when missing.
+pub(crate) fn insert_path(table: &mut Table, path: &[String], value: Value) {
+    let (last, parents) = match path.split_last() {
+        Some(split) => split,
+        None => return,
+    };
+
+    let mut current = table;
+    for key in parents {
+        let entry = current
+            .entry(key.clone())
+            .or_insert_with(|| Value::Table(Table::new()));
+
+        if !entry.is_table() {
+            *entry = Value::Table(Table::new());
+        }
+
+        current = match entry {
+            Value::Table(inner) => inner,
+            _ => unreachable!(),
+        };
+    }
+
+    current.insert(last.clone(), value);
+}
+
+/// Parses a string as an inline TOML value, such as `[1, 2]` or
+/// `{ secs = 2, nanos = 0 }`.
+pub(crate) fn parse_inline(s: &str) -> Option<Value> {
+    toml::from_str::<Table>(&format!("v = {}", s))
+        .ok()
+        .and_then(|mut t| t.remove("v"))
+}
+
+/// Converts `s` into a value of the given `kind`, so a value given as a
+/// string is checked against the schema before being deserialized.
+/// Values of unknown kind are kept as strings, to be coerced into
+/// whatever type they are deserialized into.
+pub(crate) fn typed(s: &str, kind: &Kind) -> Result<Value, String> {
+    let invalid = || format!("invalid value `{}`, expected {}", s, kind.hint());
+
+    let value = match kind {
+        Kind::Bool => Value::Boolean(s.trim().parse().map_err(|_| invalid())?),
+        Kind::Integer => Value::Integer(s.trim().parse().map_err(|_| invalid())?),
+        Kind::Float => Value::Float(s.trim().parse().map_err(|_| invalid())?),
+        Kind::Option(inner) | Kind::Secret(inner) => return typed(s, inner),
+        Kind::Enum(variants) if !variants.contains(&s) => return Err(invalid()),
+        Kind::Seq(_) => match parse_inline(s) {
+            Some(array @ Value::Array(_)) => array,
+            _ => return Err(invalid()),
+        },
+        Kind::Map(_) | Kind::Struct(_) => match parse_inline(s) {
+            Some(table @ Value::Table(_)) => table,
+            _ => return Err(invalid()),
+        },
+        Kind::String | Kind::Enum(_) | Kind::Any => Value::String(s.into()),
+    };
+
+    Ok(value)
+}
+
+/// A deserializer over a TOML value which coerces strings into
+/// the type expected by the target.
+///
+/// Values coming from environment variables or the command line
+/// are always strings, so the schema of the type being deserialized
+/// decides whether `"8080"` should become an integer or stay a string.
+/// Strings targeting structs, maps or sequences are parsed as inline
+/// TOML values.
+pub(crate) struct Coerce(pub(crate) Value);
+
+type Error = toml::de::Error;
+
+macro_rules! coerce_parse {
+    ($method:ident, $visit:ident, $ty:ty) => {
+        fn $method<V>(self, visitor: V) -> Result<V::Value, Error>
+        where
+            V: Visitor<'de>,
+        {
+            match self.0 {
+                Value::String(s) => match s.trim().parse::<$ty>() {
+                    Ok(v) => visitor.$visit(v),
+                    Err(_) => Err(de::Error::invalid_value(de::Unexpected::Str(&s), &visitor)),
+                },
+                v => Coerce(v).deserialize_any(visitor),
+            }
+        }
+    };
+}
+
+macro_rules! coerce_compound {
+    ($method:ident $(, $arg:ident: $ty:ty)*) => {
+        fn $method<V>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, Error>
+        where
+            V: Visitor<'de>,
+        {
+            match self.0 {
+                Value::String(s) => match parse_inline(&s) {
+                    Some(v) => Coerce(v).deserialize_any(visitor),
+                    None => visitor.visit_string(s),
+                },
+                v => Coerce(v).deserialize_any(visitor),
+            }
+        }
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Coerce {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            Value::String(s) => visitor.visit_string(s),
+            Value::Integer(i) => visitor.visit_i64(i),
+            Value::Float(f) => visitor.visit_f64(f),
+            Value::Boolean(b) => visitor.visit_bool(b),
+            Value::Datetime(d) => visitor.visit_string(d.to_string()),
+            Value::Array(a) => {
+                visitor.visit_seq(de::value::SeqDeserializer::new(a.into_iter().map(Coerce)))
+            }
+            Value::Table(t) => visitor.visit_map(de::value::MapDeserializer::new(
+                t.into_iter().map(|(k, v)| (k, Coerce(v))),
+            )),
+        }
+    }
+
+    coerce_parse!(deserialize_bool, visit_bool, bool);
+    coerce_parse!(deserialize_i8, visit_i64, i64);
+    coerce_parse!(deserialize_i16, visit_i64, i64);
+    coerce_parse!(deserialize_i32, visit_i64, i64);
+    coerce_parse!(deserialize_i64, visit_i64, i64);
+    coerce_parse!(deserialize_u8, visit_u64, u64);
+    coerce_parse!(deserialize_u16, visit_u64, u64);
+    coerce_parse!(deserialize_u32, visit_u64, u64);
+    coerce_parse!(deserialize_u64, visit_u64, u64);
+    coerce_parse!(deserialize_f32, visit_f64, f64);
+    coerce_parse!(deserialize_f64, visit_f64, f64);
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            Value::Integer(i) => visitor.visit_string(i.to_string()),
+            Value::Float(f) => visitor.visit_string(f.to_string()),
+            Value::Boolean(b) => visitor.visit_string(b.to_string()),
+            v => Coerce(v).deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        // TOML has no null value, a present key is always `Some`
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            Value::String(s) => visitor.visit_enum(s.into_deserializer()),
+            v => v.deserialize_enum(name, variants, visitor),
+        }
+    }
+
+    coerce_compound!(deserialize_seq);
+    coerce_compound!(deserialize_tuple, _len: usize);
+    coerce_compound!(deserialize_tuple_struct, _name: &'static str, _len: usize);
+    coerce_compound!(deserialize_map);
+    coerce_compound!(
+        deserialize_struct,
+        _name: &'static str,
+        _fields: &'static [&'static str]
+    );
+
+    serde::forward_to_deserialize_any! {
+        char bytes byte_buf unit unit_struct identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Coerce {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
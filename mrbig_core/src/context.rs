@@ -1,7 +1,12 @@
 use crate::config::Config;
+use crate::shutdown::ShutdownHooks;
 #[cfg(feature = "grpc")]
 use tonic_health::{ServingStatus, server::{HealthReporter as TonicHealthReporter}};
 #[cfg(feature = "grpc")]
+use crate::interceptor::Interceptors;
+#[cfg(feature = "grpc")]
+use crate::layer::{BoxError, BoxService, Layers, Request, Response};
+#[cfg(feature = "grpc")]
 use futures::lock::Mutex;
 #[cfg(feature = "grpc")]
 use std::sync::Arc;
@@ -45,6 +50,16 @@ pub struct Context {
     config: Option<Box<Config>>,
     #[cfg(feature = "grpc")]
     health_reporter: Arc<Mutex<Option<Box<TonicHealthReporter>>>>,
+    // Services whose health is reported, set to NOT_SERVING on shutdown.
+    #[cfg(feature = "grpc")]
+    health_services: Vec<String>,
+    shutdown_hooks: ShutdownHooks,
+    // Interceptors of the registered services, after the attribute ones.
+    #[cfg(feature = "grpc")]
+    interceptors: Interceptors,
+    // Layers in front of all services, the builtin ones included.
+    #[cfg(feature = "grpc")]
+    layers: Layers,
     // Server by tonic to be built later by service.
     #[cfg(feature = "grpc")]
     server: Option<Box<tonic::transport::Server>>,
@@ -93,6 +108,102 @@ impl Context {
 	}
     }
 
+    /// Records that the health of service `svc` is reported, so that it is
+    /// set to `NotServing` on shutdown.
+    ///
+    /// *Not supposed to be used outside of mrbig_derive macros.*
+    #[cfg(feature = "grpc")]
+    pub fn add_health_service(&mut self, svc: &str) {
+        self.health_services.push(svc.into());
+    }
+
+    /// Gets the health reporters of the services recorded with
+    /// `add_health_service`.
+    #[cfg(feature = "grpc")]
+    pub async fn get_health_reporters(&self) -> Vec<HealthReporter> {
+        let mut reporters = vec![];
+        for svc in &self.health_services {
+            reporters.push(self.get_health_reporter(svc).await);
+        }
+        reporters
+    }
+
+    /// Registers a hook to run on shutdown, once the server has stopped,
+    /// such as flushing buffers or closing connections to databases.
+    /// Hooks run in the order they were registered.
+    pub fn on_shutdown<F, Fut>(&mut self, hook: F)
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: std::future::Future<Output = ()> + Send + 'static,
+    {
+        self.shutdown_hooks.push(hook);
+    }
+
+    /// Runs the hooks registered with `on_shutdown`.
+    ///
+    /// *Not supposed to be used outside of mrbig_derive macros.*
+    pub async fn run_shutdown_hooks(&self) {
+        self.shutdown_hooks.run().await;
+    }
+
+    /// Registers an interceptor for all the services registered with
+    /// `#[mrbig_register_grpc]`, run after the ones registered so far.
+    /// See the `interceptor` module.
+    #[cfg(feature = "grpc")]
+    pub fn add_interceptor<F>(&mut self, intercept: F)
+    where
+        F: Fn(tonic::Request<()>) -> Result<tonic::Request<()>, tonic::Status>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.interceptors.push(None, intercept);
+    }
+
+    /// Registers an interceptor for the `services` given by fully
+    /// qualified name, run after the ones registered so far.
+    #[cfg(feature = "grpc")]
+    pub fn add_interceptor_for<F>(&mut self, services: &[&str], intercept: F)
+    where
+        F: Fn(tonic::Request<()>) -> Result<tonic::Request<()>, tonic::Status>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.interceptors.push(Some(services), intercept);
+    }
+
+    /// Gets the interceptors registered with `add_interceptor` and
+    /// `add_interceptor_for`.
+    ///
+    /// *Not supposed to be used outside of mrbig_derive macros.*
+    #[cfg(feature = "grpc")]
+    pub fn get_interceptors(&self) -> &Interceptors {
+        &self.interceptors
+    }
+
+    /// Adds a tower layer in front of all the services, the builtin health
+    /// and reflection servers included, inside the layers added so far.
+    /// See the `layer` module.
+    #[cfg(feature = "grpc")]
+    pub fn add_layer<L>(&mut self, layer: L)
+    where
+        L: tower::layer::Layer<BoxService> + Send + Sync + 'static,
+        L::Service: tower::Service<Request, Response = Response> + Clone + Send + 'static,
+        <L::Service as tower::Service<Request>>::Error: Into<BoxError> + 'static,
+        <L::Service as tower::Service<Request>>::Future: Send + 'static,
+    {
+        self.layers.push(layer);
+    }
+
+    /// Gets the layers added with `add_layer`.
+    ///
+    /// *Not supposed to be used outside of mrbig_derive macros.*
+    #[cfg(feature = "grpc")]
+    pub fn get_layers(&self) -> &Layers {
+        &self.layers
+    }
+
     /// Sets the grpc transport server.
     #[cfg(feature = "grpc")]
     pub fn set_server(&mut self, server: tonic::transport::Server) {
@@ -4,12 +4,29 @@ pub enum Inner {
     Io(std::io::Error),
     /// Deserialization error
     De(toml::de::Error),
+    /// Config file is not valid in its format
+    Parse {
+        format: crate::config::Format,
+        error: String,
+    },
+    /// Config value could not be deserialized, with the format of the
+    /// config file and the dotted path of the offending key
+    Key {
+        format: crate::config::Format,
+        key: String,
+        error: toml::de::Error,
+    },
+    /// Invalid configuration values, all reported at once
+    Validation(Vec<crate::config::ValidationError>),
     /// getopts error
     Opts(getopts::Fail),
     /// Address parse error
     Addr(std::net::AddrParseError),
     /// Other kind of error
     Other(String),
+    /// Not an error: the command line asked for the given output (usage,
+    /// version...) to be printed and the program to exit successfully
+    Exit(String),
 }
 
 /// Custom error type to encapsulate other errors.
@@ -26,6 +43,18 @@ impl Error {
             inner: Inner::Other(message.into()),
         }
     }
+
+    /// Create a request to print `output` and exit successfully.
+    pub fn exit(output: String) -> Self {
+        Error {
+            inner: Inner::Exit(output),
+        }
+    }
+
+    /// Whether this is a request to exit successfully rather than an error.
+    pub fn is_exit(&self) -> bool {
+        matches!(self.inner, Inner::Exit(_))
+    }
 }
 
 impl std::fmt::Display for Error {
@@ -33,8 +62,17 @@ impl std::fmt::Display for Error {
         match &self.inner {
             Inner::Io(e) => write!(f, "io error: {}", e.to_string()),
             Inner::De(e) => write!(f, "deserialization error: {}", e.to_string()),
+            Inner::Parse { format, error } => write!(f, "{} parse error: {}", format, error),
+            Inner::Key { format, key, error } => {
+                write!(f, "{} config error at `{}`: {}", format, key, error)
+            }
+            Inner::Validation(errors) => {
+                let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "invalid configuration: {}", errors.join(", "))
+            }
             Inner::Opts(e) => write!(f, "options error: {}", e.to_string()),
             Inner::Addr(e) => write!(f, "address parse error: {}", e.to_string()),
+            Inner::Exit(output) => write!(f, "{}", output),
             e => write!(f, "{:?}", e),
         }
     }
@@ -51,9 +89,13 @@ impl std::error::Error for Error {
         match self.inner {
             Inner::Io(ref e) => Some(e),
             Inner::De(ref e) => Some(e),
+            Inner::Parse { .. } => None,
+            Inner::Validation(_) => None,
+            Inner::Key { ref error, .. } => Some(error),
             Inner::Opts(ref e) => Some(e),
             Inner::Addr(ref e) => Some(e),
             Inner::Other(_) => None,
+            Inner::Exit(_) => None,
         }
     }
 }
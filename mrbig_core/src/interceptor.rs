@@ -0,0 +1,207 @@
+//! Chains of interceptors for the registered gRPC services.
+//!
+//! Interceptors are registered with the `#[mrbig_interceptor]` attribute
+//! of `#[derive(Run)]` or with `Context::add_interceptor`, for all the
+//! services registered with `#[mrbig_register_grpc]` or for some of them.
+//! Each service is given the chain of the interceptors which apply to it,
+//! run in the order they were registered: the attribute ones first, then
+//! the context ones. The first interceptor to return an error ends the
+//! chain and the error is the call's status.
+//!
+//! Interceptors see the request's metadata only. tonic 0.3 carries the
+//! extensions of the HTTP request into `tonic::Request`, but only exposes
+//! the remote address and the peer certificates out of them, so neither
+//! the interceptors nor the layers can hand typed values to the handlers.
+//! There is no extension path until tonic exposes them: values meant for
+//! the handlers, such as the caller's identity, are inserted as metadata,
+//! always overwriting the value a client may have sent under the same
+//! name (as the authentication does with `mrbig-claims-bin`):
+//!
+//! ```ignore
+//! fn check(mut req: Request<()>) -> Result<Request<()>, Status> {
+//!     let user = authenticate(req.metadata())?;
+//!     req.metadata_mut().insert("x-user", user.parse().unwrap());
+//!     Ok(req)
+//! }
+//! ```
+// tonic interceptors return their `Status`
+#![allow(clippy::result_large_err)]
+use std::sync::Arc;
+use tonic::metadata::{KeyAndValueRef, MetadataMap};
+use tonic::{Request, Status};
+
+type Intercept = dyn Fn(Request<()>) -> Result<Request<()>, Status> + Send + Sync;
+
+/// An interceptor and the services it applies to, all of them if `None`.
+#[derive(Clone)]
+struct Entry {
+    services: Option<Vec<String>>,
+    intercept: Arc<Intercept>,
+}
+
+/// Interceptors, in the order they were registered.
+#[derive(Clone, Default)]
+pub struct Interceptors(Vec<Entry>);
+
+impl Interceptors {
+    /// Registers `intercept` for the services named in `services`, given
+    /// by fully qualified name, or for all of them if `None`.
+    pub fn push<F>(&mut self, services: Option<&[&str]>, intercept: F)
+    where
+        F: Fn(Request<()>) -> Result<Request<()>, Status> + Send + Sync + 'static,
+    {
+        self.0.push(Entry {
+            services: services.map(|s| s.iter().map(|s| s.to_string()).collect()),
+            intercept: Arc::new(intercept),
+        });
+    }
+
+    /// Appends the interceptors of `other`, after the ones registered so far.
+    pub fn extend(&mut self, other: &Interceptors) {
+        self.0.extend(other.0.iter().cloned());
+    }
+
+    /// Chain of the interceptors which apply to `service`, if any.
+    pub fn chain(&self, service: &str) -> Option<tonic::Interceptor> {
+        let chain = self.applying_to(service);
+
+        if chain.is_empty() {
+            return None;
+        }
+
+        Some(tonic::Interceptor::new(move |request| run(&chain, request)))
+    }
+
+    fn applying_to(&self, service: &str) -> Vec<Arc<Intercept>> {
+        self.0
+            .iter()
+            .filter(|e| match &e.services {
+                Some(services) => services.iter().any(|s| s == service),
+                None => true,
+            })
+            .map(|e| e.intercept.clone())
+            .collect()
+    }
+
+    /// Number of registered interceptors.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether no interceptor is registered.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+// Runs the interceptors of `chain` in order, up to the first error.
+fn run(chain: &[Arc<Intercept>], request: Request<()>) -> Result<Request<()>, Status> {
+    chain
+        .iter()
+        .try_fold(request, |request, intercept| intercept(request))
+}
+
+/// Metadata entries holding credentials, whose values are never logged.
+const REDACTED: &[&str] = &["authorization", "cookie", "mrbig-claims-bin"];
+
+/// The entries of `metadata`, as logged by the debug interceptor, the
+/// values of the credentials redacted.
+pub(crate) fn describe(metadata: &MetadataMap) -> String {
+    metadata
+        .iter()
+        .map(|kv| match kv {
+            KeyAndValueRef::Ascii(k, _) if REDACTED.contains(&k.as_str()) => {
+                format!("{:?}: <redacted>", k)
+            }
+            KeyAndValueRef::Binary(k, _) if REDACTED.contains(&k.as_str()) => {
+                format!("{:?}: <redacted>", k)
+            }
+            KeyAndValueRef::Ascii(k, v) => format!("{:?}: {:?}", k, v),
+            KeyAndValueRef::Binary(k, v) => format!("{:?}: {:?}", k, v),
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+impl std::fmt::Debug for Interceptors {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Interceptors({})", self.0.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::metadata::MetadataValue;
+
+    fn tag(value: &'static str) -> impl Fn(Request<()>) -> Result<Request<()>, Status> {
+        move |mut request| {
+            request
+                .metadata_mut()
+                .append("x-tag", value.parse().unwrap());
+            Ok(request)
+        }
+    }
+
+    fn tags(interceptors: &Interceptors, service: &str) -> Result<Vec<String>, Status> {
+        let request = run(&interceptors.applying_to(service), Request::new(()))?;
+        Ok(request
+            .metadata()
+            .get_all("x-tag")
+            .iter()
+            .map(|v| v.to_str().unwrap().to_string())
+            .collect())
+    }
+
+    #[test]
+    fn chains_in_order_per_service() {
+        let mut interceptors = Interceptors::default();
+        interceptors.push(None, tag("all"));
+        interceptors.push(Some(&["helloworld.Greeter"]), tag("greeter"));
+
+        let mut extra = Interceptors::default();
+        extra.push(Some(&["hotel.Rates"]), tag("rates"));
+        extra.push(None, tag("last"));
+        interceptors.extend(&extra);
+        assert_eq!(format!("{:?}", interceptors), "Interceptors(4)");
+
+        assert_eq!(
+            tags(&interceptors, "helloworld.Greeter").unwrap(),
+            vec!["all", "greeter", "last"]
+        );
+        assert_eq!(
+            tags(&interceptors, "hotel.Rates").unwrap(),
+            vec!["all", "rates", "last"]
+        );
+        assert!(interceptors.chain("hotel.Rates").is_some());
+        assert!(Interceptors::default().chain("hotel.Rates").is_none());
+    }
+
+    #[test]
+    fn credentials_are_redacted() {
+        let mut metadata = MetadataMap::new();
+        metadata.insert("authorization", "Bearer s3cret".parse().unwrap());
+        metadata.insert("x-request-id", "42".parse().unwrap());
+        metadata.insert_bin("mrbig-claims-bin", MetadataValue::from_bytes(b"{}"));
+
+        let text = describe(&metadata);
+        assert!(!text.contains("s3cret"), "{}", text);
+        assert!(text.contains("\"authorization\": <redacted>"), "{}", text);
+        assert!(
+            text.contains("\"mrbig-claims-bin\": <redacted>"),
+            "{}",
+            text
+        );
+        assert!(text.contains("\"x-request-id\": \"42\""), "{}", text);
+    }
+
+    #[test]
+    fn first_error_ends_chain() {
+        let mut interceptors = Interceptors::default();
+        interceptors.push(None, |_| Err(Status::unauthenticated("no token")));
+        interceptors.push(None, |_| panic!("interceptor run after an error"));
+
+        let status = tags(&interceptors, "helloworld.Greeter").unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+    }
+}
@@ -0,0 +1,360 @@
+//! Tower layers in front of the gRPC services.
+//!
+//! Layers added with `Context::add_layer` wrap every service of the
+//! server, the ones registered with `#[mrbig_register_grpc]` and the
+//! builtin health and reflection servers alike. They stack in the order
+//! they were added, the first one outermost, as with `tower::ServiceBuilder`:
+//!
+//! ```ignore
+//! use mrbig_core::tower::{limit::ConcurrencyLimitLayer, timeout::TimeoutLayer};
+//!
+//! service.context.add_layer(TimeoutLayer::new(Duration::from_secs(5)));
+//! service.context.add_layer(ConcurrencyLimitLayer::new(64));
+//! ```
+//!
+//! Layers see the services as `BoxService`, which erases their types: a
+//! layer only has to work with any `Service` of HTTP requests, as the
+//! layers of the `tower` crate do. Each call polls the layers for
+//! readiness before calling them, so the concurrency limits and the load
+//! shedding of `tower` apply; a call failed by a layer is answered with a
+//! status.
+use futures::future::{BoxFuture, FutureExt, TryFutureExt};
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tonic::body::BoxBody;
+use tonic::codegen::http;
+use tonic::transport::{Body, NamedService};
+use tower::layer::Layer;
+use tower::{Service, ServiceExt};
+
+/// Requests handled by the services.
+pub type Request = http::Request<Body>;
+/// Responses of the services.
+pub type Response = http::Response<BoxBody>;
+/// Errors of the services.
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+trait CloneService:
+    Service<
+        Request,
+        Response = Response,
+        Error = BoxError,
+        Future = BoxFuture<'static, Result<Response, BoxError>>,
+    > + Send
+{
+    fn clone_box(&self) -> Box<dyn CloneService>;
+}
+
+// Boxes the futures and errors of a service
+#[derive(Clone)]
+struct Boxed<S>(S);
+
+impl<S> Service<Request> for Boxed<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Error: Into<BoxError> + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Response, BoxError>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        self.0.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        self.0.call(request).map_err(Into::into).boxed()
+    }
+}
+
+impl<S> CloneService for Boxed<S>
+where
+    S: Service<Request, Response = Response> + Clone + Send + 'static,
+    S::Error: Into<BoxError> + 'static,
+    S::Future: Send + 'static,
+{
+    fn clone_box(&self) -> Box<dyn CloneService> {
+        Box::new(self.clone())
+    }
+}
+
+/// A service, layered or not, with its type erased.
+pub struct BoxService(Box<dyn CloneService>);
+
+impl BoxService {
+    /// Erases the type of `service`.
+    pub fn new<S>(service: S) -> Self
+    where
+        S: Service<Request, Response = Response> + Clone + Send + 'static,
+        S::Error: Into<BoxError> + 'static,
+        S::Future: Send + 'static,
+    {
+        BoxService(Box::new(Boxed(service)))
+    }
+}
+
+impl Clone for BoxService {
+    fn clone(&self) -> Self {
+        BoxService(self.0.clone_box())
+    }
+}
+
+impl Service<Request> for BoxService {
+    type Response = Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Response, BoxError>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        self.0.call(request)
+    }
+}
+
+impl std::fmt::Debug for BoxService {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "BoxService")
+    }
+}
+
+type LayerFn = dyn Fn(BoxService) -> BoxService + Send + Sync;
+
+/// Layers, in the order they were added.
+#[derive(Clone, Default)]
+pub struct Layers(Vec<Arc<LayerFn>>);
+
+impl Layers {
+    /// Adds `layer`, inside the layers added so far.
+    pub fn push<L>(&mut self, layer: L)
+    where
+        L: Layer<BoxService> + Send + Sync + 'static,
+        L::Service: Service<Request, Response = Response> + Clone + Send + 'static,
+        <L::Service as Service<Request>>::Error: Into<BoxError> + 'static,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        self.0.push(Arc::new(move |service| {
+            BoxService::new(layer.layer(service))
+        }));
+    }
+
+    /// Adds the layers of `other`, inside the layers added so far.
+    pub fn extend(&mut self, other: &Layers) {
+        self.0.extend(other.0.iter().cloned());
+    }
+
+    /// Wraps `service` in the layers, the first one outermost.
+    pub fn apply(&self, service: BoxService) -> BoxService {
+        self.0
+            .iter()
+            .rev()
+            .fold(service, |service, layer| layer(service))
+    }
+
+    /// Number of layers.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether no layer was added.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl std::fmt::Debug for Layers {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Layers({})", self.0.len())
+    }
+}
+
+/// A gRPC service wrapped in layers, still routed under its name.
+///
+/// *Not supposed to be used outside of mrbig_derive macros.*
+pub struct Layered<S> {
+    inner: BoxService,
+    service: PhantomData<fn() -> S>,
+}
+
+impl<S> Layered<S>
+where
+    S: Service<Request, Response = Response> + Clone + Send + 'static,
+    S::Error: Into<BoxError> + 'static,
+    S::Future: Send + 'static,
+{
+    /// Wraps `service` in `layers`.
+    pub fn new(service: S, layers: &Layers) -> Self {
+        Layered {
+            inner: layers.apply(BoxService::new(service)),
+            service: PhantomData,
+        }
+    }
+}
+
+impl<S> Clone for Layered<S> {
+    fn clone(&self) -> Self {
+        Layered {
+            inner: self.inner.clone(),
+            service: PhantomData,
+        }
+    }
+}
+
+impl<S> Service<Request> for Layered<S> {
+    type Response = Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Response, BoxError>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        // the gRPC server calls the services without polling them for
+        // readiness, so each call drives the readiness of the layers
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        self.inner
+            .clone()
+            .oneshot(request)
+            // a failed service would end the connection and every call on it
+            .or_else(|e| futures::future::ok(status_of(e).to_http()))
+            .boxed()
+    }
+}
+
+/// The status answering a call failed by a layer.
+fn status_of(error: BoxError) -> tonic::Status {
+    if error.is::<tower::timeout::error::Elapsed>() {
+        tonic::Status::deadline_exceeded(error.to_string())
+    } else if error.is::<tower::load_shed::error::Overloaded>() {
+        tonic::Status::unavailable(error.to_string())
+    } else {
+        tonic::Status::unknown(error.to_string())
+    }
+}
+
+impl<S: NamedService> NamedService for Layered<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+impl<S> std::fmt::Debug for Layered<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Layered({})", std::any::type_name::<S>())
+    }
+}
+
+/// The layered services by name, to route the calls served outside of the
+/// gRPC server, such as the HTTP/1.1 ones.
+///
+/// *Not supposed to be used outside of mrbig_derive macros.*
+#[derive(Clone)]
+pub struct Routes {
+    services: Vec<(&'static str, BoxService)>,
+    // answers the calls to unknown services, behind the layers as well
+    unimplemented: BoxService,
+}
+
+impl Routes {
+    /// Routes to no service, the calls being answered behind `layers`.
+    pub fn new(layers: &Layers) -> Self {
+        let unimplemented = tower::service_fn(|_: Request| {
+            // as the gRPC server does
+            futures::future::ok::<_, BoxError>(
+                http::Response::builder()
+                    .header("grpc-status", "12")
+                    .header("content-type", "application/grpc")
+                    .body(BoxBody::empty())
+                    .expect("invalid response"),
+            )
+        });
+
+        Routes {
+            services: vec![],
+            unimplemented: layers.apply(BoxService::new(unimplemented)),
+        }
+    }
+
+    /// Records `service`, routed under its name, and hands it back.
+    pub fn add<S: NamedService>(&mut self, service: Layered<S>) -> Layered<S> {
+        self.services.push((S::NAME, service.inner.clone()));
+        service
+    }
+
+    /// The service the call to `path`, as in `/hotel.Hotel/Rates`, is for.
+    fn route(&self, path: &str) -> BoxService {
+        let name = path
+            .strip_prefix('/')
+            .and_then(|path| path.split_once('/'))
+            .map(|(name, _)| name);
+        self.services
+            .iter()
+            .find(|(service, _)| Some(*service) == name)
+            .map_or(&self.unimplemented, |(_, service)| service)
+            .clone()
+    }
+}
+
+impl Service<Request> for Routes {
+    type Response = Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Response, BoxError>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        // each call is given a service of its own
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        self.route(request.uri().path()).oneshot(request).boxed()
+    }
+}
+
+impl std::fmt::Debug for Routes {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let names: Vec<&str> = self.services.iter().map(|(name, _)| *name).collect();
+        write!(f, "Routes({:?})", names)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use tower::layer::layer_fn;
+    use tower::service_fn;
+
+    type Calls = Arc<Mutex<Vec<&'static str>>>;
+
+    fn record(layers: &mut Layers, calls: &Calls, name: &'static str) {
+        let calls = calls.clone();
+        layers.push(layer_fn(move |mut inner: BoxService| {
+            let calls = calls.clone();
+            service_fn(move |request| {
+                calls.lock().unwrap().push(name);
+                inner.call(request)
+            })
+        }));
+    }
+
+    #[test]
+    fn layers_stack_first_outermost() {
+        let calls = Calls::default();
+
+        let mut layers = Layers::default();
+        record(&mut layers, &calls, "outer");
+        record(&mut layers, &calls, "inner");
+        assert_eq!(format!("{:?}", layers), "Layers(2)");
+
+        let service_calls = calls.clone();
+        let service = service_fn(move |_: Request| {
+            service_calls.lock().unwrap().push("service");
+            futures::future::ok::<_, BoxError>(Response::new(BoxBody::empty()))
+        });
+
+        let mut layered = layers.apply(BoxService::new(service));
+        futures::executor::block_on(layered.call(Request::new(Body::empty()))).unwrap();
+        assert_eq!(*calls.lock().unwrap(), vec!["outer", "inner", "service"]);
+    }
+}
@@ -6,40 +6,112 @@ pub use crate::error::{Error, Inner};
 pub use ansi_term;
 pub use context::Context;
 pub use log;
+pub use schemars;
+pub use serde_json;
 
 #[cfg(feature = "grpc")]
 pub use grpc_reflection;
 #[cfg(feature = "grpc")]
 pub use tonic_health;
+#[cfg(feature = "grpc")]
+pub use tower;
+#[cfg(feature = "auth")]
+pub mod auth;
 #[cfg(feature = "telemetry")]
 pub use lazy_static::lazy_static;
 #[cfg(feature = "telemetry")]
 pub use prometheus;
 #[cfg(feature = "telemetry")]
 pub mod metrics;
+#[cfg(feature = "grpc")]
+pub mod interceptor;
+#[cfg(feature = "grpc")]
+pub mod layer;
+#[cfg(feature = "grpc")]
+pub mod listen;
+#[cfg(feature = "grpc")]
+pub mod server;
+#[cfg(feature = "grpc")]
+pub use server::{ServerHandle, Start};
+pub mod shutdown;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "tls")]
+pub mod tls;
+#[cfg(feature = "grpc")]
+pub mod transcode;
+#[cfg(feature = "web")]
+pub mod web;
 
 /// A default grpc interceptor that simply prints debug information about the received
-/// protobuf request message.
+/// protobuf request message. The values of the credentials (`authorization`,
+/// `cookie` and `mrbig-claims-bin`) are redacted.
 #[cfg(feature = "grpc")]
 pub fn default_grpc_interceptor(
     req: tonic::Request<()>,
 ) -> std::result::Result<tonic::Request<()>, tonic::Status> {
-    use tonic::metadata::KeyAndValueRef;
-
-    let text = req
-        .metadata()
-        .iter()
-        .map(|kv| match kv {
-            KeyAndValueRef::Ascii(k, v) => format!("{:?}: {:?}", k, v),
-            KeyAndValueRef::Binary(k, v) => format!("{:?}: {:?}", k, v),
-        })
-        .collect::<Vec<String>>()
-        .join(", ");
+    let text = interceptor::describe(req.metadata());
 
     log::debug!("headers: {{ {} }}", text);
     Ok(req)
 }
 
+/// Registers the JWT authentication and the policies of the `authenticated`
+/// services when `service.auth` is set, the `exempt` ones being left alone.
+///
+/// *Not supposed to be used outside of mrbig_derive macros.*
+#[cfg(feature = "grpc")]
+pub fn push_auth_interceptors(
+    service: &config::Service,
+    interceptors: &mut interceptor::Interceptors,
+    layers: &mut layer::Layers,
+    authenticated: &[&str],
+    exempt: &[&str],
+) -> Result<(), Error> {
+    #[cfg(feature = "auth")]
+    return auth::push(
+        interceptors,
+        layers,
+        service.auth.as_ref(),
+        authenticated,
+        exempt,
+    );
+
+    #[cfg(not(feature = "auth"))]
+    {
+        let _ = (interceptors, layers, authenticated, exempt);
+        match &service.auth {
+            Some(_) => Err(Error::new(
+                "authentication requires the `auth` feature of mrbig_core",
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Adds to `layers` the layer turning the gRPC-Web calls into gRPC ones
+/// when `service.grpc_web` is set.
+///
+/// *Not supposed to be used outside of mrbig_derive macros.*
+#[cfg(feature = "grpc")]
+pub fn push_grpc_web_layer(
+    service: &config::Service,
+    layers: &mut layer::Layers,
+) -> Result<(), Error> {
+    match &service.grpc_web {
+        #[cfg(feature = "web")]
+        Some(grpc_web) => web::push(layers, grpc_web),
+        #[cfg(not(feature = "web"))]
+        Some(_) => Err(Error::new(
+            "gRPC-Web requires the `web` feature of mrbig_core",
+        )),
+        None => {
+            let _ = layers;
+            Ok(())
+        }
+    }
+}
+
 #[cfg(all(feature = "env_log", not(feature = "traceable")))]
 fn init_logging(config: &config::Config) -> Result<(), Error> {
     use env_logger::Builder;
@@ -63,13 +135,35 @@ fn init_logging(config: &config::Config) -> Result<(), Error> {
 
     logger.parse(&filters);
 
+    // another service of the same process, as in tests, set it already
+    #[cfg(feature = "testing")]
+    {
+        if logger.try_init().is_err() {
+            log::debug!("logger already initialized");
+        }
+    }
+    #[cfg(not(feature = "testing"))]
     logger.init();
 
     Ok(())
 }
 
+#[cfg(all(feature = "env_log", not(feature = "traceable")))]
+fn reload_logging(_config: &config::Config) -> Result<(), Error> {
+    log::warn!("logger filters cannot be reloaded with env_log, restart to apply them");
+    Ok(())
+}
+
 #[cfg(all(feature = "traceable", not(feature = "env_log")))]
-fn init_logging(config: &config::Config) -> Result<(), Error> {
+type FilterReloader =
+    Box<dyn Fn(tracing_subscriber::filter::EnvFilter) -> Result<(), String> + Send + Sync>;
+
+/// Handle used to swap the filter of the global subscriber.
+#[cfg(all(feature = "traceable", not(feature = "env_log")))]
+static FILTER_RELOADER: std::sync::Mutex<Option<FilterReloader>> = std::sync::Mutex::new(None);
+
+#[cfg(all(feature = "traceable", not(feature = "env_log")))]
+fn env_filter(config: &config::Config) -> tracing_subscriber::filter::EnvFilter {
     use tracing_subscriber::filter::{EnvFilter, LevelFilter};
 
     let directives = match std::env::var(EnvFilter::DEFAULT_ENV) {
@@ -77,35 +171,113 @@ fn init_logging(config: &config::Config) -> Result<(), Error> {
         Err(_) => config.service.logger_filters.clone(),
     };
 
-    let mut filter = EnvFilter::new(directives);
+    let filter = EnvFilter::new(directives);
 
-    filter = match (config.service.trace, config.service.debug) {
+    match (config.service.trace, config.service.debug) {
         (false, false) => filter.add_directive(LevelFilter::INFO.into()),
         (true, _) => filter.add_directive(LevelFilter::TRACE.into()),
         _ => filter.add_directive(LevelFilter::DEBUG.into()),
-    };
+    }
+}
+
+#[cfg(all(feature = "traceable", not(feature = "env_log")))]
+fn init_logging(config: &config::Config) -> Result<(), Error> {
+    let builder = tracing_subscriber::FmtSubscriber::builder()
+        .with_env_filter(env_filter(config))
+        .with_filter_reloading();
+
+    let handle = builder.reload_handle();
+
+    // another service of the same process, as in tests, set it already
+    #[cfg(feature = "testing")]
+    {
+        if builder.try_init().is_err() {
+            log::debug!("logger already initialized");
+            return Ok(());
+        }
+    }
+    #[cfg(not(feature = "testing"))]
+    builder.init();
 
-    tracing_subscriber::FmtSubscriber::builder()
-        .with_env_filter(filter)
-        .init();
+    FILTER_RELOADER
+        .lock()
+        .map_err(|_| Error::new("logger reload handle is poisoned"))?
+        .replace(Box::new(move |filter| {
+            handle.reload(filter).map_err(|e| e.to_string())
+        }));
 
     Ok(())
 }
 
+#[cfg(all(feature = "traceable", not(feature = "env_log")))]
+fn reload_logging(config: &config::Config) -> Result<(), Error> {
+    let reloader = FILTER_RELOADER
+        .lock()
+        .map_err(|_| Error::new("logger reload handle is poisoned"))?;
+
+    match reloader.as_ref() {
+        Some(reload) => Ok(reload(env_filter(config))?),
+        None => Ok(()),
+    }
+}
+
 #[cfg(all(feature = "traceable", feature = "env_log"))]
 fn init_logging(_config: &config::Config) -> Result<(), Error> {
     Ok(())
 }
 
+#[cfg(all(feature = "traceable", feature = "env_log"))]
+fn reload_logging(_config: &config::Config) -> Result<(), Error> {
+    Ok(())
+}
+
 #[cfg(all(not(feature = "traceable"), not(feature = "env_log")))]
 fn init_logging(_config: &config::Config) -> Result<(), Error> {
     Ok(())
 }
 
+#[cfg(all(not(feature = "traceable"), not(feature = "env_log")))]
+fn reload_logging(_config: &config::Config) -> Result<(), Error> {
+    Ok(())
+}
+
 pub fn init(config: &config::Config) -> Result<(), Error> {
     init_logging(config)
 }
 
+/// Outcome of a service's `init()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Init {
+    /// The service is configured and ready to run.
+    Ready,
+    /// The command line asked for some output (usage, version, config),
+    /// which was printed, and the program should exit successfully.
+    Exit,
+}
+
+impl Init {
+    /// Turns the result of loading the configuration into an `Init`,
+    /// printing the output of exit requests.
+    pub fn from_load(result: Result<(), Error>) -> Result<Init, Error> {
+        match result {
+            Ok(()) => Ok(Init::Ready),
+            Err(Error {
+                inner: Inner::Exit(output),
+            }) => {
+                println!("{}", output);
+                Ok(Init::Exit)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Applies the parameters of a reloaded configuration which can
+/// change at runtime, such as the logger filters.
+pub fn reload(config: &config::Config) -> Result<(), Error> {
+    reload_logging(config)
+}
+
 /// Creates a tonic::transport::Server from the configuration parameters.
 #[cfg(feature = "grpc")]
 pub fn new_grpc_server(args: &config::GrpcServer) -> tonic::transport::Server {
@@ -146,29 +318,247 @@ pub fn new_grpc_server(args: &config::GrpcServer) -> tonic::transport::Server {
     builder
 }
 
-/// Preparations before starting server
-pub fn pre_server(_service_config: &config::Service) {
+/// The service of the calls served over HTTP/1.1: the requests of the
+/// `http_routes`, compiled in with `#[mrbig_transcoding]`, are transcoded
+/// when `service.transcoding` is set, the others are routed with `routes`.
+///
+/// *Not supposed to be used outside of mrbig_derive macros.*
+#[cfg(feature = "grpc")]
+pub fn http_service(
+    service: &config::Service,
+    routes: layer::Routes,
+    http_routes: Option<transcode::HttpRoutes>,
+) -> Result<layer::BoxService, Error> {
+    let routes = layer::BoxService::new(routes);
+    match (&service.transcoding, http_routes) {
+        #[cfg(feature = "transcoding")]
+        (Some(transcoding), Some(http_routes)) => {
+            let transcoder = transcode::Transcoder::new(
+                routes,
+                http_routes.routes,
+                http_routes.descriptors,
+                transcoding,
+            )?;
+            Ok(layer::BoxService::new(transcoder))
+        }
+        #[cfg(not(feature = "transcoding"))]
+        (Some(_), Some(_)) => Err(Error::new(
+            "HTTP/JSON transcoding requires the `transcoding` feature of mrbig_core",
+        )),
+        (Some(_), None) => Err(Error::new(
+            "HTTP/JSON transcoding requires the `#[mrbig_transcoding]` attribute on the service",
+        )),
+        (None, _) => Ok(routes),
+    }
+}
+
+/// Serves the services of `router` with `listeners`, bound to the
+/// addresses of the `service` parameters, over TLS when
+/// `service.grpc_server.tls` is set, until `signal` completes. The calls
+/// served over HTTP/1.1 are answered by `http`.
+#[cfg(feature = "grpc")]
+pub async fn serve<A, B, F>(
+    router: tonic::transport::server::Router<A, B>,
+    http: layer::BoxService,
+    listeners: listen::Listeners,
+    service: &config::Service,
+    signal: F,
+) -> Result<(), Error>
+where
+    A: GrpcService + Clone + Send + 'static,
+    A::Future: Send + 'static,
+    A::Error: Into<Box<dyn std::error::Error + Send + Sync>> + Send,
+    B: GrpcService + Clone + Send + 'static,
+    B::Future: Send + 'static,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>> + Send,
+    F: std::future::Future<Output = ()>,
+{
+    // the socket files are removed when `_sockets` goes out of scope
+    let (incoming, _sockets) = listeners.into_parts();
+
+    let served = match &service.grpc_server.tls {
+        #[cfg(feature = "tls")]
+        Some(tls) => {
+            let incoming = tls::accept(incoming, tls, serves_http1(service))?;
+            serve_incoming(router, http, incoming, service, signal).await
+        }
+        #[cfg(not(feature = "tls"))]
+        Some(_) => return Err(Error::new("TLS requires the `tls` feature of mrbig_core")),
+        None => {
+            let incoming = futures::StreamExt::map(incoming, Ok::<_, std::io::Error>);
+            serve_incoming(router, http, incoming, service, signal).await
+        }
+    };
+
+    served.map_err(|e| Error::new(&format!("server error: {}", e)))
+}
+
+/// Whether the server speaks HTTP/1.1 as well, for the gRPC-Web calls or
+/// the HTTP/JSON ones.
+#[cfg(any(feature = "tls", feature = "web"))]
+fn serves_http1(service: &config::Service) -> bool {
+    service.grpc_web.is_some() || service.transcoding.is_some()
+}
+
+/// Serves the connections of `incoming`, over HTTP/1.1 as well when
+/// `service.grpc_web` or `service.transcoding` is set.
+#[cfg(feature = "grpc")]
+async fn serve_incoming<A, B, I, IO, F>(
+    router: tonic::transport::server::Router<A, B>,
+    http: layer::BoxService,
+    incoming: I,
+    service: &config::Service,
+    signal: F,
+) -> Result<(), tonic::transport::Error>
+where
+    A: GrpcService + Clone + Send + 'static,
+    A::Future: Send + 'static,
+    A::Error: Into<Box<dyn std::error::Error + Send + Sync>> + Send,
+    B: GrpcService + Clone + Send + 'static,
+    B::Future: Send + 'static,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>> + Send,
+    I: futures::Stream<Item = Result<IO, std::io::Error>>,
+    IO: tokio::io::AsyncRead
+        + tokio::io::AsyncWrite
+        + tonic::transport::server::Connected
+        + Unpin
+        + Send
+        + 'static,
+    F: std::future::Future<Output = ()>,
+{
+    #[cfg(feature = "web")]
+    {
+        if serves_http1(service) {
+            let grpc_server = &service.grpc_server;
+            return web::serve(router, http, incoming, grpc_server, signal).await;
+        }
+    }
+
+    let _ = (http, service);
+    router.serve_with_incoming_shutdown(incoming, signal).await
+}
+
+/// A service routed by the gRPC server.
+#[cfg(feature = "grpc")]
+pub trait GrpcService:
+    tonic::codegen::Service<
+    tonic::codegen::http::Request<tonic::transport::Body>,
+    Response = tonic::codegen::http::Response<tonic::body::BoxBody>,
+>
+{
+}
+
+#[cfg(feature = "grpc")]
+impl<S> GrpcService for S where
+    S: tonic::codegen::Service<
+        tonic::codegen::http::Request<tonic::transport::Body>,
+        Response = tonic::codegen::http::Response<tonic::body::BoxBody>,
+    >
+{
+}
+
+/// Preparations before starting server, failing when the metrics server
+/// cannot be started.
+pub fn pre_server(_service_config: &config::Service) -> Result<(), Error> {
     #[cfg(feature = "telemetry")]
     {
-        start_metrics_server(_service_config.metrics.clone());
+        start_metrics_server(_service_config.metrics.clone())?;
     }
+    Ok(())
 }
 
+/// Completes when the process receives SIGTERM or SIGINT.
 pub async fn trap_signal() {
-    use futures::future::FutureExt;
+    use futures::future::{select, Either};
     use tokio::signal::unix::{signal, SignalKind};
 
-    let mut stream = signal(SignalKind::terminate()).expect("unable to trap signal");
+    let mut term = signal(SignalKind::terminate()).expect("unable to trap SIGTERM");
+    let mut int = signal(SignalKind::interrupt()).expect("unable to trap SIGINT");
 
-    stream.recv().map(|o| o.unwrap_or(())).await
+    let received = match select(Box::pin(term.recv()), Box::pin(int.recv())).await {
+        Either::Left(_) => "SIGTERM",
+        Either::Right(_) => "SIGINT",
+    };
+    log::info!("received {}, shutting down", received);
 }
 
-/// Starts the metrics server with configuration parameters.
-///
-/// # Panics
+/// Traps SIGHUP for `trap_reload`, to be called before the server binds
+/// its addresses so that a SIGHUP received while starting does not kill
+/// the process. Returns `None`, which disables the reload, when it cannot.
+pub fn trap_hangup() -> Option<tokio::signal::unix::Signal> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    match signal(SignalKind::hangup()) {
+        Ok(hangup) => Some(hangup),
+        Err(e) => {
+            log::warn!("unable to trap SIGHUP, reload is disabled: {}", e);
+            None
+        }
+    }
+}
+
+/// Reloads the configuration of `target` each time the process
+/// receives SIGHUP on `hangup` (see `trap_hangup`), starting from the
+/// `current` configuration. The sources are read again on the blocking
+/// threads, so that the server is not held up meanwhile.
 ///
-/// Panics if the hostname is invalid or if the server fails to start.
+/// A failed reload is logged and leaves the current configuration
+/// in place. This future never completes.
+pub async fn trap_reload<'de, C>(
+    target: &mut C,
+    current: config::Config,
+    hangup: Option<tokio::signal::unix::Signal>,
+) where
+    C: config::Configurable<'de>,
+{
+    let mut current = current;
+
+    if let Some(mut hangup) = hangup {
+        while hangup.recv().await.is_some() {
+            log::info!("reloading configuration");
+
+            let loading = current.clone();
+            let reloaded = match tokio::task::spawn_blocking(move || loading.reload()).await {
+                Ok(loaded) => loaded.and_then(|config| target.apply_reload(&current, config)),
+                Err(e) => Err(Error::new(&format!("reload aborted: {}", e))),
+            };
+            match reloaded {
+                Ok(config) => current = config,
+                Err(e) => log::error!("failed to reload configuration: {}", e),
+            }
+        }
+    }
+
+    futures::future::pending::<()>().await
+}
+
+/// Drives the `server` future while reloading the configuration of
+/// `target` on SIGHUP (see `trap_reload`). Completes with the server.
+pub async fn with_reload<'de, C, F>(
+    target: &mut C,
+    config: config::Config,
+    hangup: Option<tokio::signal::unix::Signal>,
+    server: F,
+) -> F::Output
+where
+    C: config::Configurable<'de>,
+    F: std::future::Future,
+{
+    use futures::future::{select, Either};
+
+    let reload = trap_reload(target, config, hangup);
+
+    futures::pin_mut!(server, reload);
+
+    match select(server, reload).await {
+        Either::Left((output, _)) => output,
+        Either::Right(_) => unreachable!("reload never completes"),
+    }
+}
+
+/// Starts the metrics server with configuration parameters, failing if
+/// its hostname cannot be resolved or its address bound.
 #[cfg(feature = "telemetry")]
-fn start_metrics_server(config: metrics::Config) {
-    tokio::spawn(metrics::start_server(config));
+fn start_metrics_server(config: metrics::Config) -> Result<(), Error> {
+    metrics::start_server(config)
 }
@@ -0,0 +1,239 @@
+//! Listeners of the gRPC server.
+//!
+//! The server listens at every address of `service.listen`, or at
+//! `service.hostname` and `service.port` when there are none. Unix domain
+//! sockets are created with the permissions of `service.socket_mode`,
+//! replacing the stale sockets left by a previous run, and are removed
+//! once the server stops.
+//!
+//! Binding happens before serving, so TCP addresses may be given port 0:
+//! `Listeners::addresses` tells the ports picked by the system.
+use crate::config::{Listen, Service};
+use crate::error::Error;
+use futures::stream::{self, Stream, StreamExt};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tonic::transport::server::Connected;
+
+/// Pause after a failed accept, such as when running out of file
+/// descriptors, before accepting again.
+const ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// A connection with a client.
+pub(crate) enum Connection {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Connected for Connection {
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Connection::Tcp(stream) => stream.peer_addr().ok(),
+            #[cfg(unix)]
+            Connection::Unix(_) => None,
+        }
+    }
+}
+
+impl AsyncRead for Connection {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            Connection::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(unix)]
+            Connection::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Connection {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            Connection::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(unix)]
+            Connection::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Connection::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(unix)]
+            Connection::Unix(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Connection::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(unix)]
+            Connection::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+/// Socket files created by `bind`, removed when dropped.
+pub(crate) struct Sockets(Vec<PathBuf>);
+
+impl Drop for Sockets {
+    fn drop(&mut self) {
+        for path in &self.0 {
+            match std::fs::remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    log::warn!("failed to remove socket `{}`: {}", path.display(), e)
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Connections accepted by the listeners.
+pub(crate) type Incoming = Pin<Box<dyn Stream<Item = Connection> + Send>>;
+
+/// Listeners bound to the addresses of the service, ready to serve.
+pub struct Listeners {
+    incoming: Incoming,
+    sockets: Sockets,
+    addresses: Vec<Listen>,
+}
+
+impl Listeners {
+    /// Addresses the listeners are bound to, with the actual ports of
+    /// the TCP addresses given port 0.
+    pub fn addresses(&self) -> &[Listen] {
+        &self.addresses
+    }
+
+    /// Stream of the connections accepted on any of the addresses, and
+    /// the socket files to remove once done.
+    pub(crate) fn into_parts(self) -> (Incoming, Sockets) {
+        (self.incoming, self.sockets)
+    }
+}
+
+impl std::fmt::Debug for Listeners {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Listeners")
+            .field("addresses", &self.addresses)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Binds the addresses of `service` (see `Service::addresses`). Failed
+/// accepts are logged and skipped once serving.
+pub async fn bind(service: &Service) -> Result<Listeners, Error> {
+    let keepalive = service.grpc_server.tcp_keepalive;
+    let mut sockets = Sockets(vec![]);
+    let mut listeners: Vec<Incoming> = vec![];
+    let mut addresses = vec![];
+
+    for listen in service.addresses()? {
+        let fail = |e: std::io::Error| format!("cannot listen at `{}`: {}", listen, e);
+
+        let bound = match &listen {
+            Listen::Tcp(addr) => {
+                let listener = TcpListener::bind(addr).await.map_err(fail)?;
+                let bound = Listen::Tcp(listener.local_addr().map_err(fail)?);
+                let connections = accepted(listener, bound.clone()).filter_map(move |tcp| {
+                    let configured = tcp
+                        .set_nodelay(true)
+                        .and_then(|_| tcp.set_keepalive(keepalive));
+                    if let Err(e) = configured {
+                        log::warn!("failed to configure TCP connection: {}", e);
+                    }
+                    futures::future::ready(Some(Connection::Tcp(tcp)))
+                });
+                listeners.push(Box::pin(connections));
+                bound
+            }
+            #[cfg(unix)]
+            Listen::Unix(path) => {
+                use std::os::unix::fs::PermissionsExt;
+
+                remove_stale(path).map_err(|e| format!("cannot listen at `{}`: {}", listen, e))?;
+                let listener = UnixListener::bind(path).map_err(fail)?;
+                sockets.0.push(path.clone());
+
+                let permissions = std::fs::Permissions::from_mode(service.socket_mode);
+                std::fs::set_permissions(path, permissions).map_err(fail)?;
+
+                listeners.push(Box::pin(
+                    accepted(listener, listen.clone()).map(Connection::Unix),
+                ));
+                listen.clone()
+            }
+            #[cfg(not(unix))]
+            Listen::Unix(_) => {
+                return Err(Error::new(&format!(
+                    "cannot listen at `{}`: Unix domain sockets are not supported here",
+                    listen
+                )));
+            }
+        };
+
+        log::info!("listening at {}", bound);
+        addresses.push(bound);
+    }
+
+    Ok(Listeners {
+        incoming: Box::pin(stream::select_all(listeners)),
+        sockets,
+        addresses,
+    })
+}
+
+/// Connections accepted by `listener`, logging and skipping the errors.
+fn accepted<L, T>(listener: L, listen: Listen) -> impl Stream<Item = T>
+where
+    L: Stream<Item = std::io::Result<T>>,
+{
+    listener.filter_map(move |conn| {
+        let listen = listen.clone();
+        async move {
+            match conn {
+                Ok(conn) => Some(conn),
+                Err(e) => {
+                    log::warn!("failed to accept connection at {}: {}", listen, e);
+                    tokio::time::delay_for(ACCEPT_BACKOFF).await;
+                    None
+                }
+            }
+        }
+    })
+}
+
+/// Removes the socket at `path` if no server listens to it anymore.
+#[cfg(unix)]
+fn remove_stale(path: &std::path::Path) -> Result<(), String> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return Ok(()),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err("the file exists and is not a socket".into());
+    }
+    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+        return Err("another server listens to the socket".into());
+    }
+
+    log::debug!("removing stale socket `{}`", path.display());
+    std::fs::remove_file(path).map_err(|e| e.to_string())
+}
@@ -1,14 +1,20 @@
-use serde_derive::Deserialize;
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
 use prometheus::{Encoder, TextEncoder};
 use hyper::{
     header::CONTENT_TYPE,
     service::{make_service_fn, service_fn},
     Body, Request, Response, Server,
 };
+use std::net::ToSocketAddrs;
 
-#[derive(Clone, Debug, Deserialize)]
+/// Metrics server related configuration parameters.
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[serde(default)]
 pub struct Config {
+    /// Hostname to bind to when serving the metrics.
     pub hostname: String,
+    /// Port number to bind when serving the metrics.
     pub port: u16,
 }
 
@@ -37,13 +43,26 @@ async fn serve_req(_req: Request<Body>) -> Result<Response<Body>, hyper::Error>
     Ok(response)
 }
 
-pub(crate) async fn start_server(config: Config) {
-    let address = format!("{}:{}", config.hostname, config.port).parse().expect("bad address");
+/// Binds the metrics server, failing when its address cannot be resolved
+/// or bound, then spawns it.
+pub(crate) fn start_server(config: Config) -> Result<(), crate::error::Error> {
+    let address = (config.hostname.as_str(), config.port)
+        .to_socket_addrs()
+        .map_err(|e| format!("cannot resolve `{}`: {}", config.hostname, e))?
+        .next()
+        .ok_or_else(|| format!("`{}` resolves to no address", config.hostname))?;
 
-    Server::bind(&address)
-	.serve(make_service_fn(|_| async {
+    let server = Server::try_bind(&address)
+        .map_err(|e| format!("metrics server cannot bind {}: {}", address, e))?
+        .serve(make_service_fn(|_| async {
             Ok::<_, hyper::Error>(service_fn(serve_req))
-	}))
-	.await
-	.expect("server error");
+        }));
+
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            log::error!("metrics server error: {}", e);
+        }
+    });
+
+    Ok(())
 }
@@ -0,0 +1,111 @@
+//! Handle on a running gRPC server.
+//!
+//! The `start()` method generated by `#[derive(Run)]` binds the server
+//! and returns right away with a `ServerHandle`, which tells the bound
+//! addresses, shuts the server down and waits for it to stop. `run()` is
+//! `start()` followed by `join()`. Ports can be left to the system with
+//! port 0, as in tests:
+//!
+//! ```ignore
+//! let args = vec!["micro".into(), "--port".into(), "0".into()];
+//! service.init_with_args(args).await?;
+//!
+//! let server = service.start(MyGreeter::default()).await?;
+//! let url = format!("http://{}", server.local_addr().unwrap());
+//! // (...)
+//! server.shutdown();
+//! server.join().await?;
+//! ```
+use crate::config::Listen;
+use crate::error::Error;
+use futures::channel::oneshot;
+use futures::future::{self, BoxFuture, Either, FutureExt};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+
+/// A running gRPC server.
+#[derive(Debug)]
+pub struct ServerHandle {
+    addresses: Vec<Listen>,
+    shutdown: Mutex<Option<oneshot::Sender<()>>>,
+    join: tokio::task::JoinHandle<Result<(), Error>>,
+}
+
+impl ServerHandle {
+    /// Spawns the server future returned by `serve`, which serves at
+    /// `addresses` until the future it is given completes: on SIGTERM,
+    /// SIGINT or a call to `shutdown`.
+    ///
+    /// *Not supposed to be used outside of mrbig_derive macros.*
+    pub fn spawn<S, F>(addresses: Vec<Listen>, serve: S) -> Self
+    where
+        S: FnOnce(BoxFuture<'static, ()>) -> F,
+        F: Future<Output = Result<(), Error>> + Send + 'static,
+    {
+        let (shutdown, requested) = oneshot::channel();
+
+        let signal = async move {
+            let signal = crate::trap_signal().boxed();
+            match future::select(signal, requested).await {
+                Either::Left(_) => {}
+                Either::Right((Ok(()), _)) => log::info!("shutdown requested, shutting down"),
+                // the handle was dropped, the server is left running
+                Either::Right((Err(_), signal)) => signal.await,
+            }
+        };
+
+        ServerHandle {
+            addresses,
+            shutdown: Mutex::new(Some(shutdown)),
+            join: tokio::spawn(serve(signal.boxed())),
+        }
+    }
+
+    /// Addresses the server is bound to, with the actual ports of the TCP
+    /// addresses given port 0.
+    pub fn addresses(&self) -> &[Listen] {
+        &self.addresses
+    }
+
+    /// First TCP address the server is bound to, if any.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.addresses.iter().find_map(|listen| match listen {
+            Listen::Tcp(addr) => Some(*addr),
+            _ => None,
+        })
+    }
+
+    /// Starts shutting the server down gracefully, as on SIGTERM. Returns
+    /// right away, `join` waits for the server to stop.
+    pub fn shutdown(&self) {
+        let shutdown = self.shutdown.lock().expect("shutdown lock poisoned").take();
+        if let Some(shutdown) = shutdown {
+            let _ = shutdown.send(());
+        }
+    }
+
+    /// Waits for the server to stop, once shut down.
+    pub async fn join(self) -> Result<(), Error> {
+        self.join
+            .await
+            .map_err(|e| Error::new(&format!("server task failed: {}", e)))?
+    }
+}
+
+/// A service derived with `#[derive(Run)]`, started from command line
+/// arguments and its handlers, a tuple in the order of the
+/// `#[mrbig_register_grpc]` attributes, such as `(MyGreeter::default(),)`.
+///
+/// The derived `init_with_args()` and `start()` are specific to each
+/// service, this lets generic code, such as `testing::TestServer::with`,
+/// start any of them.
+pub trait Start<H>: Sized {
+    /// Initializes the service with `args` then starts it with `handlers`,
+    /// failing when `args` ask to exit, as `--help` does.
+    fn start_with_args(
+        self,
+        args: Vec<String>,
+        handlers: H,
+    ) -> BoxFuture<'static, Result<ServerHandle, Error>>;
+}
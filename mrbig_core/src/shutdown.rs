@@ -0,0 +1,145 @@
+//! Graceful shutdown of the gRPC server.
+//!
+//! On SIGTERM or SIGINT, the service goes through these phases, each of
+//! them logged:
+//!
+//! 1. every service whose health is reported is set to `NOT_SERVING`;
+//! 2. the server keeps serving for `service.shutdown_grace`, so that load
+//!    balancers notice and stop sending new requests;
+//! 3. the server stops accepting connections and drains the in-flight
+//!    requests, for up to `service.drain_timeout`, after which the
+//!    remaining ones are abandoned;
+//! 4. the hooks registered with `Context::on_shutdown` run, in order.
+#[cfg(feature = "grpc")]
+use crate::{config::Service, context::HealthReporter, error::Error};
+#[cfg(feature = "grpc")]
+use futures::future::{self, Either};
+use futures::future::{BoxFuture, FutureExt};
+use std::future::Future;
+use std::sync::Mutex;
+
+type Hook = Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>;
+
+/// Hooks run once the server has stopped.
+#[derive(Default)]
+pub struct ShutdownHooks(Mutex<Vec<Hook>>);
+
+impl ShutdownHooks {
+    /// Registers `hook`, to be run after the hooks registered so far.
+    pub fn push<F, Fut>(&self, hook: F)
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.hooks().push(Box::new(move || hook().boxed()));
+    }
+
+    /// Runs the registered hooks in order, removing them.
+    pub async fn run(&self) {
+        let hooks: Vec<Hook> = self.hooks().drain(..).collect();
+
+        for (i, hook) in hooks.into_iter().enumerate() {
+            log::info!("running shutdown hook {}", i + 1);
+            hook().await;
+        }
+    }
+
+    fn hooks(&self) -> std::sync::MutexGuard<'_, Vec<Hook>> {
+        self.0.lock().expect("shutdown hooks lock poisoned")
+    }
+}
+
+impl std::fmt::Debug for ShutdownHooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "ShutdownHooks({})", self.hooks().len())
+    }
+}
+
+/// Drives the server returned by `serve` until `signal` completes, then
+/// shuts it down gracefully (see the module documentation), setting the
+/// services of `health` to `NOT_SERVING` first. `serve` is given the
+/// future which tells the server to stop accepting connections.
+#[cfg(feature = "grpc")]
+pub async fn graceful<S, F, T>(
+    serve: S,
+    signal: T,
+    service: &Service,
+    health: Vec<HealthReporter>,
+) -> Result<(), Error>
+where
+    S: FnOnce(BoxFuture<'static, ()>) -> F,
+    F: Future<Output = Result<(), Error>>,
+    T: Future<Output = ()> + Send + 'static,
+{
+    let (grace, drain_timeout) = (service.shutdown_grace, service.drain_timeout);
+    let (draining, drain_started) = futures::channel::oneshot::channel();
+
+    let stop_accepting = async move {
+        signal.await;
+
+        for reporter in &health {
+            reporter.set_not_serving().await;
+        }
+        log::info!(
+            "shutting down: health set to NOT_SERVING for {} services",
+            health.len()
+        );
+
+        if grace > std::time::Duration::from_secs(0) {
+            log::info!("shutting down: waiting {:?} before draining", grace);
+            tokio::time::delay_for(grace).await;
+        }
+
+        log::info!(
+            "shutting down: draining in-flight requests for up to {:?}",
+            drain_timeout
+        );
+        let _ = draining.send(());
+    };
+
+    let drain_timeout = async move {
+        match drain_started.await {
+            Ok(()) => tokio::time::delay_for(drain_timeout).await,
+            // the server stopped on its own
+            Err(_) => future::pending().await,
+        }
+    };
+
+    let server = serve(stop_accepting.boxed());
+    futures::pin_mut!(server, drain_timeout);
+
+    match future::select(server, drain_timeout).await {
+        Either::Left((served, _)) => {
+            log::info!("shutting down: server stopped");
+            served
+        }
+        Either::Right(_) => {
+            log::warn!(
+                "shutting down: drain timeout elapsed, the remaining requests are abandoned"
+            );
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn hooks_run_in_order_once() {
+        let hooks = ShutdownHooks::default();
+        let ran = Arc::new(Mutex::new(vec![]));
+
+        for i in 1..=3 {
+            let ran = ran.clone();
+            hooks.push(move || async move { ran.lock().unwrap().push(i) });
+        }
+        assert_eq!(format!("{:?}", hooks), "ShutdownHooks(3)");
+
+        futures::executor::block_on(hooks.run());
+        futures::executor::block_on(hooks.run());
+        assert_eq!(*ran.lock().unwrap(), vec![1, 2, 3]);
+    }
+}
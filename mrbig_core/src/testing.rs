@@ -0,0 +1,158 @@
+//! Harness to run services in tests, behind the `testing` feature.
+//!
+//! A `TestServer` starts a service derived with `#[derive(Run)]` on a port
+//! left to the system, connects to it and hands out the clients to call it.
+//! The server is shut down when the `TestServer` is dropped, or with `stop`
+//! to wait for it to be gone:
+//!
+//! ```ignore
+//! use mrbig_core::testing::TestServer;
+//!
+//! #[tokio::test]
+//! async fn says_hello() {
+//!     let server = TestServer::with(Micro::default(), (MyGreeter::default(),))
+//!         .await
+//!         .unwrap();
+//!
+//!     let mut client = GreeterClient::new(server.channel());
+//!     // (...)
+//!     server.stop().await.unwrap();
+//! }
+//! ```
+//!
+//! `TestServer::start` takes the future of `start` instead, for services
+//! initialized with other arguments than `args(&[])`.
+//!
+//! The service is called over TCP on the loopback interface: tokio 0.2 has
+//! no in-memory duplex stream to serve and connect through, so the tests
+//! need a network stack, and a port each.
+use crate::error::Error;
+use crate::server::{ServerHandle, Start};
+use std::future::Future;
+use std::net::SocketAddr;
+use tonic::transport::{Channel, Endpoint};
+use tonic_health::ServingStatus;
+
+/// Types generated from the grpc.health.v1 proto, client included.
+pub mod health {
+    tonic::include_proto!("grpc.health.v1");
+}
+
+pub use grpc_reflection::proto::server_reflection_client::ServerReflectionClient;
+pub use health::health_client::HealthClient;
+
+/// Command line arguments serving at 127.0.0.1 on a port left to the
+/// system, metrics included, followed by `extra`, to be given to
+/// `init_with_args`.
+pub fn args(extra: &[&str]) -> Vec<String> {
+    let mut args = vec!["test", "--hostname", "127.0.0.1", "--port", "0"];
+    if cfg!(feature = "telemetry") {
+        args.extend(vec!["--set", "service.metrics.port=0"]);
+    }
+    args.into_iter()
+        .chain(extra.iter().copied())
+        .map(String::from)
+        .collect()
+}
+
+/// A service running in the test process, shut down when dropped.
+#[derive(Debug)]
+pub struct TestServer {
+    handle: Option<ServerHandle>,
+    addr: SocketAddr,
+    channel: Channel,
+}
+
+impl TestServer {
+    /// Initializes `micro` with `args(&[])`, starts it with `handlers`, a
+    /// tuple in the order of its `#[mrbig_register_grpc]` attributes, then
+    /// connects to it.
+    pub async fn with<M, H>(micro: M, handlers: H) -> Result<Self, Error>
+    where
+        M: Start<H>,
+    {
+        Self::start(micro.start_with_args(args(&[]), handlers)).await
+    }
+
+    /// Waits for the service started by `start`, which should initialize
+    /// it with `args` and return `start(handlers)`, then connects to it.
+    pub async fn start<F>(start: F) -> Result<Self, Error>
+    where
+        F: Future<Output = Result<ServerHandle, Error>>,
+    {
+        let handle = start.await?;
+        let addr = handle
+            .local_addr()
+            .ok_or_else(|| Error::new("the service is not bound to a TCP address"))?;
+
+        let channel = Endpoint::from_shared(format!("http://{}", addr))
+            .map_err(|e| Error::new(&format!("invalid address {}: {}", addr, e)))?
+            .connect()
+            .await
+            .map_err(|e| Error::new(&format!("failed to connect to {}: {}", addr, e)))?;
+
+        Ok(TestServer {
+            handle: Some(handle),
+            addr,
+            channel,
+        })
+    }
+
+    /// Address the service is bound to.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Channel connected to the service, to build clients from.
+    pub fn channel(&self) -> Channel {
+        self.channel.clone()
+    }
+
+    /// Client of the service's health server.
+    pub fn health(&self) -> HealthClient<Channel> {
+        HealthClient::new(self.channel())
+    }
+
+    /// Client of the service's reflection server.
+    pub fn reflection(&self) -> ServerReflectionClient<Channel> {
+        ServerReflectionClient::new(self.channel())
+    }
+
+    /// Health status of `service`, `""` standing for the whole server.
+    pub async fn health_status(&self, service: &str) -> Result<ServingStatus, Error> {
+        let request = health::HealthCheckRequest {
+            service: service.into(),
+        };
+        let response = self
+            .health()
+            .check(request)
+            .await
+            .map_err(|e| Error::new(&format!("health check failed: {}", e)))?;
+
+        use health::health_check_response::ServingStatus as Status;
+        Ok(match Status::from_i32(response.into_inner().status) {
+            Some(Status::Serving) => ServingStatus::Serving,
+            Some(Status::NotServing) => ServingStatus::NotServing,
+            _ => ServingStatus::Unknown,
+        })
+    }
+
+    /// Shuts the service down and waits for it to stop.
+    pub async fn stop(mut self) -> Result<(), Error> {
+        match self.handle.take() {
+            Some(handle) => {
+                handle.shutdown();
+                handle.join().await
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        if let Some(handle) = &self.handle {
+            handle.shutdown();
+        }
+    }
+}
@@ -0,0 +1,283 @@
+//! TLS and mutual TLS for the gRPC server.
+//!
+//! When `service.grpc_server.tls` is set, the server only accepts TLS
+//! connections, using the certificate and key read from the given files.
+//! Setting `client_ca` requires clients to present a certificate signed by
+//! one of its certificate authorities (mutual TLS), whose identity is then
+//! available to the handlers with `PeerIdentity::of(&request)`.
+//!
+//! The files are checked for changes every `reload_interval`: renewed
+//! certificates are used for the new connections, without a restart.
+use crate::config::Tls;
+use crate::error::Error;
+use crate::listen::{self, Incoming};
+use futures::future::ready;
+use futures::stream::{Stream, StreamExt};
+use std::fs::File;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock, Weak};
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::rustls::internal::pemfile;
+use tokio_rustls::rustls::{
+    AllowAnyAuthenticatedClient, Certificate, NoClientAuth, PrivateKey, RootCertStore,
+    ServerConfig, Session,
+};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+use tonic::transport::server::Connected;
+
+/// Time allowed to clients to complete the handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Handshakes in progress at once, the next connections wait.
+const CONCURRENT_HANDSHAKES: usize = 64;
+
+/// Identity of a client authenticated by its certificate, with mutual TLS.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerIdentity {
+    /// Common name of the certificate subject.
+    pub common_name: Option<String>,
+    /// DNS names, URIs (such as SPIFFE IDs) and email addresses of the
+    /// certificate subject alternative names.
+    pub alt_names: Vec<String>,
+    /// DER encoded certificates presented by the client, leaf first.
+    pub certificates: Vec<Vec<u8>>,
+}
+
+impl PeerIdentity {
+    /// Identity of the client which sent `request`, if it presented a
+    /// certificate.
+    pub fn of<T>(request: &tonic::Request<T>) -> Option<Self> {
+        // tonic wraps the DER encoded certificates, despite the name
+        let certificates = request
+            .peer_certs()?
+            .iter()
+            .map(|cert| cert.get_ref().to_vec())
+            .collect();
+        Self::from_der(certificates)
+    }
+
+    /// Identity described by the DER encoded `certificates`, leaf first.
+    pub fn from_der(certificates: Vec<Vec<u8>>) -> Option<Self> {
+        use x509_parser::extensions::GeneralName;
+
+        let (_, leaf) = x509_parser::parse_x509_certificate(certificates.first()?).ok()?;
+
+        let common_name = leaf
+            .subject()
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+            .map(String::from);
+
+        let alt_names = match leaf.subject_alternative_name() {
+            Ok(Some(ext)) => ext
+                .value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::DNSName(name)
+                    | GeneralName::URI(name)
+                    | GeneralName::RFC822Name(name) => Some(name.to_string()),
+                    _ => None,
+                })
+                .collect(),
+            _ => vec![],
+        };
+
+        Some(PeerIdentity {
+            common_name,
+            alt_names,
+            certificates,
+        })
+    }
+}
+
+/// A TLS connection with a client, which tells tonic the client's
+/// address and certificates.
+pub(crate) struct Connection(TlsStream<listen::Connection>);
+
+impl Connected for Connection {
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        self.0.get_ref().0.remote_addr()
+    }
+
+    fn peer_certs(&self) -> Option<Vec<tonic::transport::Certificate>> {
+        let certs = self.0.get_ref().1.get_peer_certificates()?;
+        // as tonic does, the DER encoded certificate is wrapped as is
+        Some(
+            certs
+                .into_iter()
+                .map(|cert| tonic::transport::Certificate::from_pem(cert.0))
+                .collect(),
+        )
+    }
+}
+
+impl AsyncRead for Connection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Connection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+/// Establishes TLS connections with the clients of `connections`,
+/// skipping the failed handshakes, which are logged. HTTP/1.1 is offered
+/// along HTTP/2 when `http1` is set.
+pub(crate) fn accept(
+    connections: Incoming,
+    tls: &Tls,
+    http1: bool,
+) -> Result<impl Stream<Item = Result<Connection, std::io::Error>>, Error> {
+    let config = server_config(tls, http1)?;
+    let acceptor = Arc::new(RwLock::new(TlsAcceptor::from(Arc::new(config))));
+
+    // the watcher stops once the server drops the stream
+    tokio::spawn(watch(tls.clone(), http1, Arc::downgrade(&acceptor)));
+
+    let connections = connections
+        .map(move |conn| {
+            let acceptor = acceptor.read().expect("TLS acceptor lock poisoned").clone();
+            tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(conn))
+        })
+        .buffer_unordered(CONCURRENT_HANDSHAKES)
+        .filter_map(|handshake| {
+            ready(match handshake {
+                Ok(Ok(stream)) => Some(Ok(Connection(stream))),
+                Ok(Err(e)) => {
+                    log::debug!("TLS handshake failed: {}", e);
+                    None
+                }
+                Err(_) => {
+                    log::debug!("TLS handshake timed out");
+                    None
+                }
+            })
+        });
+
+    Ok(connections)
+}
+
+/// Replaces the acceptor each time the files of `tls` change.
+async fn watch(tls: Tls, http1: bool, acceptor: Weak<RwLock<TlsAcceptor>>) {
+    let mut stamps = modified(&tls);
+
+    loop {
+        tokio::time::delay_for(tls.reload_interval).await;
+
+        let acceptor = match acceptor.upgrade() {
+            Some(acceptor) => acceptor,
+            None => return,
+        };
+
+        let current = modified(&tls);
+        if current == stamps {
+            continue;
+        }
+        stamps = current;
+
+        match server_config(&tls, http1) {
+            Ok(config) => {
+                *acceptor.write().expect("TLS acceptor lock poisoned") =
+                    TlsAcceptor::from(Arc::new(config));
+                log::info!("reloaded TLS certificates");
+            }
+            Err(e) => log::error!("failed to reload TLS certificates: {}", e),
+        }
+    }
+}
+
+/// Modification times of the files of `tls`.
+fn modified(tls: &Tls) -> Vec<Option<SystemTime>> {
+    std::iter::once(&tls.cert)
+        .chain(std::iter::once(&tls.key))
+        .chain(tls.client_ca.iter())
+        .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+/// Reads the files of `tls` into a server configuration.
+fn server_config(tls: &Tls, http1: bool) -> Result<ServerConfig, String> {
+    let certs = certificates(&tls.cert)?;
+    let key = private_key(&tls.key)?;
+
+    let verifier = match &tls.client_ca {
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in certificates(path)? {
+                roots
+                    .add(&cert)
+                    .map_err(|e| format!("`{}`: invalid certificate: {}", path.display(), e))?;
+            }
+            AllowAnyAuthenticatedClient::new(roots)
+        }
+        None => NoClientAuth::new(),
+    };
+
+    let mut config = ServerConfig::new(verifier);
+    config.set_single_cert(certs, key).map_err(|e| {
+        let (cert, key) = (tls.cert.display(), tls.key.display());
+        format!("`{}` and `{}`: {}", cert, key, e)
+    })?;
+    if http1 {
+        // the first protocol of the server the client offers is picked:
+        // the clients offering both, as browsers do, speak HTTP/2 and their
+        // calls go to the gRPC server, so only the HTTP/1.1 clients get the
+        // transcoded routes
+        config.set_protocols(&[b"h2".to_vec(), b"http/1.1".to_vec()]);
+    } else {
+        config.set_protocols(&[b"h2".to_vec()]);
+    }
+
+    Ok(config)
+}
+
+fn certificates(path: &Path) -> Result<Vec<Certificate>, String> {
+    match pemfile::certs(&mut reader(path)?) {
+        Ok(certs) if !certs.is_empty() => Ok(certs),
+        _ => Err(format!("`{}` holds no PEM certificate", path.display())),
+    }
+}
+
+fn private_key(path: &Path) -> Result<PrivateKey, String> {
+    let pkcs8 = pemfile::pkcs8_private_keys(&mut reader(path)?).unwrap_or_default();
+    let rsa = pemfile::rsa_private_keys(&mut reader(path)?).unwrap_or_default();
+
+    pkcs8
+        .into_iter()
+        .chain(rsa)
+        .next()
+        .ok_or_else(|| format!("`{}` holds no PEM private key", path.display()))
+}
+
+fn reader(path: &Path) -> Result<BufReader<File>, String> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| format!("cannot read `{}`: {}", path.display(), e))
+}
@@ -0,0 +1,480 @@
+//! HTTP/JSON transcoding of the `google.api.http` annotations.
+//!
+//! `mrbig_build::compile_protos` generates an `HttpRoute` for each binding
+//! of the methods annotated with `google.api.http`, compiled in the
+//! services deriving `Run` with the `#[mrbig_transcoding]` attribute. When
+//! `service.transcoding` is set, which requires the `transcoding` feature,
+//! the server answers the HTTP/1.1 requests matching these routes with
+//! JSON, alongside the gRPC calls at the same addresses:
+//!
+//! ```proto
+//! import "google/api/annotations.proto";
+//!
+//! service Hotel {
+//!     rpc Rates(HotelRequest) returns (HotelResponse) {
+//!         option (google.api.http) = {
+//!             get: "/v1/hotels/{hotel_id}/rates"
+//!             additional_bindings { post: "/v1/rates:search" body: "*" }
+//!         };
+//!     }
+//! }
+//! ```
+//!
+//! The request message is made of the fields bound by the path, of the
+//! JSON body as set by `body`, and of the query parameters unless the body
+//! makes the whole message. The query parameters naming no field are
+//! ignored. The response message, or its `response_body` field, is written
+//! as JSON; the responses of the methods streaming them are written as a
+//! JSON array.
+//!
+//! The calls go through the layers and the interceptors of the services,
+//! with the headers of the HTTP requests as metadata. A failed call is
+//! answered with the HTTP status of its gRPC status, as of
+//! `google.rpc.Code`, and a `{"code": ..., "message": ...}` body.
+//!
+//! The routes are only served over HTTP/1.1: the HTTP/2 connections go to
+//! the gRPC server, which answers the JSON requests with `UNIMPLEMENTED`.
+//! Over TLS, the clients offering both protocols speak HTTP/2.
+
+#[cfg(feature = "transcoding")]
+mod codec;
+#[cfg(feature = "transcoding")]
+mod template;
+
+#[cfg(feature = "transcoding")]
+pub(crate) use transcoder::Transcoder;
+
+/// An HTTP route of a `google.api.http` annotation.
+///
+/// *Not supposed to be used outside of the code generated by mrbig_build.*
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HttpRoute {
+    /// HTTP method, as in `GET`.
+    pub verb: &'static str,
+    /// Path template, as in `/v1/hotels/{hotel_id}/rates`.
+    pub pattern: &'static str,
+    /// Fully qualified method, as in `hotel.Hotel.Rates`.
+    pub method: &'static str,
+    /// Field of the request set by the body, `*` for the whole message,
+    /// empty for none.
+    pub body: &'static str,
+    /// Field of the response written as the body, empty for the whole
+    /// message.
+    pub response_body: &'static str,
+}
+
+/// The HTTP routes generated by mrbig_build, with the descriptors of the
+/// messages of their methods.
+///
+/// *Not supposed to be used outside of mrbig_derive macros.*
+#[derive(Clone, Copy, Debug)]
+pub struct HttpRoutes {
+    /// Routes of the annotated methods.
+    pub routes: &'static [HttpRoute],
+    /// Encoded `FileDescriptorSet` of the annotated methods.
+    pub descriptors: &'static [u8],
+}
+
+#[cfg(feature = "transcoding")]
+mod transcoder {
+    use super::codec::{json_name, Pool};
+    use super::template::{self, Template};
+    use super::HttpRoute;
+    use crate::config;
+    use crate::error::Error;
+    use crate::layer::{self, BoxError, BoxService};
+    use futures::future::{BoxFuture, FutureExt};
+    use hyper::body::HttpBody;
+    use prost_types::field_descriptor_proto::Label;
+    use serde_json::{json, Map, Value};
+    use std::sync::Arc;
+    use std::task::{Context, Poll};
+    use tonic::body::BoxBody;
+    use tonic::codegen::http::{self, header, HeaderMap, HeaderValue, Method, StatusCode};
+    use tonic::transport::Body;
+    use tonic::Code;
+    use tower::{Service, ServiceExt};
+
+    /// Headers of the HTTP requests not handed to the gRPC services.
+    const SKIPPED_HEADERS: &[&str] = &[
+        "host",
+        "connection",
+        "keep-alive",
+        "upgrade",
+        "te",
+        "transfer-encoding",
+        "content-type",
+        "content-length",
+        "accept",
+        "accept-encoding",
+    ];
+
+    /// A route of the transcoder, with its parsed template.
+    #[derive(Debug)]
+    struct Rule {
+        route: HttpRoute,
+        template: Template,
+        path: String,
+    }
+
+    /// The service answering the HTTP/JSON requests of the `google.api.http`
+    /// routes, the other requests going to the inner one.
+    #[derive(Clone)]
+    pub(crate) struct Transcoder {
+        inner: BoxService,
+        rules: Arc<Vec<Rule>>,
+        pool: Arc<Pool>,
+        emit_defaults: bool,
+    }
+
+    impl Transcoder {
+        /// Transcodes the requests of `routes` into calls to `inner`, the
+        /// messages being described by the encoded `FileDescriptorSet` of
+        /// `descriptors`.
+        pub(crate) fn new(
+            inner: BoxService,
+            routes: &[HttpRoute],
+            descriptors: &[u8],
+            transcoding: &config::Transcoding,
+        ) -> Result<Self, Error> {
+            let pool = match routes.is_empty() {
+                true => Pool::default(),
+                false => Pool::decode(descriptors).map_err(|e| Error::new(&e))?,
+            };
+
+            let mut rules = vec![];
+            for route in routes {
+                let template = Template::parse(route.pattern).map_err(|e| Error::new(&e))?;
+                if pool.method(route.method).is_none() {
+                    return Err(Error::new(&format!(
+                        "no descriptor of the method `{}`",
+                        route.method
+                    )));
+                }
+                let path = match route.method.rsplit_once('.') {
+                    Some((service, method)) => format!("/{}/{}", service, method),
+                    None => format!("/{}", route.method),
+                };
+                rules.push(Rule {
+                    route: *route,
+                    template,
+                    path,
+                });
+            }
+
+            Ok(Transcoder {
+                inner,
+                rules: Arc::new(rules),
+                pool: Arc::new(pool),
+                emit_defaults: transcoding.emit_defaults,
+            })
+        }
+    }
+
+    impl Service<layer::Request> for Transcoder {
+        type Response = layer::Response;
+        type Error = BoxError;
+        type Future = BoxFuture<'static, Result<layer::Response, BoxError>>;
+
+        fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+            self.inner.poll_ready(cx)
+        }
+
+        fn call(&mut self, request: layer::Request) -> Self::Future {
+            let content_type = request
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok());
+            // gRPC-Web calls included
+            let grpc = matches!(content_type, Some(value) if value.starts_with("application/grpc"));
+            if grpc || request.method() == Method::OPTIONS {
+                return self.inner.call(request);
+            }
+
+            let this = self.clone();
+            async move {
+                let response = match this.transcode(request).await {
+                    Ok(response) => response,
+                    Err((code, message)) => error(code, &message),
+                };
+                Ok(response)
+            }
+            .boxed()
+        }
+    }
+
+    type Failure = (Code, String);
+
+    impl Transcoder {
+        async fn transcode(self, request: layer::Request) -> Result<layer::Response, Failure> {
+            let path = request.uri().path().to_string();
+            let (rule, bindings) = self
+                .rules
+                .iter()
+                .filter(|rule| rule.route.verb == request.method().as_str())
+                .find_map(|rule| Some((rule, rule.template.matches(&path)?)))
+                .ok_or_else(|| {
+                    let message = format!("no route for {} {}", request.method(), path);
+                    (Code::NotFound, message)
+                })?;
+            let method = self.pool.method(rule.route.method).expect("unknown method");
+
+            let query = request.uri().query().unwrap_or_default().to_string();
+            let (parts, body) = request.into_parts();
+            let body = hyper::body::to_bytes(body)
+                .await
+                .map_err(|e| (Code::Internal, format!("failed to read the body: {}", e)))?;
+
+            // the body, then the query parameters and the path, bound over it
+            let mut message = match rule.route.body {
+                "" => Value::Object(Map::new()),
+                "*" => parse(&body)?,
+                field => {
+                    let mut message = Value::Object(Map::new());
+                    self.bind(&method.input, &mut message, field, parse(&body)?)?;
+                    message
+                }
+            };
+            if rule.route.body != "*" {
+                for (field, value) in template::query_pairs(&query) {
+                    if self.resolve(&method.input, &field).is_some() {
+                        self.bind(&method.input, &mut message, &field, Value::String(value))?;
+                    }
+                }
+            }
+            for (field, value) in bindings {
+                self.bind(&method.input, &mut message, field, Value::String(value))?;
+            }
+
+            let encoded = self
+                .pool
+                .encode(&method.input, &message)
+                .map_err(|e| (Code::InvalidArgument, e))?;
+            let mut frame = Vec::with_capacity(encoded.len() + 5);
+            frame.push(0);
+            frame.extend_from_slice(&(encoded.len() as u32).to_be_bytes());
+            frame.extend(encoded);
+
+            let mut call = http::Request::post(rule.path.as_str())
+                .body(Body::from(frame))
+                .expect("invalid request");
+            for (name, value) in &parts.headers {
+                if !SKIPPED_HEADERS.contains(&name.as_str()) {
+                    call.headers_mut().append(name, value.clone());
+                }
+            }
+            let headers = call.headers_mut();
+            headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/grpc"),
+            );
+            headers.insert(header::TE, HeaderValue::from_static("trailers"));
+
+            let response = self
+                .inner
+                .clone()
+                .oneshot(call)
+                .await
+                .map_err(|e| (Code::Internal, e.to_string()))?;
+
+            let (parts, mut body) = response.into_parts();
+            let mut messages = vec![];
+            let mut data = vec![];
+            if grpc_status(&parts.headers).is_none() {
+                while let Some(chunk) = body.data().await {
+                    let chunk = chunk.map_err(|status| (status.code(), status.message().into()))?;
+                    data.extend_from_slice(&chunk);
+                }
+                let mut frames = &data[..];
+                while frames.len() >= 5 {
+                    let mut len = [0; 4];
+                    len.copy_from_slice(&frames[1..5]);
+                    let len = u32::from_be_bytes(len) as usize;
+                    let end = (5 + len).min(frames.len());
+                    messages.push(&frames[5..end]);
+                    frames = &frames[end..];
+                }
+            }
+            let trailers = match grpc_status(&parts.headers) {
+                Some(_) => parts.headers,
+                None => body
+                    .trailers()
+                    .await
+                    .map_err(|status| (status.code(), status.message().into()))?
+                    .unwrap_or_default(),
+            };
+            let (code, message) =
+                grpc_status(&trailers).unwrap_or((Code::Unknown, "no grpc-status".into()));
+            if code != Code::Ok {
+                return Err((code, message));
+            }
+
+            let mut decoded = vec![];
+            for message in messages {
+                let mut value = self
+                    .pool
+                    .decode_message(&method.output, message, self.emit_defaults)
+                    .map_err(|e| (Code::Internal, e))?;
+                if !rule.route.response_body.is_empty() {
+                    value = match self.resolve(&method.output, rule.route.response_body) {
+                        Some(keys) => keys
+                            .iter()
+                            .try_fold(&value, |value, key| value.get(key))
+                            .cloned()
+                            .unwrap_or(Value::Null),
+                        None => Value::Null,
+                    };
+                }
+                decoded.push(value);
+            }
+
+            let body = match method.server_streaming {
+                true => Value::Array(decoded),
+                false => decoded.pop().unwrap_or(Value::Null),
+            };
+            Ok(json_response(StatusCode::OK, &body))
+        }
+
+        /// The JSON names of the fields along the dotted `path` of the
+        /// `message` fields, if they all exist.
+        fn resolve(&self, message: &str, path: &str) -> Option<Vec<String>> {
+            let mut message = message.to_string();
+            let mut keys = vec![];
+            for name in path.split('.') {
+                let field = self.pool.field(&message, name)?;
+                keys.push(json_name(field));
+                message = field.type_name().to_string();
+            }
+            Some(keys)
+        }
+
+        /// Sets the field along the dotted `path` of `value` to `field`, the
+        /// repeated ones being appended to.
+        fn bind(
+            &self,
+            message: &str,
+            value: &mut Value,
+            path: &str,
+            field: Value,
+        ) -> Result<(), Failure> {
+            let unknown = || (Code::InvalidArgument, format!("unknown field `{}`", path));
+            let mut message = message.to_string();
+            let mut value = value;
+            let names: Vec<&str> = path.split('.').collect();
+
+            for (i, name) in names.iter().enumerate() {
+                let descriptor = self.pool.field(&message, name).ok_or_else(unknown)?;
+                let key = json_name(descriptor);
+                let object = value.as_object_mut().ok_or_else(unknown)?;
+                // the proto name is replaced by the JSON one, not to be set twice
+                if let Some(previous) = object.remove(descriptor.name()) {
+                    object.entry(key.clone()).or_insert(previous);
+                }
+
+                if i + 1 == names.len() {
+                    let repeated = descriptor.label() == Label::Repeated;
+                    match object.get_mut(&key) {
+                        Some(Value::Array(values)) if repeated && !field.is_array() => {
+                            values.push(field)
+                        }
+                        _ if repeated && !field.is_array() => {
+                            object.insert(key, Value::Array(vec![field]));
+                        }
+                        _ => {
+                            object.insert(key, field);
+                        }
+                    }
+                    return Ok(());
+                }
+
+                message = descriptor.type_name().to_string();
+                value = object
+                    .entry(key)
+                    .or_insert_with(|| Value::Object(Map::new()));
+            }
+            Ok(())
+        }
+    }
+
+    /// The JSON of a request body, `{}` if empty.
+    fn parse(body: &[u8]) -> Result<Value, Failure> {
+        if body.iter().all(u8::is_ascii_whitespace) {
+            return Ok(Value::Object(Map::new()));
+        }
+        serde_json::from_slice(body)
+            .map_err(|e| (Code::InvalidArgument, format!("invalid JSON body: {}", e)))
+    }
+
+    /// The code and the decoded message of the `grpc-status` of `headers`.
+    fn grpc_status(headers: &HeaderMap) -> Option<Failure> {
+        let code = headers.get("grpc-status")?.to_str().ok()?.parse().ok()?;
+        let message = headers
+            .get("grpc-message")
+            .and_then(|message| message.to_str().ok())
+            .map(|message| template::decode(message, false))
+            .unwrap_or_default();
+        Some((Code::from_i32(code), message))
+    }
+
+    /// The HTTP status of a gRPC code, as of `google.rpc.Code`.
+    pub(crate) fn http_status(code: Code) -> StatusCode {
+        match code {
+            Code::Ok => StatusCode::OK,
+            Code::Cancelled => StatusCode::from_u16(499).expect("invalid status"),
+            Code::InvalidArgument | Code::FailedPrecondition | Code::OutOfRange => {
+                StatusCode::BAD_REQUEST
+            }
+            Code::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+            Code::NotFound => StatusCode::NOT_FOUND,
+            Code::AlreadyExists | Code::Aborted => StatusCode::CONFLICT,
+            Code::PermissionDenied => StatusCode::FORBIDDEN,
+            Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+            Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+            Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+            Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            // unknown, internal and data loss
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error(code: Code, message: &str) -> layer::Response {
+        let body = json!({ "code": code as i32, "message": message });
+        json_response(http_status(code), &body)
+    }
+
+    fn json_response(status: StatusCode, body: &Value) -> layer::Response {
+        http::Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(BoxBody::map_from(Body::from(body.to_string())))
+            .expect("invalid response")
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn maps_grpc_codes() {
+            assert_eq!(http_status(Code::Ok), StatusCode::OK);
+            assert_eq!(http_status(Code::Cancelled).as_u16(), 499);
+            assert_eq!(http_status(Code::OutOfRange), StatusCode::BAD_REQUEST);
+            assert_eq!(http_status(Code::Unauthenticated), StatusCode::UNAUTHORIZED);
+            assert_eq!(
+                http_status(Code::DataLoss),
+                StatusCode::INTERNAL_SERVER_ERROR
+            );
+
+            let mut headers = HeaderMap::new();
+            headers.insert("grpc-status", HeaderValue::from_static("5"));
+            headers.insert(
+                "grpc-message",
+                HeaderValue::from_static("no%20such%20hotel"),
+            );
+            assert_eq!(
+                grpc_status(&headers),
+                Some((Code::NotFound, "no such hotel".into()))
+            );
+        }
+    }
+}
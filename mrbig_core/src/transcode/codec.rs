@@ -0,0 +1,720 @@
+//! JSON mapping of the protobuf messages, driven by their descriptors.
+//!
+//! Fields are read by their name or their JSON name, and written by their
+//! JSON name. 64-bit integers are written as strings, enums by name and
+//! bytes in base64, as of the proto3 JSON mapping. The wrapper types, such
+//! as `google.protobuf.StringValue`, are written as their value; the other
+//! well-known types are written as plain messages.
+use prost::Message;
+use prost_types::field_descriptor_proto::{Label, Type};
+use prost_types::{DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorSet};
+use serde_json::{Map, Number, Value};
+use std::collections::HashMap;
+use std::convert::TryInto;
+
+const WRAPPERS: &[&str] = &[
+    ".google.protobuf.DoubleValue",
+    ".google.protobuf.FloatValue",
+    ".google.protobuf.Int64Value",
+    ".google.protobuf.UInt64Value",
+    ".google.protobuf.Int32Value",
+    ".google.protobuf.UInt32Value",
+    ".google.protobuf.BoolValue",
+    ".google.protobuf.StringValue",
+    ".google.protobuf.BytesValue",
+];
+
+// wire types
+const VARINT: u64 = 0;
+const FIXED64: u64 = 1;
+const LEN: u64 = 2;
+const FIXED32: u64 = 5;
+
+/// A method of the descriptors.
+#[derive(Debug)]
+pub(crate) struct Method {
+    pub(crate) input: String,
+    pub(crate) output: String,
+    pub(crate) server_streaming: bool,
+}
+
+/// The messages, enums and methods of a set of file descriptors, by fully
+/// qualified name. Messages and enums are named with a leading dot, as in
+/// `.hotel.Request`, methods without, as in `hotel.Hotel.Rates`.
+#[derive(Debug, Default)]
+pub(crate) struct Pool {
+    messages: HashMap<String, DescriptorProto>,
+    enums: HashMap<String, EnumDescriptorProto>,
+    methods: HashMap<String, Method>,
+}
+
+impl Pool {
+    /// Reads the encoded `FileDescriptorSet` of `descriptors`.
+    pub(crate) fn decode(descriptors: &[u8]) -> Result<Self, String> {
+        let set = FileDescriptorSet::decode(descriptors)
+            .map_err(|e| format!("invalid descriptors: {}", e))?;
+
+        let mut pool = Pool::default();
+        for file in set.file {
+            let package = match file.package() {
+                "" => String::new(),
+                package => format!(".{}", package),
+            };
+            for service in &file.service {
+                for method in &service.method {
+                    let name = format!("{}.{}.{}", package, service.name(), method.name());
+                    pool.methods.insert(
+                        name.trim_start_matches('.').to_string(),
+                        Method {
+                            input: method.input_type().to_string(),
+                            output: method.output_type().to_string(),
+                            server_streaming: method.server_streaming(),
+                        },
+                    );
+                }
+            }
+            for en in file.enum_type {
+                pool.enums.insert(format!("{}.{}", package, en.name()), en);
+            }
+            for message in file.message_type {
+                pool.insert_message(&package, message);
+            }
+        }
+        Ok(pool)
+    }
+
+    fn insert_message(&mut self, prefix: &str, mut message: DescriptorProto) {
+        let name = format!("{}.{}", prefix, message.name());
+        for en in message.enum_type.drain(..) {
+            self.enums.insert(format!("{}.{}", name, en.name()), en);
+        }
+        for nested in message.nested_type.drain(..) {
+            self.insert_message(&name, nested);
+        }
+        self.messages.insert(name, message);
+    }
+
+    /// The method named `name`, as in `hotel.Hotel.Rates`.
+    pub(crate) fn method(&self, name: &str) -> Option<&Method> {
+        self.methods.get(name)
+    }
+
+    fn message(&self, name: &str) -> Result<&DescriptorProto, String> {
+        self.messages
+            .get(name)
+            .ok_or_else(|| format!("unknown message `{}`", name.trim_start_matches('.')))
+    }
+
+    /// The field of `message` named `name`, or with `name` as JSON name.
+    pub(crate) fn field(&self, message: &str, name: &str) -> Option<&FieldDescriptorProto> {
+        let message = self.message(message).ok()?;
+        message
+            .field
+            .iter()
+            .find(|f| f.name() == name || json_name(f) == name)
+    }
+
+    fn map_entry(&self, field: &FieldDescriptorProto) -> Option<&DescriptorProto> {
+        if field.r#type() != Type::Message || field.label() != Label::Repeated {
+            return None;
+        }
+        let entry = self.messages.get(field.type_name())?;
+        match &entry.options {
+            Some(options) if options.map_entry() => Some(entry),
+            _ => None,
+        }
+    }
+
+    /// Encodes `value` as a `message`.
+    pub(crate) fn encode(&self, message: &str, value: &Value) -> Result<Vec<u8>, String> {
+        let mut buf = vec![];
+        self.encode_message(message, value, &mut buf)?;
+        Ok(buf)
+    }
+
+    fn encode_message(&self, name: &str, value: &Value, buf: &mut Vec<u8>) -> Result<(), String> {
+        if WRAPPERS.contains(&name) && !value.is_object() {
+            let mut wrapped = Map::new();
+            wrapped.insert("value".into(), value.clone());
+            return self.encode_message(name, &Value::Object(wrapped), buf);
+        }
+
+        let object = match value {
+            Value::Object(object) => object,
+            _ => {
+                return Err(format!(
+                    "expected an object for `{}`",
+                    name.trim_start_matches('.')
+                ))
+            }
+        };
+        let message = self.message(name)?;
+
+        for (key, value) in object {
+            let field = message
+                .field
+                .iter()
+                .find(|f| f.name() == key || json_name(f) == *key)
+                .ok_or_else(|| format!("unknown field `{}` of `{}`", key, message.name()))?;
+
+            let result = match (value, self.map_entry(field)) {
+                (Value::Null, _) => Ok(()),
+                (Value::Object(entries), Some(entry)) => entries.iter().try_for_each(|(k, v)| {
+                    let mut bytes = vec![];
+                    self.encode_value(&entry.field[0], &Value::String(k.clone()), &mut bytes)?;
+                    self.encode_value(&entry.field[1], v, &mut bytes)?;
+                    put_key(buf, field.number(), LEN);
+                    put_varint(buf, bytes.len() as u64);
+                    buf.extend(bytes);
+                    Ok(())
+                }),
+                (Value::Array(values), _) if field.label() == Label::Repeated => values
+                    .iter()
+                    .try_for_each(|value| self.encode_value(field, value, buf)),
+                (value, _) => self.encode_value(field, value, buf),
+            };
+            result.map_err(|e| format!("field `{}`: {}", key, e))?;
+        }
+        Ok(())
+    }
+
+    fn encode_value(
+        &self,
+        field: &FieldDescriptorProto,
+        value: &Value,
+        buf: &mut Vec<u8>,
+    ) -> Result<(), String> {
+        let number = field.number();
+        match field.r#type() {
+            Type::Double => {
+                put_key(buf, number, FIXED64);
+                buf.extend(&float(value)?.to_le_bytes());
+            }
+            Type::Float => {
+                put_key(buf, number, FIXED32);
+                buf.extend(&(float(value)? as f32).to_le_bytes());
+            }
+            Type::Int64 | Type::Int32 => {
+                put_key(buf, number, VARINT);
+                put_varint(buf, int(value)? as u64);
+            }
+            Type::Uint64 | Type::Uint32 => {
+                put_key(buf, number, VARINT);
+                put_varint(buf, uint(value)?);
+            }
+            Type::Sint64 | Type::Sint32 => {
+                let n = int(value)?;
+                put_key(buf, number, VARINT);
+                put_varint(buf, ((n << 1) ^ (n >> 63)) as u64);
+            }
+            Type::Fixed64 => {
+                put_key(buf, number, FIXED64);
+                buf.extend(&uint(value)?.to_le_bytes());
+            }
+            Type::Sfixed64 => {
+                put_key(buf, number, FIXED64);
+                buf.extend(&int(value)?.to_le_bytes());
+            }
+            Type::Fixed32 => {
+                put_key(buf, number, FIXED32);
+                buf.extend(&(uint(value)? as u32).to_le_bytes());
+            }
+            Type::Sfixed32 => {
+                put_key(buf, number, FIXED32);
+                buf.extend(&(int(value)? as i32).to_le_bytes());
+            }
+            Type::Bool => {
+                let b = match value {
+                    Value::Bool(b) => *b,
+                    Value::String(s) if s == "true" => true,
+                    Value::String(s) if s == "false" => false,
+                    _ => return Err("expected a boolean".into()),
+                };
+                put_key(buf, number, VARINT);
+                put_varint(buf, b as u64);
+            }
+            Type::Enum => {
+                let n = match value {
+                    Value::String(s) => match self.enum_number(field.type_name(), s) {
+                        Some(n) => n as i64,
+                        None => int(value)?,
+                    },
+                    _ => int(value)?,
+                };
+                put_key(buf, number, VARINT);
+                put_varint(buf, n as u64);
+            }
+            Type::String => {
+                let s = value.as_str().ok_or("expected a string")?;
+                put_bytes(buf, number, s.as_bytes());
+            }
+            Type::Bytes => {
+                let s = value.as_str().ok_or("expected a base64 string")?;
+                put_bytes(buf, number, &decode_base64(s)?);
+            }
+            Type::Message => {
+                let mut bytes = vec![];
+                self.encode_message(field.type_name(), value, &mut bytes)?;
+                put_bytes(buf, number, &bytes);
+            }
+            Type::Group => return Err("groups are not supported".into()),
+        }
+        Ok(())
+    }
+
+    fn enum_number(&self, name: &str, value: &str) -> Option<i32> {
+        let en = self.enums.get(name)?;
+        en.value
+            .iter()
+            .find(|v| v.name() == value)
+            .map(|v| v.number())
+    }
+
+    fn enum_name(&self, name: &str, number: i32) -> Option<&str> {
+        let en = self.enums.get(name)?;
+        en.value
+            .iter()
+            .find(|v| v.number() == number)
+            .map(|v| v.name())
+    }
+
+    /// Decodes the `message` of `bytes`, writing the fields left to their
+    /// default value as well if `emit_defaults` is set.
+    pub(crate) fn decode_message(
+        &self,
+        name: &str,
+        mut bytes: &[u8],
+        emit_defaults: bool,
+    ) -> Result<Value, String> {
+        let message = self.message(name)?;
+        let mut object = Map::new();
+
+        while !bytes.is_empty() {
+            let key = get_varint(&mut bytes)?;
+            let (number, wire) = ((key >> 3) as i32, key & 7);
+            let raw = match wire {
+                VARINT => Raw::Varint(get_varint(&mut bytes)?),
+                FIXED64 => {
+                    Raw::Fixed64(u64::from_le_bytes(take(&mut bytes, 8)?.try_into().unwrap()))
+                }
+                FIXED32 => {
+                    Raw::Fixed32(u32::from_le_bytes(take(&mut bytes, 4)?.try_into().unwrap()))
+                }
+                LEN => {
+                    let len = get_varint(&mut bytes)? as usize;
+                    Raw::Len(take(&mut bytes, len)?)
+                }
+                _ => return Err(format!("unsupported wire type {}", wire)),
+            };
+
+            let field = match message.field.iter().find(|f| f.number() == number) {
+                Some(field) => field,
+                // unknown fields are skipped
+                None => continue,
+            };
+            let key = json_name(field);
+
+            if let (Some(_), Raw::Len(bytes)) = (self.map_entry(field), &raw) {
+                let entry = self.decode_message(field.type_name(), bytes, true)?;
+                let k = match &entry["key"] {
+                    Value::String(k) => k.clone(),
+                    k => k.to_string(),
+                };
+                let entries = object
+                    .entry(key)
+                    .or_insert_with(|| Value::Object(Map::new()));
+                if let Value::Object(entries) = entries {
+                    entries.insert(k, entry["value"].clone());
+                }
+                continue;
+            }
+
+            let values = match (&raw, field.r#type()) {
+                // packed repeated scalars
+                (Raw::Len(packed), t)
+                    if !matches!(t, Type::String | Type::Bytes | Type::Message) =>
+                {
+                    let mut packed = *packed;
+                    let mut values = vec![];
+                    while !packed.is_empty() {
+                        let raw = match t {
+                            Type::Double | Type::Fixed64 | Type::Sfixed64 => Raw::Fixed64(
+                                u64::from_le_bytes(take(&mut packed, 8)?.try_into().unwrap()),
+                            ),
+                            Type::Float | Type::Fixed32 | Type::Sfixed32 => Raw::Fixed32(
+                                u32::from_le_bytes(take(&mut packed, 4)?.try_into().unwrap()),
+                            ),
+                            _ => Raw::Varint(get_varint(&mut packed)?),
+                        };
+                        values.push(self.decode_value(field, raw, emit_defaults)?);
+                    }
+                    values
+                }
+                _ => vec![self.decode_value(field, raw, emit_defaults)?],
+            };
+
+            if field.label() == Label::Repeated {
+                let array = object.entry(key).or_insert_with(|| Value::Array(vec![]));
+                if let Value::Array(array) = array {
+                    array.extend(values);
+                }
+            } else if let Some(value) = values.into_iter().last() {
+                object.insert(key, value);
+            }
+        }
+
+        if emit_defaults {
+            for field in &message.field {
+                let key = json_name(field);
+                if object.contains_key(&key) || field.oneof_index.is_some() {
+                    continue;
+                }
+                if let Some(default) = self.default_value(field) {
+                    object.insert(key, default);
+                }
+            }
+        }
+
+        if WRAPPERS.contains(&name) {
+            let value = object.remove("value");
+            let field = &message.field[0];
+            return Ok(value
+                .or_else(|| self.default_value(field))
+                .unwrap_or(Value::Null));
+        }
+        Ok(Value::Object(object))
+    }
+
+    fn decode_value(
+        &self,
+        field: &FieldDescriptorProto,
+        raw: Raw,
+        emit_defaults: bool,
+    ) -> Result<Value, String> {
+        let value = match (field.r#type(), raw) {
+            (Type::Double, Raw::Fixed64(n)) => float_value(f64::from_bits(n)),
+            (Type::Float, Raw::Fixed32(n)) => float_value(f32::from_bits(n) as f64),
+            (Type::Int64, Raw::Varint(n)) => Value::String((n as i64).to_string()),
+            (Type::Uint64, Raw::Varint(n)) => Value::String(n.to_string()),
+            (Type::Int32, Raw::Varint(n)) => Value::from(n as i64 as i32),
+            (Type::Uint32, Raw::Varint(n)) => Value::from(n as u32),
+            (Type::Sint64, Raw::Varint(n)) => Value::String(zigzag(n).to_string()),
+            (Type::Sint32, Raw::Varint(n)) => Value::from(zigzag(n) as i32),
+            (Type::Fixed64, Raw::Fixed64(n)) => Value::String(n.to_string()),
+            (Type::Sfixed64, Raw::Fixed64(n)) => Value::String((n as i64).to_string()),
+            (Type::Fixed32, Raw::Fixed32(n)) => Value::from(n),
+            (Type::Sfixed32, Raw::Fixed32(n)) => Value::from(n as i32),
+            (Type::Bool, Raw::Varint(n)) => Value::Bool(n != 0),
+            (Type::Enum, Raw::Varint(n)) => match self.enum_name(field.type_name(), n as i32) {
+                Some(name) => Value::String(name.to_string()),
+                None => Value::from(n as i32),
+            },
+            (Type::String, Raw::Len(bytes)) => Value::String(
+                String::from_utf8(bytes.to_vec()).map_err(|_| "invalid UTF-8 string")?,
+            ),
+            (Type::Bytes, Raw::Len(bytes)) => Value::String(base64::encode(bytes)),
+            (Type::Message, Raw::Len(bytes)) => {
+                self.decode_message(field.type_name(), bytes, emit_defaults)?
+            }
+            _ => return Err(format!("unexpected wire type for field `{}`", field.name())),
+        };
+        Ok(value)
+    }
+
+    fn default_value(&self, field: &FieldDescriptorProto) -> Option<Value> {
+        if self.map_entry(field).is_some() {
+            return Some(Value::Object(Map::new()));
+        }
+        if field.label() == Label::Repeated {
+            return Some(Value::Array(vec![]));
+        }
+        let value = match field.r#type() {
+            Type::Double | Type::Float => Value::from(0.0),
+            Type::Int64 | Type::Uint64 | Type::Sint64 | Type::Fixed64 | Type::Sfixed64 => {
+                Value::String("0".into())
+            }
+            Type::Int32 | Type::Uint32 | Type::Sint32 | Type::Fixed32 | Type::Sfixed32 => {
+                Value::from(0)
+            }
+            Type::Bool => Value::Bool(false),
+            Type::String | Type::Bytes => Value::String(String::new()),
+            Type::Enum => match self.enum_name(field.type_name(), 0) {
+                Some(name) => Value::String(name.to_string()),
+                None => Value::from(0),
+            },
+            Type::Message | Type::Group => return None,
+        };
+        Some(value)
+    }
+}
+
+enum Raw<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Fixed32(u32),
+    Len(&'a [u8]),
+}
+
+/// The JSON name of `field`, as set by protoc or as it would.
+pub(crate) fn json_name(field: &FieldDescriptorProto) -> String {
+    match field.json_name() {
+        "" => {
+            let mut name = String::new();
+            let mut upper = false;
+            for c in field.name().chars() {
+                match c {
+                    '_' => upper = true,
+                    c if upper => {
+                        name.extend(c.to_uppercase());
+                        upper = false;
+                    }
+                    c => name.push(c),
+                }
+            }
+            name
+        }
+        name => name.to_string(),
+    }
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn put_key(buf: &mut Vec<u8>, number: i32, wire: u64) {
+    put_varint(buf, (number as u64) << 3 | wire);
+}
+
+fn put_bytes(buf: &mut Vec<u8>, number: i32, bytes: &[u8]) {
+    put_key(buf, number, LEN);
+    put_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn get_varint(bytes: &mut &[u8]) -> Result<u64, String> {
+    let mut value = 0;
+    for (i, byte) in bytes.iter().enumerate().take(10) {
+        value |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            *bytes = &bytes[i + 1..];
+            return Ok(value);
+        }
+    }
+    Err("invalid varint".into())
+}
+
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], String> {
+    if bytes.len() < len {
+        return Err("truncated message".into());
+    }
+    let (taken, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Ok(taken)
+}
+
+fn zigzag(n: u64) -> i64 {
+    (n >> 1) as i64 ^ -((n & 1) as i64)
+}
+
+fn int(value: &Value) -> Result<i64, String> {
+    match value {
+        Value::Number(n) => n
+            .as_i64()
+            .or_else(|| n.as_f64().filter(|f| f.fract() == 0.0).map(|f| f as i64)),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| format!("expected an integer, got {}", value))
+}
+
+fn uint(value: &Value) -> Result<u64, String> {
+    match value {
+        Value::Number(n) => n.as_u64().or_else(|| {
+            n.as_f64()
+                .filter(|f| f.fract() == 0.0 && *f >= 0.0)
+                .map(|f| f as u64)
+        }),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| format!("expected an unsigned integer, got {}", value))
+}
+
+fn float(value: &Value) -> Result<f64, String> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => match s.as_str() {
+            "NaN" => Some(f64::NAN),
+            "Infinity" => Some(f64::INFINITY),
+            "-Infinity" => Some(f64::NEG_INFINITY),
+            s => s.parse().ok(),
+        },
+        _ => None,
+    }
+    .ok_or_else(|| format!("expected a number, got {}", value))
+}
+
+fn float_value(f: f64) -> Value {
+    match Number::from_f64(f) {
+        Some(n) => Value::Number(n),
+        None if f.is_nan() => Value::String("NaN".into()),
+        None if f > 0.0 => Value::String("Infinity".into()),
+        None => Value::String("-Infinity".into()),
+    }
+}
+
+/// Decodes standard or URL-safe base64, padded or not.
+fn decode_base64(s: &str) -> Result<Vec<u8>, String> {
+    let unpadded = s.trim_end_matches('=');
+    base64::decode_config(unpadded, base64::STANDARD_NO_PAD)
+        .or_else(|_| base64::decode_config(unpadded, base64::URL_SAFE_NO_PAD))
+        .map_err(|e| format!("invalid base64: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost_types::{
+        DescriptorProto, EnumValueDescriptorProto, FileDescriptorProto, MessageOptions,
+    };
+    use serde_json::json;
+
+    fn field(
+        name: &str,
+        number: i32,
+        t: Type,
+        label: Label,
+        type_name: &str,
+    ) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.into()),
+            number: Some(number),
+            r#type: Some(t as i32),
+            label: Some(label as i32),
+            type_name: Some(type_name.into()).filter(|n: &String| !n.is_empty()),
+            ..Default::default()
+        }
+    }
+
+    fn pool() -> Pool {
+        let optional = Label::Optional;
+        let tags = DescriptorProto {
+            name: Some("TagsEntry".into()),
+            field: vec![
+                field("key", 1, Type::String, optional, ""),
+                field("value", 2, Type::Int32, optional, ""),
+            ],
+            options: Some(MessageOptions {
+                map_entry: Some(true),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let hotel = DescriptorProto {
+            name: Some("Hotel".into()),
+            field: vec![
+                field("hotel_id", 1, Type::String, optional, ""),
+                field("rooms", 2, Type::Int64, optional, ""),
+                field("rate", 3, Type::Double, optional, ""),
+                field("open", 4, Type::Bool, optional, ""),
+                field("stars", 5, Type::Enum, optional, ".hotel.Stars"),
+                field("scores", 6, Type::Sint32, Label::Repeated, ""),
+                field("photo", 7, Type::Bytes, optional, ""),
+                field(
+                    "tags",
+                    8,
+                    Type::Message,
+                    Label::Repeated,
+                    ".hotel.Hotel.TagsEntry",
+                ),
+                field("nearby", 9, Type::Message, Label::Repeated, ".hotel.Hotel"),
+            ],
+            nested_type: vec![tags],
+            ..Default::default()
+        };
+        let stars = EnumDescriptorProto {
+            name: Some("Stars".into()),
+            value: vec![
+                EnumValueDescriptorProto {
+                    name: Some("UNRATED".into()),
+                    number: Some(0),
+                    ..Default::default()
+                },
+                EnumValueDescriptorProto {
+                    name: Some("FIVE".into()),
+                    number: Some(5),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let set = FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("hotel.proto".into()),
+                package: Some("hotel".into()),
+                message_type: vec![hotel],
+                enum_type: vec![stars],
+                ..Default::default()
+            }],
+        };
+
+        let mut descriptors = vec![];
+        set.encode(&mut descriptors).unwrap();
+        Pool::decode(&descriptors).unwrap()
+    }
+
+    #[test]
+    fn round_trips_json() {
+        let pool = pool();
+        let hotel = json!({
+            "hotelId": "h1",
+            "rooms": "120",
+            "rate": 99.5,
+            "open": true,
+            "stars": "FIVE",
+            "scores": [-1, 2],
+            "photo": "AQID",
+            "tags": {"pool": 1},
+            "nearby": [{"hotelId": "h2"}],
+        });
+
+        let bytes = pool.encode(".hotel.Hotel", &hotel).unwrap();
+        assert_eq!(
+            pool.decode_message(".hotel.Hotel", &bytes, false).unwrap(),
+            hotel
+        );
+
+        // proto names, numbers as strings and enums by number are read too
+        let bound = json!({"hotel_id": "h1", "rooms": 120, "rate": "99.5", "stars": 5});
+        let bytes = pool.encode(".hotel.Hotel", &bound).unwrap();
+        let decoded = pool.decode_message(".hotel.Hotel", &bytes, true).unwrap();
+        assert_eq!(decoded["rooms"], "120");
+        assert_eq!(decoded["stars"], "FIVE");
+        assert_eq!(decoded["open"], false);
+        assert_eq!(decoded["tags"], json!({}));
+    }
+
+    #[test]
+    fn rejects_invalid_json() {
+        let pool = pool();
+        for (json, error) in [
+            (json!({"name": "h1"}), "unknown field `name` of `Hotel`"),
+            (
+                json!({"rooms": "many"}),
+                "field `rooms`: expected an integer",
+            ),
+            (
+                json!({"nearby": [{"open": "yes"}]}),
+                "field `nearby`: field `open`",
+            ),
+            (json!([]), "expected an object for `hotel.Hotel`"),
+        ] {
+            let e = pool.encode(".hotel.Hotel", &json).unwrap_err();
+            assert!(e.starts_with(error), "{}", e);
+        }
+    }
+}
@@ -0,0 +1,332 @@
+//! Path templates of the `google.api.http` annotations, such as
+//! `/v1/{name=shelves/*/books/*}:publish`.
+
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Literal(String),
+    // `*`, a single segment
+    Single,
+    // `**`, the remaining segments
+    Multi,
+}
+
+/// A field bound to segments of the paths, up to the end if `end` is
+/// `None`.
+#[derive(Debug, PartialEq)]
+struct Variable {
+    field: String,
+    start: usize,
+    end: Option<usize>,
+}
+
+/// A parsed path template.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Template {
+    segments: Vec<Segment>,
+    variables: Vec<Variable>,
+    verb: Option<String>,
+}
+
+impl Template {
+    /// Parses `pattern`, as of the `google.api.http` syntax.
+    pub(crate) fn parse(pattern: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            pattern: pattern.as_bytes(),
+            pos: 0,
+            template: Template {
+                segments: vec![],
+                variables: vec![],
+                verb: None,
+            },
+        };
+        parser
+            .template()
+            .map_err(|e| format!("invalid path template `{}`: {}", pattern, e))?;
+        Ok(parser.template)
+    }
+
+    /// The fields bound by `path`, if it matches, with their decoded value.
+    pub(crate) fn matches(&self, path: &str) -> Option<Vec<(&str, String)>> {
+        let path = path.strip_prefix('/')?;
+        let path = match &self.verb {
+            Some(verb) => path.strip_suffix(verb.as_str())?.strip_suffix(':')?,
+            None => path,
+        };
+        let parts: Vec<&str> = path.split('/').collect();
+
+        let multi = matches!(self.segments.last(), Some(Segment::Multi));
+        let fixed = self.segments.len() - multi as usize;
+        if parts.len() < fixed || (!multi && parts.len() != fixed) {
+            return None;
+        }
+
+        let matched = self
+            .segments
+            .iter()
+            .zip(&parts)
+            .all(|(segment, part)| match segment {
+                Segment::Literal(literal) => literal == part,
+                Segment::Single => !part.is_empty(),
+                Segment::Multi => true,
+            });
+        if !matched {
+            return None;
+        }
+
+        let bindings = self
+            .variables
+            .iter()
+            .map(|v| {
+                let end = v.end.unwrap_or(parts.len());
+                (
+                    v.field.as_str(),
+                    decode(&parts[v.start..end].join("/"), false),
+                )
+            })
+            .collect();
+        Some(bindings)
+    }
+}
+
+struct Parser<'a> {
+    pattern: &'a [u8],
+    pos: usize,
+    template: Template,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.pattern.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String> {
+        match self.peek() {
+            Some(next) if next == c => {
+                self.pos += 1;
+                Ok(())
+            }
+            _ => Err(format!("expected `{}` at {}", c as char, self.pos)),
+        }
+    }
+
+    // Template = "/" Segments [ Verb ]
+    fn template(&mut self) -> Result<(), String> {
+        self.expect(b'/')?;
+        self.segments(true)?;
+
+        if self.peek() == Some(b':') {
+            self.pos += 1;
+            let verb = self.literal()?;
+            self.template.verb = Some(verb);
+        }
+        if self.pos != self.pattern.len() {
+            return Err(format!(
+                "unexpected `{}` at {}",
+                self.pattern[self.pos] as char, self.pos
+            ));
+        }
+
+        let multi = self
+            .template
+            .segments
+            .iter()
+            .position(|s| *s == Segment::Multi);
+        match multi {
+            Some(i) if i + 1 != self.template.segments.len() => Err("`**` must come last".into()),
+            _ => Ok(()),
+        }
+    }
+
+    // Segments = Segment { "/" Segment }
+    fn segments(&mut self, variables: bool) -> Result<(), String> {
+        loop {
+            self.segment(variables)?;
+            if self.peek() != Some(b'/') {
+                return Ok(());
+            }
+            self.pos += 1;
+        }
+    }
+
+    // Segment = "*" | "**" | LITERAL | Variable
+    fn segment(&mut self, variables: bool) -> Result<(), String> {
+        let segment = match self.peek() {
+            Some(b'{') if variables => return self.variable(),
+            Some(b'*') => {
+                self.pos += 1;
+                match self.peek() {
+                    Some(b'*') => {
+                        self.pos += 1;
+                        Segment::Multi
+                    }
+                    _ => Segment::Single,
+                }
+            }
+            _ => Segment::Literal(self.literal()?),
+        };
+        self.template.segments.push(segment);
+        Ok(())
+    }
+
+    // Variable = "{" FieldPath [ "=" Segments ] "}"
+    fn variable(&mut self) -> Result<(), String> {
+        self.expect(b'{')?;
+        let field = self.take_while(|c| c.is_ascii_alphanumeric() || c == b'_' || c == b'.');
+        if field.is_empty() {
+            return Err(format!("expected a field at {}", self.pos));
+        }
+
+        let start = self.template.segments.len();
+        if self.peek() == Some(b'=') {
+            self.pos += 1;
+            self.segments(false)?;
+        } else {
+            self.template.segments.push(Segment::Single);
+        }
+        self.expect(b'}')?;
+
+        let end = match self.template.segments.last() {
+            Some(Segment::Multi) => None,
+            _ => Some(self.template.segments.len()),
+        };
+        self.template.variables.push(Variable { field, start, end });
+        Ok(())
+    }
+
+    fn literal(&mut self) -> Result<String, String> {
+        let literal = self.take_while(|c| !matches!(c, b'/' | b'{' | b'}' | b'*' | b':' | b'='));
+        match literal.is_empty() {
+            true => Err(format!("expected a literal at {}", self.pos)),
+            false => Ok(literal),
+        }
+    }
+
+    fn take_while(&mut self, accept: impl Fn(u8) -> bool) -> String {
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if accept(c)) {
+            self.pos += 1;
+        }
+        String::from_utf8_lossy(&self.pattern[start..self.pos]).into_owned()
+    }
+}
+
+/// Decodes the percent-encoded `value`, and the `+` of the query strings
+/// as spaces if `query` is set.
+pub(crate) fn decode(value: &str, query: bool) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|hex| {
+            let hex = std::str::from_utf8(hex).ok()?;
+            u8::from_str_radix(hex, 16).ok()
+        });
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+            (b'+', _) if query => decoded.push(b' '),
+            (byte, _) => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The decoded pairs of the `query` string.
+pub(crate) fn query_pairs(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((key, value)) => (decode(key, true), decode(value, true)),
+            None => (decode(pair, true), String::new()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bindings(pattern: &str, path: &str) -> Option<Vec<(String, String)>> {
+        let template = Template::parse(pattern).unwrap();
+        let bindings = template.matches(path)?;
+        Some(
+            bindings
+                .into_iter()
+                .map(|(field, value)| (field.to_string(), value))
+                .collect(),
+        )
+    }
+
+    fn bound(pairs: &[(&str, &str)]) -> Option<Vec<(String, String)>> {
+        Some(
+            pairs
+                .iter()
+                .map(|(f, v)| (f.to_string(), v.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn matches_templates() {
+        assert_eq!(
+            bindings("/v1/hotels/{hotel_id}/rates", "/v1/hotels/h%201/rates"),
+            bound(&[("hotel_id", "h 1")])
+        );
+        assert_eq!(
+            bindings("/v1/hotels/{hotel_id}/rates", "/v1/hotels/h1"),
+            None
+        );
+        assert_eq!(bindings("/v1/hotels/{hotel_id}", "/v1/hotels/"), None);
+
+        assert_eq!(
+            bindings(
+                "/v1/{name=shelves/*/books/*}:publish",
+                "/v1/shelves/s1/books/b2:publish"
+            ),
+            bound(&[("name", "shelves/s1/books/b2")])
+        );
+        assert_eq!(
+            bindings(
+                "/v1/{name=shelves/*/books/*}:publish",
+                "/v1/shelves/s1/books/b2"
+            ),
+            None
+        );
+
+        assert_eq!(
+            bindings("/v1/files/{path=**}", "/v1/files/a/b/c.txt"),
+            bound(&[("path", "a/b/c.txt")])
+        );
+        assert_eq!(bindings("/v1/*/{id}", "/v1/any/1"), bound(&[("id", "1")]));
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        for pattern in &[
+            "v1/hotels",
+            "/v1/{}",
+            "/v1/**/rates",
+            "/v1/{id",
+            "/v1//rates",
+        ] {
+            assert!(Template::parse(pattern).is_err(), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn decodes_queries() {
+        assert_eq!(
+            query_pairs("in_date=2020-10-01&guest.name=Ada+L%C3%B6&flag"),
+            vec![
+                ("in_date".to_string(), "2020-10-01".to_string()),
+                ("guest.name".to_string(), "Ada Lö".to_string()),
+                ("flag".to_string(), String::new()),
+            ]
+        );
+    }
+}
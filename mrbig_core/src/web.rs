@@ -0,0 +1,622 @@
+//! gRPC-Web calls, behind the `web` feature.
+//!
+//! When `service.grpc_web` is set, every service of the server, the health
+//! and reflection ones included, accepts the gRPC-Web calls of browsers,
+//! binary (`application/grpc-web`) or base64 encoded
+//! (`application/grpc-web-text`), alongside the native gRPC ones. The
+//! server then speaks HTTP/1.1 as well as HTTP/2 at the same addresses:
+//! the connections opening with the HTTP/2 preface go to the gRPC server,
+//! the others to an HTTP/1.1 server routing to the same services.
+//!
+//! Cross-origin calls are allowed as set by `service.grpc_web.cors`. The
+//! calls from other origins are served without the CORS headers, for the
+//! browsers to turn them down.
+//!
+//! The handlers of the calls served over HTTP/1.1 are not told the address
+//! or the certificates of the client.
+use crate::config::{self, Cors};
+use crate::error::Error;
+use crate::layer::{self, BoxError, BoxService, Layers};
+use futures::future::{self, BoxFuture, Either, FutureExt};
+use futures::stream::{Stream, StreamExt};
+use hyper::body::{Bytes, HttpBody};
+use hyper::server::conn::Http;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite};
+use tokio::sync::{mpsc, watch};
+use tonic::body::BoxBody;
+use tonic::codegen::http::{self, header, HeaderMap, HeaderValue, Method, StatusCode};
+use tonic::transport::server::{Connected, Router};
+use tonic::transport::Body;
+use tonic::Status;
+use tower::limit::ConcurrencyLimit;
+use tower::timeout::Timeout;
+use tower::Service;
+
+const GRPC_WEB: &str = "application/grpc-web";
+const GRPC_WEB_TEXT: &str = "application/grpc-web-text";
+
+/// Request headers of gRPC-Web, always allowed.
+const ALLOWED_HEADERS: &[&str] = &["content-type", "x-grpc-web", "x-user-agent", "grpc-timeout"];
+
+/// Response headers of gRPC-Web, always exposed.
+const EXPOSED_HEADERS: &[&str] = &["grpc-status", "grpc-message"];
+
+/// Opening of the HTTP/2 connections.
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// Time allowed to clients to open their connection.
+const PREFACE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Adds to `layers` the layer turning the gRPC-Web calls into gRPC ones,
+/// as set by `grpc_web`.
+pub fn push(layers: &mut Layers, grpc_web: &config::GrpcWeb) -> Result<(), Error> {
+    let cors = Arc::new(CorsHeaders::new(&grpc_web.cors)?);
+    layers.push(tower::layer::layer_fn(move |inner| GrpcWeb {
+        inner,
+        cors: cors.clone(),
+    }));
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Encoding {
+    Binary,
+    Text,
+}
+
+impl Encoding {
+    /// Encoding of the gRPC-Web calls, `None` for the other calls.
+    fn of(headers: &HeaderMap) -> Option<Self> {
+        let content_type = headers.get(header::CONTENT_TYPE)?.to_str().ok()?;
+        if content_type.starts_with(GRPC_WEB_TEXT) {
+            Some(Encoding::Text)
+        } else if content_type.starts_with(GRPC_WEB) {
+            Some(Encoding::Binary)
+        } else {
+            None
+        }
+    }
+
+    fn content_type(self) -> HeaderValue {
+        HeaderValue::from_static(match self {
+            Encoding::Binary => "application/grpc-web+proto",
+            Encoding::Text => "application/grpc-web-text+proto",
+        })
+    }
+
+    fn encode(self, data: Bytes) -> Bytes {
+        match self {
+            Encoding::Binary => data,
+            Encoding::Text => Bytes::from(base64::encode(&data)),
+        }
+    }
+}
+
+/// CORS headers of the responses, as set by `Cors`.
+#[derive(Debug)]
+struct CorsHeaders {
+    any_origin: bool,
+    origins: Vec<HeaderValue>,
+    allow_headers: HeaderValue,
+    expose_headers: HeaderValue,
+    allow_credentials: bool,
+    max_age: HeaderValue,
+}
+
+impl CorsHeaders {
+    fn new(cors: &Cors) -> Result<Self, Error> {
+        let value = |value: &str| {
+            HeaderValue::from_str(value)
+                .map_err(|_| Error::new(&format!("`{}` is not a valid header value", value)))
+        };
+        let list = |defaults: &[&str], extra: &[String]| {
+            let all: Vec<&str> = defaults
+                .iter()
+                .copied()
+                .chain(extra.iter().map(String::as_str))
+                .collect();
+            value(&all.join(", "))
+        };
+
+        Ok(CorsHeaders {
+            any_origin: cors.allowed_origins.iter().any(|o| o == "*"),
+            origins: cors
+                .allowed_origins
+                .iter()
+                .map(|o| value(o))
+                .collect::<Result<_, _>>()?,
+            allow_headers: list(ALLOWED_HEADERS, &cors.allowed_headers)?,
+            expose_headers: list(EXPOSED_HEADERS, &cors.exposed_headers)?,
+            allow_credentials: cors.allow_credentials,
+            max_age: value(&cors.max_age.as_secs().to_string())?,
+        })
+    }
+
+    /// The origin of the request, if allowed.
+    fn allowed(&self, headers: &HeaderMap) -> Option<HeaderValue> {
+        let origin = headers.get(header::ORIGIN)?;
+        if self.any_origin || self.origins.contains(origin) {
+            Some(origin.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Adds the headers of the responses to the calls from `origin`.
+    fn apply(&self, origin: Option<HeaderValue>, headers: &mut HeaderMap) {
+        headers.append(header::VARY, HeaderValue::from_static("origin"));
+        if let Some(origin) = origin {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+            headers.insert(
+                header::ACCESS_CONTROL_EXPOSE_HEADERS,
+                self.expose_headers.clone(),
+            );
+            if self.allow_credentials {
+                headers.insert(
+                    header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                    HeaderValue::from_static("true"),
+                );
+            }
+        }
+    }
+
+    /// Response to the preflight requests from `origin`.
+    fn preflight(&self, origin: Option<HeaderValue>) -> layer::Response {
+        let mut response = http::Response::new(BoxBody::empty());
+        *response.status_mut() = StatusCode::NO_CONTENT;
+
+        let allowed = origin.is_some();
+        let headers = response.headers_mut();
+        self.apply(origin, headers);
+        if allowed {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_METHODS,
+                HeaderValue::from_static("POST, OPTIONS"),
+            );
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_HEADERS,
+                self.allow_headers.clone(),
+            );
+            headers.insert(header::ACCESS_CONTROL_MAX_AGE, self.max_age.clone());
+        }
+        response
+    }
+}
+
+/// Turns the gRPC-Web calls into gRPC ones, and answers the preflight
+/// requests.
+#[derive(Clone)]
+struct GrpcWeb {
+    inner: BoxService,
+    cors: Arc<CorsHeaders>,
+}
+
+impl Service<layer::Request> for GrpcWeb {
+    type Response = layer::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<layer::Response, BoxError>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: layer::Request) -> Self::Future {
+        let origin = self.cors.allowed(request.headers());
+        if request.method() == Method::OPTIONS {
+            return future::ok(self.cors.preflight(origin)).boxed();
+        }
+
+        let encoding = match Encoding::of(request.headers()) {
+            Some(encoding) => encoding,
+            None => return self.inner.call(request),
+        };
+
+        // the service ready for this call, left in place of the clone
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let cors = self.cors.clone();
+
+        async move {
+            let request = match into_grpc(request, encoding).await {
+                Ok(request) => request,
+                Err(status) => return Ok(status.to_http()),
+            };
+
+            let response = inner.call(request).await?;
+            let (mut parts, body) = response.into_parts();
+            parts
+                .headers
+                .insert(header::CONTENT_TYPE, encoding.content_type());
+            parts.headers.remove(header::CONTENT_LENGTH);
+            cors.apply(origin, &mut parts.headers);
+
+            let body = BoxBody::new(WebBody {
+                inner: body,
+                encoding,
+                data_done: false,
+                done: false,
+            });
+            Ok(http::Response::from_parts(parts, body))
+        }
+        .boxed()
+    }
+}
+
+/// The gRPC call of a gRPC-Web one.
+async fn into_grpc(request: layer::Request, encoding: Encoding) -> Result<layer::Request, Status> {
+    let (mut parts, body) = request.into_parts();
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/grpc"),
+    );
+    parts.headers.remove(header::CONTENT_LENGTH);
+
+    let body = match encoding {
+        Encoding::Binary => body,
+        Encoding::Text => {
+            let text = hyper::body::to_bytes(body)
+                .await
+                .map_err(|e| Status::internal(format!("failed to read the body: {}", e)))?;
+            Body::from(decode_text(&text)?)
+        }
+    };
+
+    Ok(http::Request::from_parts(parts, body))
+}
+
+/// Decodes a base64 body, which may be made of several padded chunks.
+// the status of the call, as returned by the handlers
+#[allow(clippy::result_large_err)]
+fn decode_text(text: &[u8]) -> Result<Vec<u8>, Status> {
+    let text: Vec<u8> = text
+        .iter()
+        .copied()
+        .filter(|b| !b.is_ascii_whitespace())
+        .collect();
+
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    for quantum in text.chunks(4) {
+        let decoded = base64::decode(quantum)
+            .map_err(|e| Status::invalid_argument(format!("invalid base64 body: {}", e)))?;
+        bytes.extend(decoded);
+    }
+    Ok(bytes)
+}
+
+/// Frame of the gRPC-Web responses holding the trailers.
+fn trailers_frame(trailers: &HeaderMap) -> Bytes {
+    let mut block = vec![];
+    for (name, value) in trailers {
+        block.extend_from_slice(name.as_str().as_bytes());
+        block.push(b':');
+        block.extend_from_slice(value.as_bytes());
+        block.extend_from_slice(b"\r\n");
+    }
+
+    let mut frame = Vec::with_capacity(5 + block.len());
+    frame.push(0x80);
+    frame.extend_from_slice(&(block.len() as u32).to_be_bytes());
+    frame.extend(block);
+    Bytes::from(frame)
+}
+
+/// Body of the gRPC-Web responses: the gRPC one, followed by the trailers.
+struct WebBody {
+    inner: BoxBody,
+    encoding: Encoding,
+    data_done: bool,
+    done: bool,
+}
+
+impl HttpBody for WebBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Status>>> {
+        let this = &mut *self;
+        if this.done {
+            return Poll::Ready(None);
+        }
+
+        if !this.data_done {
+            match futures::ready!(Pin::new(&mut this.inner).poll_data(cx)) {
+                Some(Ok(data)) => return Poll::Ready(Some(Ok(this.encoding.encode(data)))),
+                Some(Err(status)) => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(status)));
+                }
+                None => this.data_done = true,
+            }
+        }
+
+        let trailers = futures::ready!(Pin::new(&mut this.inner).poll_trailers(cx));
+        this.done = true;
+        Poll::Ready(match trailers {
+            Ok(Some(trailers)) => Some(Ok(this.encoding.encode(trailers_frame(&trailers)))),
+            Ok(None) => None,
+            Err(status) => Some(Err(status)),
+        })
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Status>> {
+        Poll::Ready(Ok(None))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.done
+    }
+}
+
+/// A connection whose first bytes were read, to be read again.
+pub(crate) struct Rewind<IO> {
+    prefix: Vec<u8>,
+    read: usize,
+    io: IO,
+}
+
+impl<IO: Connected> Connected for Rewind<IO> {
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        self.io.remote_addr()
+    }
+
+    fn peer_certs(&self) -> Option<Vec<tonic::transport::Certificate>> {
+        self.io.peer_certs()
+    }
+}
+
+impl<IO: AsyncRead + Unpin> AsyncRead for Rewind<IO> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = &mut *self;
+        if this.read < this.prefix.len() {
+            let rest = &this.prefix[this.read..];
+            let n = rest.len().min(buf.len());
+            buf[..n].copy_from_slice(&rest[..n]);
+            this.read += n;
+            return Poll::Ready(Ok(n));
+        }
+        Pin::new(&mut this.io).poll_read(cx, buf)
+    }
+}
+
+impl<IO: AsyncWrite + Unpin> AsyncWrite for Rewind<IO> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.io).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.io).poll_shutdown(cx)
+    }
+}
+
+/// Reads the first bytes of `io`, telling whether they are the HTTP/2
+/// preface.
+async fn sniff<IO: AsyncRead + Unpin>(mut io: IO) -> std::io::Result<(bool, Rewind<IO>)> {
+    let mut prefix = vec![0; PREFACE.len()];
+    let mut read = 0;
+    while read < PREFACE.len() && prefix[..read] == PREFACE[..read] {
+        match io.read(&mut prefix[read..]).await? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    prefix.truncate(read);
+
+    let http2 = prefix == PREFACE;
+    Ok((
+        http2,
+        Rewind {
+            prefix,
+            read: 0,
+            io,
+        },
+    ))
+}
+
+/// Completes once `stop` is set, or dropped.
+async fn stopped(mut stop: watch::Receiver<bool>) {
+    while let Some(false) = stop.recv().await {}
+}
+
+/// Serves the HTTP/2 connections of `incoming` with `router`, and the
+/// others with an HTTP/1.1 server answering with `http`, until `signal`
+/// completes and the calls in flight are done.
+pub(crate) async fn serve<A, B, I, IO, F>(
+    router: Router<A, B>,
+    http: BoxService,
+    incoming: I,
+    grpc_server: &config::GrpcServer,
+    signal: F,
+) -> Result<(), tonic::transport::Error>
+where
+    A: crate::GrpcService + Clone + Send + 'static,
+    A::Future: Send + 'static,
+    A::Error: Into<BoxError> + Send,
+    B: crate::GrpcService + Clone + Send + 'static,
+    B::Future: Send + 'static,
+    B::Error: Into<BoxError> + Send,
+    I: Stream<Item = Result<IO, std::io::Error>>,
+    IO: AsyncRead + AsyncWrite + Connected + Unpin + Send + 'static,
+    F: std::future::Future<Output = ()>,
+{
+    let (stop_tx, stop) = watch::channel(false);
+    let (http2_tx, http2) = mpsc::unbounded_channel::<Result<Rewind<IO>, std::io::Error>>();
+    // each HTTP/1.1 connection holds a sender until done
+    let (done_tx, mut done) = mpsc::channel::<()>(1);
+
+    let service = http1_service(http, grpc_server);
+    let limit = grpc_server.concurrency_limit_per_connection;
+    let accept = incoming
+        .take_until(stopped(stop.clone()))
+        .for_each(move |conn| {
+            match conn {
+                Ok(io) => {
+                    let connection =
+                        connection(io, http2_tx.clone(), service.clone(), limit, stop.clone());
+                    let done_tx = done_tx.clone();
+                    tokio::spawn(async move {
+                        connection.await;
+                        drop(done_tx);
+                    });
+                }
+                Err(e) => log::debug!("failed to accept a connection: {}", e),
+            }
+            future::ready(())
+        });
+
+    let signal = async move {
+        signal.await;
+        let _ = stop_tx.broadcast(true);
+    };
+    let grpc = router.serve_with_incoming_shutdown(http2, signal);
+
+    let (served, _) = future::join(grpc, accept).await;
+    // every sender is dropped once the connections are done
+    done.recv().await;
+    served
+}
+
+/// Service of the HTTP/1.1 connections, with the timeout of the gRPC
+/// server. Its concurrency limit is set by `connection`, per connection.
+fn http1_service(service: BoxService, grpc_server: &config::GrpcServer) -> BoxService {
+    match grpc_server.timeout {
+        Some(timeout) => BoxService::new(Timeout::new(service, timeout)),
+        None => service,
+    }
+}
+
+/// Hands `io` to the gRPC server if it speaks HTTP/2, or serves it with
+/// `service` over HTTP/1.1, at most `limit` calls at a time, until `stop`
+/// is set.
+async fn connection<IO>(
+    io: IO,
+    http2: mpsc::UnboundedSender<Result<Rewind<IO>, std::io::Error>>,
+    service: BoxService,
+    limit: Option<usize>,
+    stop: watch::Receiver<bool>,
+) where
+    IO: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let io = match tokio::time::timeout(PREFACE_TIMEOUT, sniff(io)).await {
+        Ok(Ok((true, io))) => {
+            let _ = http2.send(Ok(io));
+            return;
+        }
+        Ok(Ok((false, io))) => io,
+        Ok(Err(e)) => {
+            log::debug!("failed to read the connection: {}", e);
+            return;
+        }
+        Err(_) => {
+            log::debug!("connection timed out before its first request");
+            return;
+        }
+    };
+
+    // the clones of a limited service share its limit, so each connection
+    // gets its own, as the gRPC server does
+    let service = match limit {
+        Some(limit) => BoxService::new(ConcurrencyLimit::new(service, limit)),
+        None => service,
+    };
+    let mut conn = Http::new().http1_only(true).serve_connection(io, service);
+    let served = match future::select(&mut conn, stopped(stop).boxed()).await {
+        Either::Left((served, _)) => served,
+        Either::Right((_, _)) => {
+            Pin::new(&mut conn).graceful_shutdown();
+            conn.await
+        }
+    };
+
+    if let Err(e) = served {
+        log::debug!("HTTP/1.1 connection error: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_chunked_text() {
+        let text = format!("{}{}", base64::encode(b"gRPC"), base64::encode(b"-Web"));
+        assert_eq!(decode_text(text.as_bytes()).unwrap(), b"gRPC-Web");
+        assert!(decode_text(b"not base64!").is_err());
+
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", HeaderValue::from_static("0"));
+        let frame = trailers_frame(&trailers);
+        assert_eq!(&frame[..], &b"\x80\x00\x00\x00\x0fgrpc-status:0\r\n"[..]);
+    }
+
+    #[test]
+    fn sniffs_http2() {
+        use futures::executor::block_on;
+
+        let (http2, mut io) =
+            block_on(sniff(&b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\nframes"[..])).unwrap();
+        assert!(http2);
+        let mut read = vec![];
+        block_on(io.read_to_end(&mut read)).unwrap();
+        assert_eq!(read, b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\nframes");
+
+        let (http2, _) = block_on(sniff(&b"POST /hotel.Hotel/Rates HTTP/1.1\r\n"[..])).unwrap();
+        assert!(!http2);
+    }
+
+    #[test]
+    fn allows_origins() {
+        let cors: Cors = toml::from_str(
+            r#"
+            allowed_origins = ["https://app.example.com"]
+            allowed_headers = ["authorization"]
+            "#,
+        )
+        .unwrap();
+        let cors = CorsHeaders::new(&cors).unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ORIGIN,
+            HeaderValue::from_static("https://app.example.com"),
+        );
+        let response = cors.preflight(cors.allowed(&headers));
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let allowed = &response.headers()[header::ACCESS_CONTROL_ALLOW_HEADERS];
+        assert_eq!(
+            allowed,
+            "content-type, x-grpc-web, x-user-agent, grpc-timeout, authorization"
+        );
+
+        headers.insert(
+            header::ORIGIN,
+            HeaderValue::from_static("https://evil.example.com"),
+        );
+        let response = cors.preflight(cors.allowed(&headers));
+        assert!(!response
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+}
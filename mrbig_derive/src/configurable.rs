@@ -1,5 +1,7 @@
 use proc_macro::TokenStream;
-use syn::{parse_macro_input, DeriveInput, Field, Ident, Type};
+use syn::parse::{Parse, ParseStream, Result};
+use syn::punctuated::Punctuated;
+use syn::{parse_macro_input, DeriveInput, Field, Ident, Token, Type};
 
 // Attribute names to look for
 const ATTR_CONFIG_EXTRA: &str = "mrbig_config_extra";
@@ -11,6 +13,61 @@ struct InnerConfig {
     context: Option<Field>,
 }
 
+/// Arguments of the `mrbig_config_extra` attribute.
+#[derive(Default)]
+struct ExtraArgs {
+    validate: Option<syn::Path>,
+    flags: Option<syn::Path>,
+    sources: Option<syn::Path>,
+    schema: bool,
+    print: bool,
+}
+
+impl Parse for ExtraArgs {
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        // expect named arguments
+        let fields: Punctuated<syn::MetaNameValue, Token![,]> =
+            input.parse_terminated(syn::MetaNameValue::parse)?;
+
+        let mut args = ExtraArgs::default();
+
+        fields.into_iter().for_each(|meta| {
+            let left = meta.path.get_ident().expect("LHS must be an identifier");
+
+            let lit = || match &meta.lit {
+                syn::Lit::Str(l) => l.value(),
+                t => panic!("RHS argument for {} must be a literal: {:?}", left, t),
+            };
+            let path = || {
+                syn::parse_str::<syn::Path>(&lit()).expect("RHS argument is not a function path")
+            };
+
+            match left.to_string().as_str() {
+                "validate" => args.validate = Some(path()),
+                "flags" => args.flags = Some(path()),
+                "sources" => args.sources = Some(path()),
+                "schema" => args.schema = lit() == "true",
+                "print" => args.print = lit() == "true",
+                other => panic!("unknown `{}` argument: {}", ATTR_CONFIG_EXTRA, other),
+            }
+        });
+
+        Ok(args)
+    }
+}
+
+fn extra_args(field: &Field) -> ExtraArgs {
+    field
+        .attrs
+        .iter()
+        .find(|a| a.path.is_ident(ATTR_CONFIG_EXTRA) && !a.tokens.is_empty())
+        .map(|a| {
+            a.parse_args::<ExtraArgs>()
+                .expect("failed to parse mrbig_config_extra arguments")
+        })
+        .unwrap_or_default()
+}
+
 fn assert_unique(field: syn::Field, name: &str, is_some: bool) -> Option<Field> {
     if is_some {
         panic!("only one `{}` attribute is allowed per field", name);
@@ -153,6 +210,75 @@ fn set_extra_method_configurable_impl(field: &Field) -> syn::ImplItemMethod {
     }
 }
 
+fn validate_extra_method_configurable_impl(validate: &syn::Path) -> syn::ImplItemMethod {
+    parse_quote! {
+        fn validate_extra(
+            &self,
+            extra: &Self::Extra,
+            validation: &mut ::mrbig_core::config::Validation,
+        ) {
+            #validate(extra, validation);
+        }
+    }
+}
+
+fn flags_method_configurable_impl(flags: Option<&syn::Path>) -> syn::ImplItemMethod {
+    let declared: syn::Expr = match flags {
+        Some(flags) => parse_quote! { #flags() },
+        None => parse_quote! { Vec::new() },
+    };
+    // one flag per parameter of the extra struct, after the declared ones
+    parse_quote! {
+        fn flags(&self) -> Vec<::mrbig_core::config::Flag> {
+            let mut flags: Vec<::mrbig_core::config::Flag> = #declared;
+            let extra = ::mrbig_core::config::schema::describe::<Self::Extra>();
+            let params = ::mrbig_core::config::Flag::params(&extra, &flags);
+            flags.extend(params);
+            flags
+        }
+    }
+}
+
+fn sources_method_configurable_impl(sources: &syn::Path) -> syn::ImplItemMethod {
+    parse_quote! {
+        fn config_sources(
+            &self,
+        ) -> Vec<::std::sync::Arc<dyn ::mrbig_core::config::ConfigSource>> {
+            #sources()
+        }
+    }
+}
+
+fn extra_schema_method_configurable_impl() -> syn::ImplItemMethod {
+    parse_quote! {
+        fn extra_schema(&self) -> Option<::mrbig_core::serde_json::Value> {
+            Some(::mrbig_core::config::schema::json_schema_of::<Self::Extra>())
+        }
+    }
+}
+
+fn dump_config_method_configurable_impl() -> syn::ImplItemMethod {
+    parse_quote! {
+        fn dump_config(
+            &self,
+            config: &::mrbig_core::config::Config,
+            extra: &Self::Extra,
+            format: ::mrbig_core::config::Format,
+        ) -> Result<String, ::mrbig_core::Error> {
+            config.dump_with(extra, format)
+        }
+    }
+}
+
+fn version_method_configurable_impl() -> syn::ImplItemMethod {
+    // expanded in the user's crate, so it is the user's package
+    parse_quote! {
+        fn version(&self) -> Option<String> {
+            Some(format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")))
+        }
+    }
+}
+
 fn extra_type_configurable_impl(ty: Type) -> syn::ImplItemType {
     parse_quote! { type Extra = #ty; }
 }
@@ -193,14 +319,52 @@ pub(crate) fn derive(input: TokenStream) -> TokenStream {
             .push(syn::ImplItem::Method(set_extra_method_configurable_impl(
                 &extra,
             )));
+        let args = extra_args(&extra);
+        if let Some(validate) = args.validate {
+            trait_impl
+                .items
+                .push(syn::ImplItem::Method(validate_extra_method_configurable_impl(
+                    &validate,
+                )));
+        }
+        trait_impl
+            .items
+            .push(syn::ImplItem::Method(flags_method_configurable_impl(
+                args.flags.as_ref(),
+            )));
+        if let Some(sources) = args.sources {
+            trait_impl
+                .items
+                .push(syn::ImplItem::Method(sources_method_configurable_impl(
+                    &sources,
+                )));
+        }
+        if args.schema {
+            trait_impl
+                .items
+                .push(syn::ImplItem::Method(extra_schema_method_configurable_impl()));
+        }
+        if args.print {
+            trait_impl
+                .items
+                .push(syn::ImplItem::Method(dump_config_method_configurable_impl()));
+        }
     } else {
         trait_impl
             .items
             .push(syn::ImplItem::Type(extra_type_configurable_impl(
                 parse_quote! { ::mrbig_core::config::Void },
             )));
+        // nothing but `Mr. Big`'s own parameters to print
+        trait_impl
+            .items
+            .push(syn::ImplItem::Method(dump_config_method_configurable_impl()));
     }
 
+    trait_impl
+        .items
+        .push(syn::ImplItem::Method(version_method_configurable_impl()));
+
     // context must exist
     let context = inner.context.unwrap();
 
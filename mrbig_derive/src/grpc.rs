@@ -9,6 +9,7 @@ pub struct Arg {
     pub service_fqn: String,
     pub reflect: bool,
     pub health: bool,
+    pub auth: bool,
 }
 
 impl Arg {
@@ -72,6 +73,7 @@ impl Arg {
             service_fqn,
             reflect,
             health,
+            auth: true,
         })
     }
 }
@@ -86,6 +88,7 @@ impl Parse for Arg {
         let mut trt: Option<Type> = None;
         let mut rfl = true;
         let mut hlt = true;
+        let mut ath = true;
         let mut ident: Option<Ident> = None;
 
         fields.into_iter().for_each(|mnv| {
@@ -119,11 +122,15 @@ impl Parse for Arg {
                     let boolean: syn::LitBool = parse_quote! { #right };
                     hlt = boolean.value;
                 }
+                "auth" => {
+                    let boolean: syn::LitBool = parse_quote! { #right };
+                    ath = boolean.value;
+                }
                 a => unimplemented!("'{}' not supported", a),
             }
         });
 
-        let grpc_arg = match (srv, trt) {
+        let mut grpc_arg = match (srv, trt) {
             (None, None) => {
                 return Err(syn::Error::new(
                     ident.unwrap().span(),
@@ -134,7 +141,61 @@ impl Parse for Arg {
             (None, Some(t)) => Arg::new_from_trait(t, rfl, hlt)?,
             (Some(s), Some(t)) => Arg::new_from_trait_and_service(t, s, rfl, hlt)?,
         };
+        grpc_arg.auth = ath;
 
         Ok(grpc_arg)
     }
 }
+
+/// Arguments of the `#[mrbig_interceptor(fn = "...", services = "...")]`
+/// attribute.
+#[derive(Debug, Clone)]
+pub struct InterceptorArg {
+    pub function: syn::Path,
+    pub services: Option<Vec<String>>,
+}
+
+impl Parse for InterceptorArg {
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        use syn::ext::IdentExt;
+
+        let mut function: Option<syn::Path> = None;
+        let mut services: Option<Vec<String>> = None;
+
+        // `fn` is a keyword, so named arguments are parsed by hand
+        while !input.is_empty() {
+            let left = input.call(Ident::parse_any)?;
+            input.parse::<Token![=]>()?;
+            let right: syn::LitStr = input.parse()?;
+
+            match left.to_string().as_str() {
+                "fn" => function = Some(right.parse()?),
+                "services" => {
+                    services = Some(
+                        right
+                            .value()
+                            .split(',')
+                            .map(|s| s.trim().to_string())
+                            .filter(|s| !s.is_empty())
+                            .collect(),
+                    )
+                }
+                a => {
+                    return Err(syn::Error::new(
+                        left.span(),
+                        format!("'{}' not supported", a),
+                    ))
+                }
+            }
+
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+
+        match function {
+            Some(function) => Ok(InterceptorArg { function, services }),
+            None => Err(input.error("must provide the 'fn' argument")),
+        }
+    }
+}
@@ -31,7 +31,7 @@ mod service;
 /// #[allow(unused_variables, dead_code)]
 /// #[tonic::async_trait]
 /// trait RunFoo: ::mrbig_core::config::Configurable<'static> + Send + Sync + Sized + 'static {
-///     fn init(&mut self) -> ::std::result::Result<(), ::mrbig_core::Error>;
+///     fn init(&mut self) -> ::std::result::Result<::mrbig_core::Init, ::mrbig_core::Error>;
 ///
 ///     async fn run<T1: Trait1, T2: Trait2, ...>(self, s1: T1, s2: T2, ...) -> std::result::Result<(), ::mrbig_core::Error>;
 /// }
@@ -41,7 +41,10 @@ mod service;
 /// endpoint macro attributes that were used.
 ///
 /// The `init()` method is used for server initialization purposes,
-/// mainly for parsing configuration parameters.
+/// mainly for parsing configuration parameters. It returns
+/// `mrbig_core::Init::Exit` when the command line only asked for some
+/// output, such as `--help` or `--version`, which was printed; the
+/// program should then exit without running the service.
 ///
 /// The `run()` method sets the server up and runs it.
 ///
@@ -55,7 +58,7 @@ mod service;
 /// [tonic](https://github.com/hyperium/tonic), so only types generated
 /// by `tonic` are accepted.
 ///
-/// The attribute takes three named arguments:
+/// The attribute takes the following named arguments:
 /// * `service`: fully qualified name of the gRPC proto service.
 /// * `trait`: trait type from `tonic` generated code, that must be
 /// implemented by user.
@@ -63,6 +66,8 @@ mod service;
 /// endpoint.
 /// * `health`: boolean to enable/disable health check for the gRPC
 /// endpoint.
+/// * `auth`: boolean to enable/disable the JWT authentication of the
+///   gRPC endpoint when `service.auth` is set, enabled by default.
 ///
 /// At least one of `service` or `trait` arguments must be provided.
 ///
@@ -101,6 +106,34 @@ mod service;
 /// The implementor type is the trait type followed by *Server*
 /// (trait `Greeter` implementor is `GreeterServer`)
 ///
+/// ## Interceptors
+///
+/// The `#[mrbig_interceptor(fn = "...", services = "...")]` attribute
+/// registers an interceptor, with the type of a `tonic` interceptor
+/// function: `fn(Request<()>) -> Result<Request<()>, Status>`.
+///
+/// The attribute takes two named arguments:
+/// * `fn`: path to the interceptor function.
+/// * `services`: comma separated fully qualified names of the
+///   registered gRPC services to intercept the calls of. All of them when
+///   omitted.
+///
+/// Interceptors run in the order of the attributes, then the ones
+/// registered with `Context::add_interceptor`, up to the first one
+/// returning an error. The reflection and health check servers are not
+/// intercepted:
+///
+/// ```ignore
+/// #[derive(Run, Configurable)]
+/// #[mrbig_register_grpc(service = "helloworld.Greeter")]
+/// #[mrbig_register_grpc(service = "helloworld.Farewell")]
+/// #[mrbig_interceptor(fn = "auth::check")]
+/// #[mrbig_interceptor(fn = "quota::check", services = "helloworld.Greeter")]
+/// struct Micro {
+///     context: mrbig_core::Context,
+/// }
+/// ```
+///
 /// ## Disable reflection
 ///
 /// gRPC reflection can be disabled completely by adding the attribute
@@ -110,12 +143,21 @@ mod service;
 ///
 /// gRPC health check server can be disabled completely by adding the
 /// attribute `#[mrbig_disable_grpc_health]`.
+///
+/// ## HTTP/JSON transcoding
+///
+/// The HTTP routes generated by `mrbig_build::compile_protos` for the
+/// methods annotated with `google.api.http` are compiled in with the
+/// attribute `#[mrbig_transcoding]`, and served when `service.transcoding`
+/// is set.
 #[proc_macro_derive(
     Run,
     attributes(
         mrbig_register_grpc,
+        mrbig_interceptor,
         mrbig_disable_reflection,
-        mrbig_disable_grpc_health
+        mrbig_disable_grpc_health,
+        mrbig_transcoding
     )
 )]
 pub fn derive_run_fn(input: TokenStream) -> TokenStream {
@@ -151,6 +193,103 @@ pub fn derive_run_fn(input: TokenStream) -> TokenStream {
 /// When the microservice's `init()` method is called (from trait Run),
 /// the user defined configuration parameters are deserialized into the
 /// field marked with this attribute.
+///
+/// The user defined parameters can be validated once loaded by naming a
+/// function in the `validate` argument. The function is given the
+/// parameters and a `mrbig_core::config::Validation` to record the
+/// errors in, reported along with `Mr. Big`'s own:
+///
+/// ```ignore
+/// fn check(extra: &Extra, validation: &mut Validation) {
+///     validation.check(extra.retries < 10, "retries", "must be less than 10");
+/// }
+///
+/// #[derive(Configurable)]
+/// struct Micro {
+///     context: mrbig_core::Context,
+///     #[mrbig_config_extra(validate = "check")]
+///     extra: Extra,
+/// }
+/// ```
+///
+/// Every user defined parameter can be set from the command line with a
+/// flag named after its dotted key: `--db-url` sets `db.url`, and boolean
+/// parameters get a switch. Other flags, or flags with a short name or a
+/// better description, are declared by naming a function returning them
+/// in the `flags` argument, and replace the generated ones for the same keys:
+///
+/// ```ignore
+/// fn flags() -> Vec<Flag> {
+///     vec![Flag::new("db-url", "db.url", "set the database URL").short('u')]
+/// }
+///
+/// #[derive(Configurable)]
+/// struct Micro {
+///     context: mrbig_core::Context,
+///     #[mrbig_config_extra(flags = "flags")]
+///     extra: Extra,
+/// }
+/// ```
+///
+/// Other configuration sources, such as a directory of mounted files or an
+/// HTTP endpoint, are declared by naming a function returning them in the
+/// `sources` argument. They are loaded after the config file, in order:
+///
+/// ```ignore
+/// fn sources() -> Vec<Arc<dyn ConfigSource>> {
+///     vec![
+///         Arc::new(DirectorySource::new("/etc/micro/secrets").prefix("db")),
+///         Arc::new(HttpSource::new("http://config:8500/v1/micro.json")),
+///     ]
+/// }
+///
+/// #[derive(Configurable)]
+/// struct Micro {
+///     context: mrbig_core::Context,
+///     #[mrbig_config_extra(sources = "sources")]
+///     extra: Extra,
+/// }
+/// ```
+///
+/// `--config-schema` prints the JSON Schema of the configuration. With
+/// `schema = "true"`, the user defined parameters must implement
+/// `schemars::JsonSchema`, so their doc comments and defaults are
+/// carried into the schema:
+///
+/// ```ignore
+/// /// Parameters of the greeter.
+/// #[derive(Default, Deserialize, mrbig_core::schemars::JsonSchema)]
+/// #[schemars(crate = "mrbig_core::schemars")]
+/// struct Extra {
+///     /// Greeting sent back to the callers.
+///     #[serde(default = "default_greeting")]
+///     greeting: String,
+/// }
+///
+/// #[derive(Configurable)]
+/// struct Micro {
+///     context: mrbig_core::Context,
+///     #[mrbig_config_extra(schema = "true")]
+///     extra: Extra,
+/// }
+/// ```
+///
+/// `--print-config` prints the effective configuration. With
+/// `print = "true"`, the user defined parameters must implement
+/// `serde::Serialize`, so they are printed as deserialized, the values
+/// left to their defaults included, rather than as they were set:
+///
+/// ```ignore
+/// #[derive(Configurable)]
+/// struct Micro {
+///     context: mrbig_core::Context,
+///     #[mrbig_config_extra(print = "true")]
+///     extra: Extra,
+/// }
+/// ```
+///
+/// The user defined parameters are listed by `--help`, and `--version`
+/// prints the name and version of the crate deriving `Configurable`.
 #[proc_macro_derive(Configurable, attributes(mrbig_config, mrbig_config_extra))]
 pub fn derive_configurable_fn(input: TokenStream) -> TokenStream {
     configurable::derive(input)
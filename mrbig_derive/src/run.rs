@@ -1,7 +1,7 @@
 use proc_macro::TokenStream;
 use syn::{parse_macro_input, Block, DeriveInput, Ident, ItemFn};
 
-use crate::grpc::Arg;
+use crate::grpc::{Arg, InterceptorArg};
 
 pub(crate) fn derive(input: TokenStream) -> TokenStream {
     let d = parse_macro_input!(input as DeriveInput);
@@ -14,16 +14,13 @@ struct ServerHandler {
     ident: syn::Ident,
 }
 
-struct Interceptor {
-    name: syn::Ident,
-    flag: syn::Ident,
-}
-
 struct Generate {
     grpc_args: Vec<Arg>,
+    interceptor_args: Vec<InterceptorArg>,
     ident: syn::Ident,
     disable_reflection: bool,
     disable_health: bool,
+    transcoding: bool,
 }
 
 impl Generate {
@@ -33,6 +30,8 @@ impl Generate {
         let attr_disable_reflection = Ident::new("mrbig_disable_reflection", ident.span());
         let attr_disable_health = Ident::new("mrbig_disable_grpc_health", ident.span());
         let attr_register_grpc = Ident::new("mrbig_register_grpc", ident.span());
+        let attr_interceptor = Ident::new("mrbig_interceptor", ident.span());
+        let attr_transcoding = Ident::new("mrbig_transcoding", ident.span());
 
         // check if reflection is disabled
         let disable_reflection = attrs.iter().any(|a| {
@@ -50,6 +49,29 @@ impl Generate {
                 .unwrap_or(false)
         });
 
+        // check if the HTTP routes generated by mrbig_build are served
+        let transcoding = attrs.iter().any(|a| {
+            a.path
+                .get_ident()
+                .map(|id| *id == attr_transcoding)
+                .unwrap_or(false)
+        });
+
+        // get the interceptor attribute arguments, in order.
+        let interceptor_args: Vec<InterceptorArg> = attrs
+            .iter()
+            .filter(|a| {
+                a.path
+                    .get_ident()
+                    .map(|id| *id == attr_interceptor)
+                    .unwrap_or(false)
+            })
+            .map(|a| {
+                a.parse_args()
+                    .expect("bad format for interceptor arguments")
+            })
+            .collect();
+
         // get the register_grpc_endpoint attribute arguments.
         let grpc_args: Vec<Arg> = attrs
             .into_iter()
@@ -65,11 +87,22 @@ impl Generate {
             })
             .collect();
 
+        // interceptors apply to registered services only
+        for arg in &interceptor_args {
+            for service in arg.services.iter().flatten() {
+                if !grpc_args.iter().any(|a| a.service_fqn == *service) {
+                    panic!("interceptor for '{}', which is not registered", service);
+                }
+            }
+        }
+
         Generate {
             grpc_args,
+            interceptor_args,
             ident,
             disable_reflection,
             disable_health,
+            transcoding,
         }
     }
 
@@ -78,6 +111,25 @@ impl Generate {
     }
 
     fn empty_run_function(&self) -> ItemFn {
+        let (generics, fn_args) = self.handler_params();
+        parse_quote! {
+            async fn run<#(#generics),*>(self, #(#fn_args),*) -> std::result::Result<(), ::mrbig_core::Error> {
+                Err(::mrbig_core::Error::new("cannot run, not implemented"))
+            }
+        }
+    }
+
+    fn empty_start_function(&self) -> ItemFn {
+        let (generics, fn_args) = self.handler_params();
+        parse_quote! {
+            async fn start<#(#generics),*>(self, #(#fn_args),*) -> std::result::Result<::mrbig_core::ServerHandle, ::mrbig_core::Error> {
+                Err(::mrbig_core::Error::new("cannot start, not implemented"))
+            }
+        }
+    }
+
+    // Generic types and arguments of the service handlers
+    fn handler_params(&self) -> (Vec<syn::GenericParam>, Vec<syn::ExprType>) {
         let span = self.ident.span();
 
         let generics: Vec<syn::GenericParam> = self
@@ -104,20 +156,32 @@ impl Generate {
                 parse_quote! { #sname: #tname }
             })
             .collect();
-        parse_quote! {
-            async fn run<#(#generics),*>(self, #(#fn_args),*) -> std::result::Result<(), ::mrbig_core::Error> {
-                Err(::mrbig_core::Error::new("cannot run, not implemented"))
-            }
-        }
+
+        (generics, fn_args)
+    }
+
+    // Register the interceptors of the attributes
+    fn push_interceptors(&self, interceptors: &Ident) -> Vec<syn::Stmt> {
+        self.interceptor_args
+            .iter()
+            .map(|arg| {
+                let function = &arg.function;
+                match &arg.services {
+                    Some(services) => parse_quote! {
+                        #interceptors.push(Some(&[#(#services),*]), #function);
+                    },
+                    None => parse_quote! {
+                        #interceptors.push(None, #function);
+                    },
+                }
+            })
+            .collect()
     }
 
     // Create the servers defined by the user
-    fn user_servers(&self, icept: &Interceptor) -> Vec<ServerHandler> {
+    fn user_servers(&self, interceptors: &Ident) -> Vec<ServerHandler> {
         let span = self.ident.span();
 
-        let interceptor_name = &icept.name;
-        let interceptor_flag = &icept.flag;
-
         self.grpc_args
             .iter()
             .enumerate()
@@ -125,12 +189,13 @@ impl Generate {
                 let handler = &h.handler;
                 let arg_name = Ident::new(&format!("s{}", i), span);
                 let handler_name = Ident::new(&format!("handler{}", i), span);
+                let service = &h.service_fqn;
 
                 ServerHandler {
                     stmt: parse_quote! {
-                        let #handler_name = match #interceptor_flag {
-                            true => #handler::with_interceptor(#arg_name, #interceptor_name),
-                            false => #handler::new(#arg_name),
+                        let #handler_name = match #interceptors.chain(#service) {
+                            Some(chain) => #handler::with_interceptor(#arg_name, chain),
+                            None => #handler::new(#arg_name),
                         };
                     },
                     ident: handler_name,
@@ -242,6 +307,9 @@ impl Generate {
 
                 let context = micro.get_context_mut();
         context.set_health_reporter(reporter).await;
+        for svc in &[ #lits ] {
+            context.add_health_service(svc);
+        }
 
         health_server
             };
@@ -272,12 +340,16 @@ impl Generate {
             .collect()
     }
 
-    fn init_method_block(&self, config_stmt: syn::Stmt) -> Block {
+    fn init_method_block(&self, config_expr: syn::Expr) -> Block {
         parse_quote! {
         {
                     // Import locally to disambiguate trait methods
                     use ::mrbig_core::config::Configurable;
-                    #config_stmt;
+
+                    // Help, version, etc. ask to exit before running
+                    if ::mrbig_core::Init::from_load(#config_expr)? == ::mrbig_core::Init::Exit {
+                        return Ok(::mrbig_core::Init::Exit);
+                    }
 
                     ::mrbig_core::init(self.get_config().unwrap())?;
 
@@ -288,21 +360,23 @@ impl Generate {
                     let context = self.get_context_mut();
                     context.set_server(server);
 
-                    Ok(())
+                    Ok(::mrbig_core::Init::Ready)
         }
         }
     }
 
-    fn run_method_impl(&self) -> Block {
+    fn start_method_impl(&self) -> Block {
         let span = self.ident.span();
 
-        let icept = Interceptor {
-            name: Ident::new("interceptor", span),
-            flag: Ident::new("will_intercept", span),
-        };
+        let interceptors = Ident::new("interceptors", span);
+        let push_interceptors = self.push_interceptors(&interceptors);
+        let (authenticated, exempt): (Vec<&Arg>, Vec<&Arg>) =
+            self.grpc_args.iter().partition(|arg| arg.auth);
+        let authenticated = authenticated.iter().map(|arg| &arg.service_fqn);
+        let exempt = exempt.iter().map(|arg| &arg.service_fqn);
 
         let servers: Vec<ServerHandler> = self
-            .user_servers(&icept)
+            .user_servers(&interceptors)
             .into_iter()
             .chain(self.builtin_servers().into_iter())
             .collect();
@@ -311,55 +385,111 @@ impl Generate {
         let (create_handlers, handler_list): (Vec<syn::Stmt>, Vec<syn::Ident>) =
             servers.into_iter().map(|s| (s.stmt, s.ident)).unzip();
 
-        let Interceptor {
-            name: interceptor_name,
-            flag: interceptor_flag,
-        } = icept;
+        // the HTTP routes generated by mrbig_build, only when opted in
+        let http_routes: syn::Expr = if self.transcoding {
+            parse_quote! {
+                {
+                    mod http_routes {
+                        use ::mrbig_core::transcode::HttpRoute;
+                        include!(concat!(env!("OUT_DIR"), "/mrbig_build_http_routes.rs"));
+                    }
+                    Some(::mrbig_core::transcode::HttpRoutes {
+                        routes: http_routes::ROUTES,
+                        descriptors: http_routes::DESCRIPTORS,
+                    })
+                }
+            }
+        } else {
+            parse_quote! { None }
+        };
 
         parse_quote! {
             {
                 let mut micro = self;
 
+                // Trap SIGHUP first, so that it does not kill the process
+                // while starting
+                let hangup = ::mrbig_core::trap_hangup();
+
                 // Import locally to disambiguate trait methods
                 use ::mrbig_core::config::Configurable;
                 use ::mrbig_core::context::WithContext;
 
-                let opts = match micro.take_config() {
-                    Some(config) => config.service,
+                let config = match micro.take_config() {
+                    Some(config) => config,
                     None => {
                         return Err(::mrbig_core::Error::new("service not initialized"));
                     }
                 };
+                let opts = config.service.clone();
 
-                let address = format!("{}:{}", opts.hostname, opts.port);
+                // Every service, builtin ones included, behind the layers,
+                // the gRPC-Web one outermost
+                let mut layers = ::mrbig_core::layer::Layers::default();
+                ::mrbig_core::push_grpc_web_layer(&opts, &mut layers)?;
+                layers.extend(micro.get_context().get_layers());
 
-                let #interceptor_name = ::mrbig_core::default_grpc_interceptor;
-                let #interceptor_flag = opts.debug;
+                // Interceptors run in order: the debug one, the authentication,
+                // the attribute ones, then the ones registered on the context
+                let mut #interceptors = ::mrbig_core::interceptor::Interceptors::default();
+                if opts.debug {
+                    #interceptors.push(None, ::mrbig_core::default_grpc_interceptor);
+                }
+                ::mrbig_core::push_auth_interceptors(
+                    &opts,
+                    &mut #interceptors,
+                    &mut layers,
+                    &[#(#authenticated),*],
+                    &[#(#exempt),*],
+                )?;
+                #(#push_interceptors)*
+                #interceptors.extend(micro.get_context().get_interceptors());
 
                 #(#create_handlers)*
 
+                // Bind right away, the actual addresses are known from here
+                let listeners = ::mrbig_core::listen::bind(&opts).await?;
+
                 if opts.debug {
-                    ::mrbig_core::log::debug!("serving at: {}", address);
+                    ::mrbig_core::log::debug!("serving at: {:?}", listeners.addresses());
                 }
 
-                ::mrbig_core::pre_server(&opts);
-
-                let signal = ::mrbig_core::trap_signal();
+                ::mrbig_core::pre_server(&opts)?;
 
                 let mut builder = micro
                     .get_context_mut()
                     .take_server()
                     .ok_or(::mrbig_core::Error::new("no server available"))?;
 
-                builder
-                    #(.add_service(#handler_list))*
-                .serve_with_shutdown(address.parse()?, signal)
-                    .await
-                    .map_err(|e| format!("server error: {}", e))?;
+                // The routes serve the calls made over HTTP/1.1, transcoded or not
+                let mut routes = ::mrbig_core::layer::Routes::new(&layers);
+                let router = builder
+                    #(.add_service(routes.add(::mrbig_core::layer::Layered::new(#handler_list, &layers))))*;
+
+                let http = ::mrbig_core::http_service(&opts, routes, #http_routes)?;
+
+                let health = micro.get_context().get_health_reporters().await;
+
+                let addresses = listeners.addresses().to_vec();
+                let handle = ::mrbig_core::ServerHandle::spawn(addresses, move |signal| async move {
+                    // Shut down gracefully on SIGTERM, SIGINT or when asked
+                    let server = ::mrbig_core::shutdown::graceful(
+                        |stop| ::mrbig_core::serve(router, http, listeners, &opts, stop),
+                        signal,
+                        &opts,
+                        health,
+                    );
+
+                    // Reload the configuration on SIGHUP while serving
+                    ::mrbig_core::with_reload(&mut micro, config, hangup, server).await?;
 
-                ::mrbig_core::log::info!("gracefully shutting down");
+                    micro.get_context().run_shutdown_hooks().await;
+                    ::mrbig_core::log::info!("gracefully shut down");
 
-                Ok(())
+                    Ok(())
+                });
+
+                Ok(handle)
             }
         }
     }
@@ -371,20 +501,40 @@ impl Into<TokenStream> for Generate {
         // an empty run implementation for the trait declaration.
         let fun_empty = self.empty_run_function();
 
-        // a full implementation of the run method with the same signature.
+        let start_empty = self.empty_start_function();
+
+        // a full implementation of the start method with the same signature.
+        let mut start_complete: ItemFn = start_empty.clone();
+        start_complete.vis = syn::Visibility::Inherited;
+        start_complete.block = Box::new(self.start_method_impl());
+
+        // run is start, then waiting for the server to stop.
+        let handlers: Vec<Ident> = (0..self.grpc_args.len())
+            .map(|i| Ident::new(&format!("s{}", i), self.ident.span()))
+            .collect();
         let mut fun_complete: ItemFn = fun_empty.clone();
         fun_complete.vis = syn::Visibility::Inherited;
-        fun_complete.block = Box::new(self.run_method_impl());
+        fun_complete.block = Box::new(parse_quote! {
+            {
+                self.start(#(#handlers),*).await?.join().await
+            }
+        });
 
         // the trait is specific to the struct being derived.
         let trait_name = self.trait_name();
 
+        // start from arguments and a tuple of handlers, for generic code.
+        let (generics, _) = self.handler_params();
+        let types: Vec<Ident> = (0..self.grpc_args.len())
+            .map(|i| Ident::new(&format!("T{}", i), self.ident.span()))
+            .collect();
+
         // prepare the init method default implementations
         let init_def = self.init_method_block(parse_quote! {
-            self.load_from_args()?;
+            self.load_from_args()
         });
         let init_with_args_def = self.init_method_block(parse_quote! {
-            self.load_from_args_vec(args)?;
+            self.load_from_args_vec(args)
         });
 
         let ident = self.ident;
@@ -393,21 +543,41 @@ impl Into<TokenStream> for Generate {
             #[allow(unused_variables, dead_code)]
             #[tonic::async_trait]
             trait #trait_name: ::mrbig_core::config::Configurable<'static> + ::mrbig_core::context::WithContext + Send + Sync + Sized + 'static {
-            async fn init_with_args(&mut self, args: Vec<String>) -> ::std::result::Result<(), ::mrbig_core::Error> {
+            async fn init_with_args(&mut self, args: Vec<String>) -> ::std::result::Result<::mrbig_core::Init, ::mrbig_core::Error> {
         #init_with_args_def
         }
 
-            async fn init(&mut self) -> ::std::result::Result<(), ::mrbig_core::Error> {
+            async fn init(&mut self) -> ::std::result::Result<::mrbig_core::Init, ::mrbig_core::Error> {
                 #init_def
         }
 
+                #start_empty
+
                 #fun_empty
             }
 
             #[tonic::async_trait]
             impl #trait_name for #ident {
+                #start_complete
+
                 #fun_complete
             }
+
+            impl<#(#generics),*> ::mrbig_core::Start<(#(#types,)*)> for #ident {
+                fn start_with_args(
+                    mut self,
+                    args: Vec<String>,
+                    handlers: (#(#types,)*),
+                ) -> ::std::pin::Pin<Box<dyn ::std::future::Future<Output = ::std::result::Result<::mrbig_core::ServerHandle, ::mrbig_core::Error>> + Send>> {
+                    Box::pin(async move {
+                        let (#(#handlers,)*) = handlers;
+                        if <Self as #trait_name>::init_with_args(&mut self, args).await? == ::mrbig_core::Init::Exit {
+                            return Err(::mrbig_core::Error::new("the service exited on init"));
+                        }
+                        <Self as #trait_name>::start(self, #(#handlers),*).await
+                    })
+                }
+            }
         })
     }
 }
@@ -0,0 +1,90 @@
+include!("greeter_server_head.rs");
+
+use mrbig_core::config::Flag;
+use mrbig_core::Init;
+use mrbig_derive::{Configurable, Run};
+
+use helloworld::greeter_server::GreeterServer;
+
+// User configuration parameters
+#[derive(Default, serde_derive::Deserialize)]
+pub struct User {
+    greeting: String,
+    db: Db,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Default, serde_derive::Deserialize)]
+pub struct Db {
+    url: String,
+    pool: Option<u32>,
+}
+
+fn flags() -> Vec<Flag> {
+    vec![Flag::new("hi", "greeting", "set the greeting").short('g')]
+}
+
+// Use macro to register endpoints
+#[derive(Run, Configurable, Default)]
+#[mrbig_register_grpc(service = "helloworld.Greeter")]
+pub struct Micro {
+    context: mrbig_core::Context,
+    #[mrbig_config_extra(flags = "flags")]
+    user: User,
+}
+
+fn args(args: &[&str]) -> Vec<String> {
+    std::iter::once("micro")
+        .chain(args.iter().copied())
+        .map(String::from)
+        .collect()
+}
+
+#[tokio::main]
+async fn main() {
+    // Every parameter can be set without a config file
+    let mut service = Micro::default();
+    let init = service
+        .init_with_args(args(&[
+            "-g",
+            "Hey",
+            "--db-url",
+            "postgres://localhost/test",
+            "--db-pool",
+            "4",
+            "--dry-run",
+        ]))
+        .await
+        .expect("failed to init service");
+
+    assert_eq!(init, Init::Ready);
+    assert_eq!(service.user.greeting, "Hey");
+    assert_eq!(service.user.db.url, "postgres://localhost/test");
+    assert_eq!(service.user.db.pool, Some(4));
+    assert!(service.user.dry_run);
+
+    // The declared flag replaces the generated one
+    let mut service = Micro::default();
+    assert!(service
+        .init_with_args(args(&["--greeting", "Hey", "--db-url", "x"]))
+        .await
+        .is_err());
+
+    // Bad values are reported with their key
+    let mut service = Micro::default();
+    let err = service
+        .init_with_args(args(&["--hi", "Hey", "--db-url", "x", "--db-pool", "many"]))
+        .await
+        .err()
+        .expect("invalid value was accepted");
+    assert!(err.to_string().contains("`db.pool`"), "{}", err);
+
+    // The generated flags are listed by --help
+    let mut service = Micro::default();
+    let init = service
+        .init_with_args(args(&["--help"]))
+        .await
+        .expect("failed to print help");
+    assert_eq!(init, Init::Exit);
+}
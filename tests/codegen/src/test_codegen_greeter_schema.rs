@@ -0,0 +1,70 @@
+include!("greeter_server_head.rs");
+
+use mrbig_core::config::Configurable;
+use mrbig_core::Init;
+use mrbig_derive::{Configurable, Run};
+
+use helloworld::greeter_server::GreeterServer;
+
+/// Parameters of the greeter.
+#[derive(Default, serde_derive::Deserialize, serde_derive::Serialize)]
+#[derive(mrbig_core::schemars::JsonSchema)]
+#[schemars(crate = "mrbig_core::schemars")]
+pub struct User {
+    /// Greeting sent back to the callers.
+    #[serde(default = "default_greeting")]
+    greeting: String,
+    /// Number of attempts before giving up.
+    retries: u32,
+}
+
+fn default_greeting() -> String {
+    "Hello".into()
+}
+
+// Use macro to register endpoints
+#[derive(Run, Configurable, Default)]
+#[mrbig_register_grpc(service = "helloworld.Greeter")]
+pub struct Micro {
+    context: mrbig_core::Context,
+    #[mrbig_config_extra(schema = "true", print = "true")]
+    user: User,
+}
+
+#[tokio::main]
+async fn main() {
+    let service = Micro::default();
+    let schema = service.config_schema();
+
+    assert_eq!(schema["description"], "Parameters of the greeter.");
+    let greeting = &schema["properties"]["greeting"];
+    assert_eq!(greeting["description"], "Greeting sent back to the callers.");
+    assert_eq!(greeting["default"], "Hello");
+    assert_eq!(schema["required"], mrbig_core::serde_json::json!(["retries"]));
+    assert_eq!(
+        schema["properties"]["service"]["properties"]["hostname"]["default"],
+        "0.0.0.0"
+    );
+
+    // The schema is printed without a config file
+    let mut service = Micro::default();
+    let init = service
+        .init_with_args(vec!["micro".into(), "--config-schema".into()])
+        .await
+        .expect("failed to print the schema");
+    assert_eq!(init, Init::Exit);
+
+    // The configuration is printed as deserialized, defaults included
+    let args = vec!["micro", "--set", "retries=2", "--print-config"];
+    let printed = match Micro::default()
+        .load_from_args_vec(args.into_iter().map(String::from).collect())
+        .unwrap_err()
+        .inner
+    {
+        mrbig_core::Inner::Exit(printed) => printed,
+        e => panic!("unexpected error {:?}", e),
+    };
+    assert!(printed.contains("greeting = \"Hello\" # default"), "{}", printed);
+    assert!(printed.contains("retries = 2 # cli --set"), "{}", printed);
+    assert!(printed.contains("\n[service]\n"), "{}", printed);
+}
@@ -0,0 +1,68 @@
+include!("greeter_server_head.rs");
+
+use mrbig_core::config::{ConfigSource, DirectorySource, FileSource, Source};
+use mrbig_derive::{Configurable, Run};
+use std::sync::Arc;
+
+use helloworld::greeter_server::GreeterServer;
+
+// User configuration parameters
+#[derive(Default, serde_derive::Deserialize)]
+pub struct User {
+    greeting: String,
+    db: Db,
+}
+
+#[derive(Default, serde_derive::Deserialize)]
+pub struct Db {
+    url: String,
+    password: String,
+}
+
+static DEFAULTS: &str = "/tmp/mrbig_test_greeter_sources.toml";
+static SECRETS: &str = "/tmp/mrbig_test_greeter_sources";
+
+fn sources() -> Vec<Arc<dyn ConfigSource>> {
+    vec![
+        Arc::new(FileSource::new(DEFAULTS)),
+        Arc::new(DirectorySource::new(SECRETS).prefix("db")),
+    ]
+}
+
+// Use macro to register endpoints
+#[derive(Run, Configurable, Default)]
+#[mrbig_register_grpc(service = "helloworld.Greeter")]
+pub struct Micro {
+    context: mrbig_core::Context,
+    #[mrbig_config_extra(sources = "sources")]
+    user: User,
+}
+
+#[tokio::main]
+async fn main() {
+    std::fs::write(
+        DEFAULTS,
+        "greeting = \"Hello\"\n[db]\nurl = \"postgres://db\"\npassword = \"default\"\n",
+    )
+    .expect("failed to write to temporary config file");
+    std::fs::create_dir_all(SECRETS).expect("failed to create secrets directory");
+    std::fs::write(format!("{}/password", SECRETS), "s3cret\n")
+        .expect("failed to write to secret file");
+
+    let mut service = Micro::default();
+    service
+        .init_with_args(vec!["micro".into()])
+        .await
+        .expect("failed to init service");
+
+    assert_eq!(service.user.greeting, "Hello");
+    assert_eq!(service.user.db.url, "postgres://db");
+    assert_eq!(service.user.db.password, "s3cret");
+
+    use mrbig_core::config::Configurable;
+    let config = service.get_config().expect("no config available");
+    assert_eq!(
+        config.source_of("db.password"),
+        Source::File(format!("{}/password", SECRETS).into())
+    );
+}
@@ -0,0 +1,69 @@
+include!("greeter_server_head.rs");
+
+use mrbig_core::config::Validation;
+use mrbig_core::error::Inner;
+use mrbig_derive::{Configurable, Run};
+
+use helloworld::greeter_server::GreeterServer;
+
+// User configuration parameters
+#[derive(Default, serde_derive::Deserialize)]
+pub struct User {
+    greeting: String,
+    retries: u32,
+}
+
+fn check_user(user: &User, validation: &mut Validation) {
+    validation.check(!user.greeting.is_empty(), "greeting", "must not be empty");
+    validation.check(user.retries < 10, "retries", "must be less than 10");
+}
+
+// Use macro to register endpoints
+#[derive(Run, Configurable, Default)]
+#[mrbig_register_grpc(service = "helloworld.Greeter")]
+pub struct Micro {
+    context: mrbig_core::Context,
+    #[mrbig_config_extra(validate = "check_user")]
+    user: User,
+}
+
+static TOML_CONFIG: &str = "/tmp/mrbig_test_greeter_validate.toml";
+
+fn args() -> Vec<String> {
+    vec!["micro".into(), "--config".into(), TOML_CONFIG.into()]
+}
+
+#[tokio::main]
+async fn main() {
+    // Every invalid value is reported at once, port 0 being left to the system
+    std::fs::write(
+        TOML_CONFIG,
+        "greeting = \"\"\nretries = 12\n[service]\nport = 0\nhostname = \"not a host\"\n",
+    )
+    .expect("failed to write to temporary config file");
+
+    let mut service = Micro::default();
+    let err = service
+        .init_with_args(args())
+        .await
+        .err()
+        .expect("invalid config was accepted");
+
+    let keys: Vec<String> = match err.inner {
+        Inner::Validation(errors) => errors.into_iter().map(|e| e.key).collect(),
+        e => panic!("unexpected error: {:?}", e),
+    };
+    assert_eq!(keys, vec!["service.hostname", "greeting", "retries"]);
+
+    // A valid config goes through
+    std::fs::write(TOML_CONFIG, "greeting = \"Hi\"\nretries = 3\n")
+        .expect("failed to write to temporary config file");
+
+    let mut service = Micro::default();
+    service
+        .init_with_args(args())
+        .await
+        .expect("failed to init service");
+    assert_eq!(service.user.greeting, "Hi");
+    assert_eq!(service.user.retries, 3);
+}
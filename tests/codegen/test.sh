@@ -7,6 +7,10 @@ cargo build --quiet \
       --bin test_codegen_greeter \
       --bin test_codegen_greeter_config \
       --bin test_codegen_greeter_extra \
+      --bin test_codegen_greeter_flags \
+      --bin test_codegen_greeter_schema \
+      --bin test_codegen_greeter_sources \
+      --bin test_codegen_greeter_validate \
       --bin test_codegen_register
 
 # Project path
@@ -24,4 +28,8 @@ echo "Testing codegen"
 $COV ${TARGET_DIR}/test_codegen_greeter
 $COV ${TARGET_DIR}/test_codegen_greeter_config
 $COV ${TARGET_DIR}/test_codegen_greeter_extra
+$COV ${TARGET_DIR}/test_codegen_greeter_flags
+$COV ${TARGET_DIR}/test_codegen_greeter_schema
+$COV ${TARGET_DIR}/test_codegen_greeter_sources
+$COV ${TARGET_DIR}/test_codegen_greeter_validate
 $COV ${TARGET_DIR}/test_codegen_register
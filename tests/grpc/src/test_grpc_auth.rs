@@ -0,0 +1,192 @@
+include!("hotel_head.rs");
+
+pub mod helloworld {
+    tonic::include_proto!("helloworld");
+}
+
+use helloworld::greeter_client::GreeterClient;
+use helloworld::greeter_server::{Greeter, GreeterServer};
+use helloworld::{HelloReply, HelloRequest};
+use hotel::hotel_client::HotelClient;
+use jsonwebtoken::{EncodingKey, Header};
+use mrbig_core::auth::Claims;
+use mrbig_core::testing::{self, TestServer};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tonic::metadata::MetadataValue;
+use tonic::{Code, Request, Status};
+
+#[derive(Debug, Default)]
+pub struct Welcome {}
+
+#[tonic::async_trait]
+impl Greeter for Welcome {
+    async fn say_hello(
+        &self,
+        request: tonic::Request<HelloRequest>,
+    ) -> Result<tonic::Response<HelloReply>, tonic::Status> {
+        let claims = Claims::of(&request).expect("authenticated call without claims");
+        let message = format!("Hello {}", claims.subject().unwrap_or("nobody"));
+
+        Ok(tonic::Response::new(HelloReply { message }))
+    }
+}
+
+// Tells whether the claims reached the handler of a service left alone
+#[derive(Debug, Default)]
+pub struct Concierge {}
+
+#[tonic::async_trait]
+impl Hotel for Concierge {
+    async fn rates(
+        &self,
+        request: tonic::Request<HotelRequest>,
+    ) -> Result<tonic::Response<HotelResponse>, tonic::Status> {
+        let mut hotel = profile::Hotel::default();
+        hotel.name = match Claims::of(&request) {
+            Some(claims) => claims.subject().unwrap_or_default().to_string(),
+            None => "anonymous".into(),
+        };
+
+        Ok(tonic::Response::new(HotelResponse {
+            hotels: vec![hotel],
+            rate_plans: vec![],
+        }))
+    }
+}
+
+use mrbig_derive::{Configurable, Run};
+
+// Use macro to register endpoints
+#[derive(Run, Configurable, Default)]
+#[mrbig_register_grpc(service = "helloworld.Greeter")]
+#[mrbig_register_grpc(service = "hotel.Hotel", auth = false)]
+pub struct Micro {
+    context: mrbig_core::Context,
+}
+
+const SECRET: &str = "s3cret";
+
+fn config_file() -> String {
+    format!(
+        r#"
+        [service.auth]
+        secret = "{}"
+        audience = "greeter"
+
+        [service.auth.policies."helloworld.Greeter.SayHello"]
+        roles = ["greeter"]
+        "#,
+        SECRET
+    )
+}
+
+fn token(subject: &str, roles: &[&str], audience: &str, expires_in: i64) -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    let claims = serde_json::json!({
+        "sub": subject,
+        "aud": audience,
+        "exp": now.as_secs() as i64 + expires_in,
+        "roles": roles,
+    });
+    let key = EncodingKey::from_secret(SECRET.as_bytes());
+    jsonwebtoken::encode(&Header::default(), &claims, &key).unwrap()
+}
+
+fn bearer<T>(message: T, token: Option<String>) -> Request<T> {
+    let mut request = Request::new(message);
+    if let Some(token) = token {
+        let value = format!("Bearer {}", token).parse().unwrap();
+        request.metadata_mut().insert("authorization", value);
+    }
+    request
+}
+
+async fn say_hello(server: &TestServer, token: Option<String>) -> Result<String, Status> {
+    let hello = bearer(HelloRequest { name: "you".into() }, token);
+    let reply = GreeterClient::new(server.channel())
+        .say_hello(hello)
+        .await?;
+    Ok(reply.into_inner().message)
+}
+
+fn decisions(decision: &str) -> u64 {
+    let families = mrbig_core::prometheus::gather();
+    let family = families
+        .iter()
+        .find(|f| f.get_name() == "mrbig_authorization_decisions_total");
+    family.map_or(0, |family| {
+        family
+            .get_metric()
+            .iter()
+            .filter(|m| m.get_label().iter().any(|l| l.get_value() == decision))
+            .map(|m| m.get_counter().get_value() as u64)
+            .sum()
+    })
+}
+
+/// Authenticates the greeter with HS256 tokens and checks its policy,
+/// leaving the hotel alone, and checks the claims reach the handlers and
+/// cannot be forged.
+#[tokio::main]
+async fn main() {
+    let config = std::env::temp_dir().join(format!("test_grpc_auth_{}.toml", std::process::id()));
+    std::fs::write(&config, config_file()).expect("failed to write the config");
+
+    let path = config.to_str().unwrap().to_string();
+    let server = TestServer::start(async {
+        let mut service = Micro::default();
+        service
+            .init_with_args(testing::args(&["--config", &path]))
+            .await?;
+        service.start(Welcome {}, Concierge {}).await
+    })
+    .await
+    .expect("failed to start service");
+
+    // Valid tokens let the calls in, with their claims
+    assert_eq!(
+        say_hello(&server, Some(token("ada", &["greeter"], "greeter", 300)))
+            .await
+            .unwrap(),
+        "Hello ada"
+    );
+
+    // Callers without the role are turned down, and the decisions counted
+    let status = say_hello(&server, Some(token("bob", &["guest"], "greeter", 300)))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    assert_eq!(decisions("allowed"), 1);
+    assert_eq!(decisions("denied"), 1);
+
+    // Missing, expired or misdirected tokens are turned down
+    let rejected = vec![
+        None,
+        Some(token("ada", &["greeter"], "greeter", -3600)),
+        Some(token("ada", &["greeter"], "hotel", 300)),
+        Some("not.a.token".to_string()),
+    ];
+    for token in rejected {
+        let status = say_hello(&server, token).await.unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+    }
+
+    // The hotel opted out, and does not believe claims sent by the client
+    let mut rates = bearer(HotelRequest::default(), None);
+    let forged = MetadataValue::from_bytes(br#"{"sub":"root"}"#);
+    rates.metadata_mut().insert_bin("mrbig-claims-bin", forged);
+    let reply = HotelClient::new(server.channel())
+        .rates(rates)
+        .await
+        .expect("failed to call the hotel without a token");
+    assert_eq!(reply.into_inner().hotels[0].name, "anonymous");
+
+    // Health is not authenticated
+    assert_eq!(
+        server.health_status("helloworld.Greeter").await.unwrap(),
+        mrbig_core::tonic_health::ServingStatus::Serving
+    );
+
+    server.stop().await.expect("failed to stop service");
+    std::fs::remove_file(&config).expect("failed to remove the config");
+}
@@ -0,0 +1,86 @@
+pub mod helloworld {
+    tonic::include_proto!("helloworld");
+}
+
+use helloworld::greeter_client::GreeterClient;
+use helloworld::greeter_server::{Greeter, GreeterServer};
+use helloworld::{HelloReply, HelloRequest};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tonic::transport::Endpoint;
+
+#[derive(Debug, Default)]
+pub struct Welcome {}
+
+#[tonic::async_trait]
+impl Greeter for Welcome {
+    async fn say_hello(
+        &self,
+        request: tonic::Request<HelloRequest>,
+    ) -> Result<tonic::Response<HelloReply>, tonic::Status> {
+        Ok(tonic::Response::new(HelloReply {
+            message: format!("Hello {}", request.into_inner().name),
+        }))
+    }
+}
+
+use mrbig_derive::{Configurable, Run};
+
+// Use macro to register endpoints
+#[derive(Run, Configurable, Default)]
+#[mrbig_register_grpc(service = "helloworld.Greeter")]
+pub struct Micro {
+    context: mrbig_core::Context,
+}
+
+/// Starts the service on a port left to the system, calls it at the
+/// address bound and shuts it down through its handle.
+#[tokio::main]
+async fn main() {
+    let mut service = Micro::default();
+    let args = vec![
+        "micro".into(),
+        "--hostname".into(),
+        "127.0.0.1".into(),
+        "--port".into(),
+        "0".into(),
+    ];
+    service
+        .init_with_args(args)
+        .await
+        .expect("failed to init service");
+
+    let hook_ran = Arc::new(AtomicBool::new(false));
+    let hook_flag = hook_ran.clone();
+    service.context.on_shutdown(move || async move {
+        hook_flag.store(true, Ordering::SeqCst);
+    });
+
+    let server = service
+        .start(Welcome {})
+        .await
+        .expect("failed to start service");
+    let addr = server.local_addr().expect("no TCP address bound");
+    assert_eq!(addr.ip().to_string(), "127.0.0.1");
+    assert_ne!(addr.port(), 0);
+    assert_eq!(server.addresses().len(), 1);
+
+    // The server is bound by the time `start` returns
+    let endpoint = Endpoint::from_shared(format!("http://{}", addr)).unwrap();
+    let channel = endpoint.connect().await.expect("failed to connect");
+    let reply = GreeterClient::new(channel)
+        .say_hello(HelloRequest {
+            name: "handle".into(),
+        })
+        .await
+        .expect("failed to say hello");
+    assert_eq!(reply.into_inner().message, "Hello handle");
+
+    server.shutdown();
+    server.shutdown();
+    server.join().await.expect("failed to shut down");
+    assert!(hook_ran.load(Ordering::SeqCst));
+
+    // The port is released
+    assert!(endpoint.connect().await.is_err());
+}
@@ -0,0 +1,164 @@
+include!("hotel_head.rs");
+
+pub mod helloworld {
+    tonic::include_proto!("helloworld");
+}
+
+use helloworld::greeter_client::GreeterClient;
+use helloworld::greeter_server::{Greeter, GreeterServer};
+use helloworld::{HelloReply, HelloRequest};
+use hotel::hotel_client::HotelClient;
+use mrbig_core::testing::{self, TestServer};
+use tonic::{Code, Request, Status};
+
+#[derive(Debug, Default)]
+pub struct Welcome {}
+
+#[tonic::async_trait]
+impl Greeter for Welcome {
+    async fn say_hello(
+        &self,
+        request: tonic::Request<HelloRequest>,
+    ) -> Result<tonic::Response<HelloReply>, tonic::Status> {
+        // the interceptors hand their values over as metadata
+        let user = request.metadata().get("x-user").unwrap().to_str().unwrap();
+        let steps: Vec<&str> = request
+            .metadata()
+            .get_all("x-step")
+            .iter()
+            .map(|v| v.to_str().unwrap())
+            .collect();
+        let message = format!(
+            "Hello {} ({}, {})",
+            request.get_ref().name,
+            user,
+            steps.join(" > ")
+        );
+
+        Ok(tonic::Response::new(HelloReply { message }))
+    }
+}
+
+// Lets the callers with the token in and tells the handlers who they are
+fn authenticate(mut request: Request<()>) -> Result<Request<()>, Status> {
+    match request.metadata().get("x-token") {
+        Some(token) if token == "secret" => {
+            request
+                .metadata_mut()
+                .insert("x-user", "alice".parse().unwrap());
+            Ok(request)
+        }
+        _ => Err(Status::unauthenticated("missing or wrong token")),
+    }
+}
+
+fn step(request: Request<()>, name: &'static str) -> Result<Request<()>, Status> {
+    if request.metadata().get("x-deny").map(|v| v == name) == Some(true) {
+        return Err(Status::permission_denied(name));
+    }
+
+    let mut request = request;
+    request
+        .metadata_mut()
+        .append("x-step", name.parse().unwrap());
+    Ok(request)
+}
+
+fn greeter_step(request: Request<()>) -> Result<Request<()>, Status> {
+    step(request, "attribute")
+}
+
+use mrbig_derive::{Configurable, Run};
+
+// Use macro to register endpoints
+#[derive(Run, Configurable, Default)]
+#[mrbig_register_grpc(service = "helloworld.Greeter")]
+#[mrbig_register_grpc(service = "hotel.Hotel")]
+#[mrbig_interceptor(fn = "authenticate")]
+#[mrbig_interceptor(fn = "greeter_step", services = "helloworld.Greeter")]
+pub struct Micro {
+    context: mrbig_core::Context,
+}
+
+fn request<T>(message: T, metadata: &[(&'static str, &'static str)]) -> Request<T> {
+    let mut request = Request::new(message);
+    for (key, value) in metadata {
+        request.metadata_mut().insert(*key, value.parse().unwrap());
+    }
+    request
+}
+
+async fn say_hello(
+    server: &TestServer,
+    metadata: &[(&'static str, &'static str)],
+) -> Result<String, Status> {
+    let hello = request(HelloRequest { name: "you".into() }, metadata);
+    let reply = GreeterClient::new(server.channel())
+        .say_hello(hello)
+        .await?;
+    Ok(reply.into_inner().message)
+}
+
+async fn rates(
+    server: &TestServer,
+    metadata: &[(&'static str, &'static str)],
+) -> Result<(), Status> {
+    let rates = request(HotelRequest::default(), metadata);
+    HotelClient::new(server.channel()).rates(rates).await?;
+    Ok(())
+}
+
+/// Registers interceptors with the attribute and on the context, for all
+/// services or one of them, and checks they run in order, reject calls and
+/// hand values over to the handlers.
+#[tokio::main]
+async fn main() {
+    let server = TestServer::start(async {
+        let mut service = Micro::default();
+        service.init_with_args(testing::args(&[])).await?;
+
+        service
+            .context
+            .add_interceptor_for(&["helloworld.Greeter"], |r| step(r, "context"));
+        service.context.add_interceptor(|r| step(r, "all"));
+
+        service.start(Welcome {}, Booker {}).await
+    })
+    .await
+    .expect("failed to start service");
+
+    let token = ("x-token", "secret");
+
+    // Interceptors run in order, the attribute ones first
+    assert_eq!(
+        say_hello(&server, &[token]).await.unwrap(),
+        "Hello you (alice, attribute > context > all)"
+    );
+
+    // The first error ends the chain
+    let status = say_hello(&server, &[]).await.unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+    let status = say_hello(&server, &[token, ("x-deny", "context")])
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    assert_eq!(status.message(), "context");
+
+    // Interceptors for all services apply to the hotel, the others don't
+    assert_eq!(
+        rates(&server, &[]).await.unwrap_err().code(),
+        Code::Unauthenticated
+    );
+    assert!(rates(&server, &[token, ("x-deny", "attribute")])
+        .await
+        .is_ok());
+    assert!(rates(&server, &[token, ("x-deny", "all")]).await.is_err());
+
+    // Health is not intercepted
+    assert_eq!(
+        server.health_status("helloworld.Greeter").await.unwrap(),
+        mrbig_core::tonic_health::ServingStatus::Serving
+    );
+
+    server.stop().await.expect("failed to stop service");
+}
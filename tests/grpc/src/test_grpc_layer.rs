@@ -0,0 +1,118 @@
+pub mod helloworld {
+    tonic::include_proto!("helloworld");
+}
+
+use helloworld::greeter_client::GreeterClient;
+use helloworld::greeter_server::{Greeter, GreeterServer};
+use helloworld::{HelloReply, HelloRequest};
+use mrbig_core::layer::BoxService;
+use mrbig_core::testing::{self, TestServer};
+use mrbig_core::tonic_health::ServingStatus;
+use mrbig_core::tower::limit::ConcurrencyLimitLayer;
+use mrbig_core::tower::load_shed::LoadShedLayer;
+use mrbig_core::tower::{layer::layer_fn, service_fn, timeout::TimeoutLayer, ServiceExt};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Default)]
+pub struct Welcome {}
+
+#[tonic::async_trait]
+impl Greeter for Welcome {
+    async fn say_hello(
+        &self,
+        request: tonic::Request<HelloRequest>,
+    ) -> Result<tonic::Response<HelloReply>, tonic::Status> {
+        // the name is the time to take to reply, in milliseconds
+        let name = request.into_inner().name;
+        let millis = name.parse().unwrap_or(0);
+        tokio::time::delay_for(Duration::from_millis(millis)).await;
+
+        Ok(tonic::Response::new(HelloReply {
+            message: format!("Hello after {}ms", name),
+        }))
+    }
+}
+
+use mrbig_derive::{Configurable, Run};
+
+// Use macro to register endpoints
+#[derive(Run, Configurable, Default)]
+#[mrbig_register_grpc(service = "helloworld.Greeter")]
+pub struct Micro {
+    context: mrbig_core::Context,
+}
+
+async fn say_hello(server: &TestServer, millis: u64) -> Result<String, tonic::Status> {
+    let request = HelloRequest {
+        name: millis.to_string(),
+    };
+    let reply = GreeterClient::new(server.channel())
+        .say_hello(request)
+        .await?;
+    Ok(reply.into_inner().message)
+}
+
+/// Stacks a counting layer, a timeout, load shedding and a concurrency
+/// limit in front of the services and checks they apply to the registered
+/// and builtin services alike.
+#[tokio::main]
+async fn main() {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counted = requests.clone();
+
+    let server = TestServer::start(async move {
+        let mut service = Micro::default();
+        service.init_with_args(testing::args(&[])).await?;
+
+        service
+            .context
+            .add_layer(layer_fn(move |inner: BoxService| {
+                let counted = counted.clone();
+                service_fn(move |request| {
+                    counted.fetch_add(1, Ordering::SeqCst);
+                    // the inner layers are polled for readiness
+                    inner.clone().oneshot(request)
+                })
+            }));
+        service
+            .context
+            .add_layer(TimeoutLayer::new(Duration::from_millis(500)));
+        // one call at a time, the others are shed
+        service.context.add_layer(LoadShedLayer::new());
+        service.context.add_layer(ConcurrencyLimitLayer::new(1));
+
+        service.start(Welcome {}).await
+    })
+    .await
+    .expect("failed to start service");
+
+    assert_eq!(say_hello(&server, 0).await.unwrap(), "Hello after 0ms");
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+    // The builtin servers are behind the layers as well
+    assert_eq!(
+        server.health_status("helloworld.Greeter").await.unwrap(),
+        ServingStatus::Serving
+    );
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+    // Calls outliving the timeout fail, the others go through
+    assert!(say_hello(&server, 2000).await.is_err());
+    assert_eq!(say_hello(&server, 100).await.unwrap(), "Hello after 100ms");
+    assert_eq!(requests.load(Ordering::SeqCst), 4);
+
+    // Calls over the concurrency limit are shed, until the limit is freed
+    let (slow, shed) = futures::future::join(say_hello(&server, 300), async {
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+        say_hello(&server, 0).await
+    })
+    .await;
+    assert_eq!(slow.unwrap(), "Hello after 300ms");
+    assert!(shed.is_err());
+    assert_eq!(say_hello(&server, 0).await.unwrap(), "Hello after 0ms");
+    assert_eq!(requests.load(Ordering::SeqCst), 7);
+
+    server.stop().await.expect("failed to stop service");
+}
@@ -0,0 +1,99 @@
+pub mod helloworld {
+    tonic::include_proto!("helloworld");
+}
+
+use helloworld::greeter_client::GreeterClient;
+use helloworld::greeter_server::{Greeter, GreeterServer};
+use helloworld::{HelloReply, HelloRequest};
+use mrbig_core::testing::{self, TestServer};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Default)]
+pub struct Welcome {}
+
+#[tonic::async_trait]
+impl Greeter for Welcome {
+    async fn say_hello(
+        &self,
+        request: tonic::Request<HelloRequest>,
+    ) -> Result<tonic::Response<HelloReply>, tonic::Status> {
+        Ok(tonic::Response::new(HelloReply {
+            message: format!("Hello {}", request.into_inner().name),
+        }))
+    }
+}
+
+use mrbig_derive::{Configurable, Run};
+
+// Use macro to register endpoints
+#[derive(Run, Configurable, Default)]
+#[mrbig_register_grpc(service = "helloworld.Greeter")]
+pub struct Micro {
+    context: mrbig_core::Context,
+}
+
+async fn say_hello(server: &TestServer, name: &str) -> Result<String, tonic::Status> {
+    let request = HelloRequest { name: name.into() };
+    let reply = GreeterClient::new(server.channel())
+        .say_hello(request)
+        .await?;
+    Ok(reply.into_inner().message)
+}
+
+/// Sends SIGHUP to the process every 10ms until `stop` is set, counting
+/// the signals in `sent`.
+fn hang_up(stop: Arc<AtomicBool>, sent: Arc<AtomicUsize>) {
+    std::thread::spawn(move || {
+        while !stop.load(Ordering::SeqCst) {
+            let status = std::process::Command::new("kill")
+                .args(&["-HUP", &std::process::id().to_string()])
+                .status()
+                .expect("failed to send signal");
+            assert!(status.success());
+            sent.fetch_add(1, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    });
+}
+
+/// Sends SIGHUP to the service while it starts and while it serves, with a
+/// valid then an invalid configuration file, and checks it keeps serving.
+#[tokio::main]
+async fn main() {
+    let config = std::env::temp_dir().join(format!("test_grpc_reload_{}.toml", std::process::id()));
+    std::fs::write(&config, "[service]\nshutdown_grace = \"1s\"\n").expect("failed to write");
+
+    let path = config.to_str().unwrap().to_string();
+    let stop = Arc::new(AtomicBool::new(false));
+    let sent = Arc::new(AtomicUsize::new(0));
+    let (signals, counted) = (stop.clone(), sent.clone());
+    let server = TestServer::start(async move {
+        let mut service = Micro::default();
+        service
+            .init_with_args(testing::args(&["--config", &path]))
+            .await?;
+        // SIGHUP is trapped as soon as the service starts
+        hang_up(signals, counted);
+        service.start(Welcome {}).await
+    })
+    .await
+    .expect("failed to start service");
+
+    // Reloaded while serving
+    assert_eq!(say_hello(&server, "first").await.unwrap(), "Hello first");
+    std::fs::write(&config, "[service]\nshutdown_grace = \"2s\"\n").expect("failed to write");
+    tokio::time::delay_for(Duration::from_millis(100)).await;
+
+    // A configuration failing to load is logged, and the current one kept
+    std::fs::write(&config, "[service\n").expect("failed to write");
+    tokio::time::delay_for(Duration::from_millis(100)).await;
+    assert_eq!(say_hello(&server, "second").await.unwrap(), "Hello second");
+
+    stop.store(true, Ordering::SeqCst);
+    assert!(sent.load(Ordering::SeqCst) > 1);
+
+    server.stop().await.expect("failed to stop service");
+    std::fs::remove_file(&config).expect("failed to remove the config");
+}
@@ -0,0 +1,146 @@
+pub mod helloworld {
+    tonic::include_proto!("helloworld");
+}
+pub mod health {
+    tonic::include_proto!("grpc.health.v1");
+}
+
+use health::health_check_response::ServingStatus;
+use health::health_client::HealthClient;
+use health::HealthCheckRequest;
+use helloworld::greeter_client::GreeterClient;
+use helloworld::greeter_server::{Greeter, GreeterServer};
+use helloworld::{HelloReply, HelloRequest};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tonic::transport::Channel;
+
+#[derive(Debug, Default)]
+pub struct Welcome {}
+
+#[tonic::async_trait]
+impl Greeter for Welcome {
+    async fn say_hello(
+        &self,
+        request: tonic::Request<HelloRequest>,
+    ) -> Result<tonic::Response<HelloReply>, tonic::Status> {
+        // the name is the time to take to reply, in milliseconds
+        let name = request.into_inner().name;
+        let millis = name.parse().unwrap_or(0);
+        tokio::time::delay_for(Duration::from_millis(millis)).await;
+
+        Ok(tonic::Response::new(HelloReply {
+            message: format!("Hello after {}ms", name),
+        }))
+    }
+}
+
+use mrbig_derive::{Configurable, Run};
+
+// Use macro to register endpoints
+#[derive(Run, Configurable, Default)]
+#[mrbig_register_grpc(service = "helloworld.Greeter")]
+pub struct Micro {
+    context: mrbig_core::Context,
+}
+
+const PORT: u16 = 50445;
+const GRACE: Duration = Duration::from_secs(1);
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+async fn connect() -> Channel {
+    let endpoint = Channel::from_shared(format!("http://127.0.0.1:{}", PORT)).unwrap();
+    for _ in 0..50 {
+        if let Ok(channel) = endpoint.connect().await {
+            return channel;
+        }
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+    }
+    panic!("failed to connect to the service");
+}
+
+async fn health(channel: Channel) -> ServingStatus {
+    let request = HealthCheckRequest {
+        service: "helloworld.Greeter".into(),
+    };
+    let response = HealthClient::new(channel)
+        .check(request)
+        .await
+        .expect("failed to check health");
+    ServingStatus::from_i32(response.into_inner().status).unwrap()
+}
+
+async fn say_hello(channel: Channel, millis: u64) -> Result<String, tonic::Status> {
+    let request = HelloRequest {
+        name: millis.to_string(),
+    };
+    let reply = GreeterClient::new(channel).say_hello(request).await?;
+    Ok(reply.into_inner().message)
+}
+
+/// Runs the service, sends it the signal given as argument (`TERM` or
+/// `INT`) while requests are in flight, and checks each phase of the
+/// shutdown.
+#[tokio::main]
+async fn main() {
+    let signal = std::env::args().nth(1).unwrap_or_else(|| "TERM".into());
+
+    let mut service = Micro::default();
+    let args = vec![
+        "micro".into(),
+        "--hostname".into(),
+        "127.0.0.1".into(),
+        "--port".into(),
+        PORT.to_string(),
+        "--set".into(),
+        format!("service.shutdown_grace={}s", GRACE.as_secs()),
+        "--set".into(),
+        format!("service.drain_timeout={}s", DRAIN_TIMEOUT.as_secs()),
+    ];
+    service
+        .init_with_args(args)
+        .await
+        .expect("failed to init service");
+
+    let hook_ran = Arc::new(AtomicBool::new(false));
+    let hook_flag = hook_ran.clone();
+    service.context.on_shutdown(move || async move {
+        hook_flag.store(true, Ordering::SeqCst);
+    });
+
+    let server = tokio::spawn(async move { service.run(Welcome {}).await });
+
+    let channel = connect().await;
+    assert_eq!(health(channel.clone()).await, ServingStatus::Serving);
+
+    // A request completing while draining, and one outliving the drain timeout
+    let completed = tokio::spawn(say_hello(channel.clone(), 1500));
+    let abandoned = tokio::spawn(say_hello(channel.clone(), 60_000));
+    tokio::time::delay_for(Duration::from_millis(100)).await;
+
+    let signaled = Instant::now();
+    let status = std::process::Command::new("kill")
+        .args(&[&format!("-{}", signal), &std::process::id().to_string()])
+        .status()
+        .expect("failed to send signal");
+    assert!(status.success());
+
+    // Health is flipped first, while requests are still served
+    tokio::time::delay_for(GRACE / 2).await;
+    assert_eq!(health(channel.clone()).await, ServingStatus::NotServing);
+    assert!(say_hello(channel.clone(), 0).await.is_ok());
+
+    // The server stops once the drain timeout elapses
+    server
+        .await
+        .unwrap()
+        .expect("failed to shut down gracefully");
+    let elapsed = signaled.elapsed();
+    assert!(elapsed >= GRACE + DRAIN_TIMEOUT, "{:?}", elapsed);
+    assert!(elapsed < GRACE + DRAIN_TIMEOUT * 2, "{:?}", elapsed);
+
+    assert_eq!(completed.await.unwrap().unwrap(), "Hello after 1500ms");
+    assert!(hook_ran.load(Ordering::SeqCst));
+    drop(abandoned);
+}
@@ -0,0 +1,115 @@
+pub mod helloworld {
+    tonic::include_proto!("helloworld");
+}
+
+use helloworld::greeter_client::GreeterClient;
+use helloworld::greeter_server::{Greeter, GreeterServer};
+use helloworld::{HelloReply, HelloRequest};
+use mrbig_core::grpc_reflection::proto::server_reflection_request::MessageRequest;
+use mrbig_core::grpc_reflection::proto::server_reflection_response::MessageResponse;
+use mrbig_core::grpc_reflection::proto::ServerReflectionRequest;
+use mrbig_core::testing::{self, TestServer};
+use mrbig_core::tonic_health::ServingStatus;
+
+#[derive(Debug, Default)]
+pub struct Welcome {}
+
+#[tonic::async_trait]
+impl Greeter for Welcome {
+    async fn say_hello(
+        &self,
+        request: tonic::Request<HelloRequest>,
+    ) -> Result<tonic::Response<HelloReply>, tonic::Status> {
+        Ok(tonic::Response::new(HelloReply {
+            message: format!("Hello {}", request.into_inner().name),
+        }))
+    }
+}
+
+use mrbig_derive::{Configurable, Run};
+
+// Use macro to register endpoints
+#[derive(Run, Configurable, Default)]
+#[mrbig_register_grpc(service = "helloworld.Greeter")]
+pub struct Micro {
+    context: mrbig_core::Context,
+}
+
+async fn start() -> TestServer {
+    TestServer::with(Micro::default(), (Welcome {},))
+        .await
+        .expect("failed to start service")
+}
+
+async fn say_hello(server: &TestServer, name: &str) -> Result<String, tonic::Status> {
+    let request = HelloRequest { name: name.into() };
+    let reply = GreeterClient::new(server.channel())
+        .say_hello(request)
+        .await?;
+    Ok(reply.into_inner().message)
+}
+
+/// Runs two services side by side with the testing harness, calls them
+/// through the clients it hands out and checks they are torn down.
+#[tokio::main]
+async fn main() {
+    let first = start().await;
+    let second = start().await;
+    assert_ne!(first.addr(), second.addr());
+
+    assert_eq!(say_hello(&first, "first").await.unwrap(), "Hello first");
+    assert_eq!(say_hello(&second, "second").await.unwrap(), "Hello second");
+
+    assert_eq!(
+        first.health_status("helloworld.Greeter").await.unwrap(),
+        ServingStatus::Serving
+    );
+
+    // The reflection server lists the registered service
+    let request = ServerReflectionRequest {
+        host: String::new(),
+        message_request: Some(MessageRequest::ListServices(String::new())),
+    };
+    let mut responses = first
+        .reflection()
+        .server_reflection_info(futures::stream::iter(vec![request]))
+        .await
+        .expect("failed to query reflection")
+        .into_inner();
+    let response = responses.message().await.unwrap().unwrap();
+    let services: Vec<String> = match response.message_response {
+        Some(MessageResponse::ListServicesResponse(list)) => {
+            list.service.into_iter().map(|s| s.name).collect()
+        }
+        r => panic!("unexpected reflection response {:?}", r),
+    };
+    assert!(services.contains(&"helloworld.Greeter".to_string()));
+
+    // Stopping one leaves the other serving
+    let first_addr = first.addr();
+    first.stop().await.expect("failed to stop service");
+    let endpoint = tonic::transport::Endpoint::from_shared(format!("http://{}", first_addr));
+    assert!(endpoint.unwrap().connect().await.is_err());
+    assert_eq!(say_hello(&second, "again").await.unwrap(), "Hello again");
+
+    // Dropping shuts the server down
+    let second_addr = second.addr();
+    drop(second);
+    tokio::time::delay_for(std::time::Duration::from_millis(500)).await;
+    let endpoint = tonic::transport::Endpoint::from_shared(format!("http://{}", second_addr));
+    assert!(endpoint.unwrap().connect().await.is_err());
+
+    // A metrics server which cannot bind fails the start
+    let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = format!("service.metrics.port={}", taken.local_addr().unwrap().port());
+    for set in &["service.metrics.hostname=metrics.invalid", port.as_str()] {
+        let err = TestServer::start(async {
+            let mut service = Micro::default();
+            service.init_with_args(testing::args(&["--set", set])).await?;
+            service.start(Welcome {}).await
+        })
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("metrics"), "{}", err);
+    }
+}
@@ -0,0 +1,194 @@
+pub mod helloworld {
+    tonic::include_proto!("helloworld");
+}
+
+use helloworld::greeter_client::GreeterClient;
+use helloworld::greeter_server::{Greeter, GreeterServer};
+use helloworld::{HelloReply, HelloRequest};
+use mrbig_core::tls::PeerIdentity;
+use rcgen::{BasicConstraints, Certificate, CertificateParams, DnType, IsCa, SanType};
+use std::path::Path;
+use std::time::Duration;
+use tonic::transport::{Channel, ClientTlsConfig, Identity};
+
+#[derive(Debug, Default)]
+pub struct Welcome {}
+
+#[tonic::async_trait]
+impl Greeter for Welcome {
+    async fn say_hello(
+        &self,
+        request: tonic::Request<HelloRequest>,
+    ) -> Result<tonic::Response<HelloReply>, tonic::Status> {
+        let peer = PeerIdentity::of(&request)
+            .ok_or_else(|| tonic::Status::unauthenticated("no client certificate"))?;
+
+        Ok(tonic::Response::new(HelloReply {
+            message: format!(
+                "Hello {}, {} ({})",
+                request.into_inner().name,
+                peer.common_name.unwrap_or_default(),
+                peer.alt_names.join(", ")
+            ),
+        }))
+    }
+}
+
+use mrbig_derive::{Configurable, Run};
+
+// Use macro to register endpoints
+#[derive(Run, Configurable, Default)]
+#[mrbig_register_grpc(service = "helloworld.Greeter")]
+pub struct Micro {
+    context: mrbig_core::Context,
+}
+
+const PORT: u16 = 50443;
+
+fn ca(name: &str) -> Certificate {
+    let mut params = CertificateParams::default();
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    params.distinguished_name.push(DnType::CommonName, name);
+    Certificate::from_params(params).unwrap()
+}
+
+/// Writes a certificate signed by `ca` and its key, as `<name>.pem`
+/// and `<name>.key` in `dir`.
+fn issue(dir: &Path, name: &str, ca: &Certificate, sans: Vec<SanType>) {
+    let mut params = CertificateParams::default();
+    params.distinguished_name.push(DnType::CommonName, name);
+    params.subject_alt_names = sans;
+    let cert = Certificate::from_params(params).unwrap();
+
+    std::fs::write(
+        dir.join(format!("{}.pem", name)),
+        cert.serialize_pem_with_signer(ca).unwrap(),
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join(format!("{}.key", name)),
+        cert.serialize_private_key_pem(),
+    )
+    .unwrap();
+}
+
+fn read(dir: &Path, file: &str) -> String {
+    std::fs::read_to_string(dir.join(file)).unwrap()
+}
+
+async fn say_hello(
+    trusted_ca: &str,
+    identity: Option<(String, String)>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut tls = ClientTlsConfig::new()
+        .ca_certificate(tonic::transport::Certificate::from_pem(trusted_ca))
+        .domain_name("localhost");
+    if let Some((cert, key)) = identity {
+        tls = tls.identity(Identity::from_pem(cert, key));
+    }
+
+    let channel = Channel::from_shared(format!("https://localhost:{}", PORT))?
+        .tls_config(tls)?
+        .connect()
+        .await?;
+    let reply = GreeterClient::new(channel)
+        .say_hello(HelloRequest { name: "tls".into() })
+        .await?;
+
+    Ok(reply.into_inner().message)
+}
+
+#[tokio::main]
+async fn main() {
+    let dir = std::env::temp_dir().join("mrbig_test_grpc_tls");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    // Certificate authorities, server and client certificates
+    let (ca1, ca2) = (ca("ca1"), ca("ca2"));
+    for (name, ca) in &[("ca1", &ca1), ("ca2", &ca2)] {
+        std::fs::write(
+            dir.join(format!("{}.pem", name)),
+            ca.serialize_pem().unwrap(),
+        )
+        .unwrap();
+    }
+    issue(
+        &dir,
+        "server",
+        &ca1,
+        vec![SanType::DnsName("localhost".into())],
+    );
+    issue(
+        &dir,
+        "client",
+        &ca1,
+        vec![SanType::URI("spiffe://mrbig/client".into())],
+    );
+
+    let config = dir.join("config.toml");
+    std::fs::write(
+        &config,
+        format!(
+            r#"
+            [service]
+            hostname = "127.0.0.1"
+            port = {}
+
+            [service.grpc_server.tls]
+            cert = "{dir}/server.pem"
+            key = "{dir}/server.key"
+            client_ca = "{dir}/ca1.pem"
+            reload_interval = "500ms"
+            "#,
+            PORT,
+            dir = dir.display()
+        ),
+    )
+    .unwrap();
+
+    let mut service = Micro::default();
+    let args = vec!["micro".into(), "-c".into(), config.to_str().unwrap().into()];
+    service
+        .init_with_args(args)
+        .await
+        .expect("failed to init service");
+
+    tokio::spawn(async move {
+        service
+            .run(Welcome {})
+            .await
+            .expect("failed to run service")
+    });
+
+    let client = || Some((read(&dir, "client.pem"), read(&dir, "client.key")));
+
+    // Mutual TLS: the handler gets the client identity
+    let mut reply = Err("server not started".into());
+    for _ in 0..50 {
+        reply = say_hello(&read(&dir, "ca1.pem"), client()).await;
+        if reply.is_ok() {
+            break;
+        }
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+    }
+    assert_eq!(reply.unwrap(), "Hello tls, client (spiffe://mrbig/client)");
+
+    // Clients without a certificate are rejected
+    assert!(say_hello(&read(&dir, "ca1.pem"), None).await.is_err());
+
+    // Renewed server certificates are picked up without a restart
+    issue(
+        &dir,
+        "server",
+        &ca2,
+        vec![SanType::DnsName("localhost".into())],
+    );
+    tokio::time::delay_for(Duration::from_millis(1500)).await;
+
+    assert!(say_hello(&read(&dir, "ca1.pem"), client()).await.is_err());
+    assert_eq!(
+        say_hello(&read(&dir, "ca2.pem"), client()).await.unwrap(),
+        "Hello tls, client (spiffe://mrbig/client)"
+    );
+}
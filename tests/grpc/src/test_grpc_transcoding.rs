@@ -0,0 +1,135 @@
+include!("hotel_head.rs");
+
+use hotel::hotel_client::HotelClient;
+use hyper::{Body, Client, Method, StatusCode};
+use mrbig_core::testing::{self, TestServer};
+use serde_json::{json, Value};
+
+use mrbig_derive::{Configurable, Run};
+
+// Use macro to register endpoints
+#[derive(Run, Configurable, Default)]
+#[mrbig_register_grpc(service = "hotel.Hotel")]
+#[mrbig_transcoding]
+pub struct Micro {
+    context: mrbig_core::Context,
+}
+
+const CONFIG: &str = r#"
+[service.transcoding]
+emit_defaults = true
+"#;
+
+/// Requests `path` over HTTP/1.1 with a JSON `body`, if any, returning
+/// the status and the JSON of the response.
+async fn request(
+    server: &TestServer,
+    method: Method,
+    path: &str,
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    let body = match body {
+        Some(body) => Body::from(body.to_string()),
+        None => Body::empty(),
+    };
+    let request = hyper::Request::builder()
+        .method(method)
+        .uri(format!("http://{}{}", server.addr(), path))
+        .header("content-type", "application/json")
+        .body(body)
+        .unwrap();
+
+    let response = Client::new()
+        .request(request)
+        .await
+        .expect("failed to request");
+    assert_eq!(response.headers()["content-type"], "application/json");
+
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body = serde_json::from_slice(&body).expect("invalid JSON response");
+    (status, body)
+}
+
+/// Calls the hotel service with JSON over HTTP/1.1, binding the request
+/// from the path, the query and the body, checks the HTTP status of the
+/// failed calls, and checks the gRPC clients are still served at the same
+/// address.
+#[tokio::main]
+async fn main() {
+    let config =
+        std::env::temp_dir().join(format!("test_grpc_transcoding_{}.toml", std::process::id()));
+    std::fs::write(&config, CONFIG).expect("failed to write the config");
+
+    let path = config.to_str().unwrap().to_string();
+    let server = TestServer::start(async {
+        let mut service = Micro::default();
+        service
+            .init_with_args(testing::args(&["--config", &path]))
+            .await?;
+        service.start(Booker {}).await
+    })
+    .await
+    .expect("failed to start service");
+
+    // Bound by the path, the query and the body
+    let calls = vec![
+        (Method::GET, "/v1/hotels/2020-10-01/rates", None),
+        (
+            Method::GET,
+            "/v1/hotels/2020%2D10%2D01/rates?outDate=2020-10-03",
+            None,
+        ),
+        (Method::GET, "/v1/rates?inDate=2020-10-01", None),
+        (
+            Method::POST,
+            "/v1/rates:search",
+            Some(json!({"inDate": "2020-10-01", "outDate": "2020-10-03"})),
+        ),
+    ];
+    for (method, path, body) in calls {
+        let (status, response) = request(&server, method, path, body).await;
+        assert_eq!(status, StatusCode::OK, "{}: {}", path, response);
+        assert_eq!(response["hotels"][0]["name"], "2020-10-01", "{}", path);
+        // written as `emit_defaults` is set
+        assert_eq!(response["ratePlans"], json!([]), "{}", path);
+    }
+
+    // Failed calls
+    let calls = vec![
+        (
+            Method::GET,
+            "/v1/motels/2020-10-01",
+            None,
+            StatusCode::NOT_FOUND,
+            5,
+        ),
+        (Method::DELETE, "/v1/rates", None, StatusCode::NOT_FOUND, 5),
+        (
+            Method::POST,
+            "/v1/rates:search",
+            Some(json!({"checkIn": "2020-10-01"})),
+            StatusCode::BAD_REQUEST,
+            3,
+        ),
+    ];
+    for (method, path, body, expected, code) in calls {
+        let (status, response) = request(&server, method, path, body).await;
+        assert_eq!(status, expected, "{}: {}", path, response);
+        assert_eq!(response["code"], code, "{}", path);
+        assert!(response["message"].is_string(), "{}", path);
+    }
+
+    // gRPC clients are served at the same address
+    let reply = HotelClient::new(server.channel())
+        .rates(HotelRequest {
+            in_date: "2020-10-02".into(),
+            ..Default::default()
+        })
+        .await
+        .expect("failed to call with gRPC");
+    assert_eq!(reply.into_inner().hotels[0].name, "2020-10-02");
+
+    server.stop().await.expect("failed to stop service");
+    std::fs::remove_file(&config).expect("failed to remove the config");
+}
@@ -0,0 +1,114 @@
+pub mod helloworld {
+    tonic::include_proto!("helloworld");
+}
+
+use helloworld::greeter_client::GreeterClient;
+use helloworld::greeter_server::{Greeter, GreeterServer};
+use helloworld::{HelloReply, HelloRequest};
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::net::UnixStream;
+use tonic::transport::{Channel, Endpoint, Uri};
+
+#[derive(Debug, Default)]
+pub struct Welcome {}
+
+#[tonic::async_trait]
+impl Greeter for Welcome {
+    async fn say_hello(
+        &self,
+        request: tonic::Request<HelloRequest>,
+    ) -> Result<tonic::Response<HelloReply>, tonic::Status> {
+        Ok(tonic::Response::new(HelloReply {
+            message: format!("Hello {}", request.into_inner().name),
+        }))
+    }
+}
+
+use mrbig_derive::{Configurable, Run};
+
+// Use macro to register endpoints
+#[derive(Run, Configurable, Default)]
+#[mrbig_register_grpc(service = "helloworld.Greeter")]
+pub struct Micro {
+    context: mrbig_core::Context,
+}
+
+const PORT: u16 = 50444;
+
+async fn say_hello(channel: Channel) -> String {
+    GreeterClient::new(channel)
+        .say_hello(HelloRequest { name: "uds".into() })
+        .await
+        .expect("failed to say hello")
+        .into_inner()
+        .message
+}
+
+async fn unix_channel(socket: PathBuf) -> Result<Channel, tonic::transport::Error> {
+    // the URI is required but ignored by the connector
+    Endpoint::from_static("http://[::]:50051")
+        .connect_with_connector(tower::service_fn(move |_: Uri| {
+            UnixStream::connect(socket.clone())
+        }))
+        .await
+}
+
+#[tokio::main]
+async fn main() {
+    let dir = std::env::temp_dir().join("mrbig_test_grpc_uds");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    // A socket left behind by a previous run is replaced
+    let socket = dir.join("micro.sock");
+    drop(std::os::unix::net::UnixListener::bind(&socket).unwrap());
+
+    let mut service = Micro::default();
+    let listen = format!(
+        r#"service.listen=["tcp://127.0.0.1:{}", "unix://{}"]"#,
+        PORT,
+        socket.display()
+    );
+    let args = vec![
+        "micro".into(),
+        "--set".into(),
+        listen,
+        "--set".into(),
+        "service.socket_mode=0600".into(),
+    ];
+    service
+        .init_with_args(args)
+        .await
+        .expect("failed to init service");
+
+    tokio::spawn(async move {
+        service
+            .run(Welcome {})
+            .await
+            .expect("failed to run service")
+    });
+
+    let mut channel = None;
+    for _ in 0..50 {
+        if let Ok(connected) = unix_channel(socket.clone()).await {
+            channel = Some(connected);
+            break;
+        }
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+    }
+    let channel = channel.expect("failed to connect to the Unix socket");
+    assert_eq!(say_hello(channel).await, "Hello uds");
+
+    let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    // The TCP address is served as well
+    let channel = Endpoint::from_shared(format!("http://127.0.0.1:{}", PORT))
+        .unwrap()
+        .connect()
+        .await
+        .expect("failed to connect to the TCP address");
+    assert_eq!(say_hello(channel).await, "Hello uds");
+}
@@ -0,0 +1,241 @@
+include!("hotel_head.rs");
+
+use hotel::hotel_client::HotelClient;
+use hyper::{Body, Client, Method, StatusCode};
+use mrbig_core::layer::BoxService;
+use mrbig_core::testing::{self, TestServer};
+use mrbig_core::tower::{layer::layer_fn, service_fn, ServiceExt};
+use prost::Message;
+use std::time::{Duration, Instant};
+
+use mrbig_derive::{Configurable, Run};
+
+// Use macro to register endpoints
+#[derive(Run, Configurable, Default)]
+#[mrbig_register_grpc(service = "hotel.Hotel")]
+pub struct Micro {
+    context: mrbig_core::Context,
+}
+
+const ORIGIN: &str = "https://app.example.com";
+
+/// Delay of the calls asking for it.
+const SLOW: Duration = Duration::from_millis(300);
+
+fn config_file() -> String {
+    format!(
+        r#"
+        [service.grpc_server]
+        concurrency_limit_per_connection = 1
+
+        [service.grpc_web.cors]
+        allowed_origins = ["{}"]
+        allowed_headers = ["authorization"]
+        "#,
+        ORIGIN
+    )
+}
+
+/// A message in a gRPC frame.
+fn frame(message: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0];
+    frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    frame.extend_from_slice(message);
+    frame
+}
+
+/// The messages and the trailers of a gRPC-Web response body.
+fn unframe(mut body: &[u8]) -> (Vec<Vec<u8>>, String) {
+    let (mut messages, mut trailers) = (vec![], String::new());
+    while !body.is_empty() {
+        let mut len = [0; 4];
+        len.copy_from_slice(&body[1..5]);
+        let len = u32::from_be_bytes(len) as usize;
+        let payload = &body[5..5 + len];
+        if body[0] & 0x80 == 0 {
+            messages.push(payload.to_vec());
+        } else {
+            trailers.push_str(std::str::from_utf8(payload).unwrap());
+        }
+        body = &body[5 + len..];
+    }
+    (messages, trailers)
+}
+
+/// Calls `path` with gRPC-Web over HTTP/1.1, binary or base64 encoded,
+/// returning the messages and the trailers, or the headers of the
+/// trailers-only responses.
+async fn call(
+    server: &TestServer,
+    path: &str,
+    message: &[u8],
+    text: bool,
+) -> (Vec<Vec<u8>>, String) {
+    let (content_type, body) = match text {
+        false => ("application/grpc-web+proto", frame(message)),
+        true => (
+            "application/grpc-web-text",
+            base64::encode(frame(message)).into_bytes(),
+        ),
+    };
+    let request = hyper::Request::post(format!("http://{}{}", server.addr(), path))
+        .header("content-type", content_type)
+        .header("x-grpc-web", "1")
+        .header("origin", ORIGIN)
+        .body(Body::from(body))
+        .unwrap();
+
+    let response = Client::new()
+        .request(request)
+        .await
+        .expect("failed to call with gRPC-Web");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with(content_type.trim_end_matches("+proto")));
+    assert_eq!(response.headers()["access-control-allow-origin"], ORIGIN);
+
+    if let Some(status) = response.headers().get("grpc-status") {
+        return (
+            vec![],
+            format!("grpc-status:{}\r\n", status.to_str().unwrap()),
+        );
+    }
+
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    match text {
+        false => unframe(&body),
+        true => {
+            // each frame is encoded on its own
+            let mut decoded = vec![];
+            for quantum in body.chunks(4) {
+                decoded.extend(base64::decode(quantum).unwrap());
+            }
+            unframe(&decoded)
+        }
+    }
+}
+
+/// Calls `path` with gRPC-Web over HTTP/1.1, on a connection of its own,
+/// the call being delayed by the layer of the service.
+async fn slow_call(server: &TestServer, path: &str, message: &[u8]) {
+    let request = hyper::Request::post(format!("http://{}{}", server.addr(), path))
+        .header("content-type", "application/grpc-web+proto")
+        .header("x-grpc-web", "1")
+        .header("x-slow", "1")
+        .body(Body::from(frame(message)))
+        .unwrap();
+
+    let response = Client::new()
+        .request(request)
+        .await
+        .expect("failed to call with gRPC-Web");
+    assert_eq!(response.status(), StatusCode::OK);
+    hyper::body::to_bytes(response.into_body()).await.unwrap();
+}
+
+/// Calls the hotel and the health services with gRPC-Web, binary and text,
+/// over HTTP/1.1, checks the CORS preflight requests and the concurrency
+/// limit per connection, and checks the gRPC clients are still served at
+/// the same address.
+#[tokio::main]
+async fn main() {
+    let config = std::env::temp_dir().join(format!("test_grpc_web_{}.toml", std::process::id()));
+    std::fs::write(&config, config_file()).expect("failed to write the config");
+
+    let path = config.to_str().unwrap().to_string();
+    let server = TestServer::start(async {
+        let mut service = Micro::default();
+        service
+            .init_with_args(testing::args(&["--config", &path]))
+            .await?;
+        // the calls asking for it are delayed
+        service.context.add_layer(layer_fn(|inner: BoxService| {
+            service_fn(move |request: mrbig_core::layer::Request| {
+                let inner = inner.clone();
+                async move {
+                    if request.headers().contains_key("x-slow") {
+                        tokio::time::delay_for(SLOW).await;
+                    }
+                    inner.oneshot(request).await
+                }
+            })
+        }));
+        service.start(Booker {}).await
+    })
+    .await
+    .expect("failed to start service");
+
+    let mut rates = vec![];
+    HotelRequest {
+        in_date: "2020-10-01".into(),
+        ..Default::default()
+    }
+    .encode(&mut rates)
+    .unwrap();
+
+    for text in vec![false, true] {
+        let (messages, trailers) = call(&server, "/hotel.Hotel/Rates", &rates, text).await;
+        assert_eq!(messages.len(), 1);
+        let response = HotelResponse::decode(&messages[0][..]).unwrap();
+        assert_eq!(response.hotels[0].name, "2020-10-01");
+        assert!(trailers.contains("grpc-status:0\r\n"), "{:?}", trailers);
+    }
+
+    // The health service as well: `service = "hotel.Hotel"`, then SERVING
+    let mut check = vec![0x0a, 11];
+    check.extend_from_slice(b"hotel.Hotel");
+    let (messages, trailers) = call(&server, "/grpc.health.v1.Health/Check", &check, false).await;
+    assert_eq!(messages, vec![vec![0x08, 0x01]]);
+    assert!(trailers.contains("grpc-status:0\r\n"), "{:?}", trailers);
+
+    // Unknown services are unimplemented
+    let (messages, trailers) = call(&server, "/hotel.Motel/Rates", &rates, false).await;
+    assert!(messages.is_empty());
+    assert!(trailers.contains("grpc-status:12\r\n"), "{:?}", trailers);
+
+    // Preflight requests, from allowed origins or not
+    for (origin, allowed) in vec![(ORIGIN, true), ("https://evil.example.com", false)] {
+        let preflight = hyper::Request::builder()
+            .method(Method::OPTIONS)
+            .uri(format!("http://{}/hotel.Hotel/Rates", server.addr()))
+            .header("origin", origin)
+            .header("access-control-request-method", "POST")
+            .header("access-control-request-headers", "content-type,x-grpc-web")
+            .body(Body::empty())
+            .unwrap();
+        let response = Client::new().request(preflight).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        let headers = response.headers();
+        assert_eq!(headers.contains_key("access-control-allow-origin"), allowed);
+        if allowed {
+            assert_eq!(headers["access-control-allow-methods"], "POST, OPTIONS");
+            let allowed_headers = headers["access-control-allow-headers"].to_str().unwrap();
+            assert!(allowed_headers.contains("x-grpc-web"));
+            assert!(allowed_headers.contains("authorization"));
+            assert_eq!(headers["access-control-max-age"], "600");
+        }
+    }
+
+    // The concurrency limit applies per connection
+    let started = Instant::now();
+    futures::future::join_all((0..4).map(|_| slow_call(&server, "/hotel.Hotel/Rates", &rates)))
+        .await;
+    let elapsed = started.elapsed();
+    assert!(elapsed < SLOW * 3, "{:?}", elapsed);
+
+    // gRPC clients are served at the same address
+    let reply = HotelClient::new(server.channel())
+        .rates(HotelRequest {
+            in_date: "2020-10-02".into(),
+            ..Default::default()
+        })
+        .await
+        .expect("failed to call with gRPC");
+    assert_eq!(reply.into_inner().hotels[0].name, "2020-10-02");
+
+    server.stop().await.expect("failed to stop service");
+    std::fs::remove_file(&config).expect("failed to remove the config");
+}
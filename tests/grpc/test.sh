@@ -8,7 +8,18 @@ cargo build --quiet \
       --bin test_grpc_no_reflection \
       --bin test_grpc_multiple \
       --bin test_grpc_no_health \
-      --bin test_grpc_traceable
+      --bin test_grpc_traceable \
+      --bin test_grpc_tls \
+      --bin test_grpc_uds \
+      --bin test_grpc_shutdown \
+      --bin test_grpc_handle \
+      --bin test_grpc_testing \
+      --bin test_grpc_interceptor \
+      --bin test_grpc_layer \
+      --bin test_grpc_reload \
+      --bin test_grpc_auth \
+      --bin test_grpc_web \
+      --bin test_grpc_transcoding
 
 # Project path
 PROJECT_DIR=$(realpath $(pwd)/../..)
@@ -34,3 +45,26 @@ $COV ${TARGET_DIR}/test_grpc_traceable -d \
      | grep "INFO server{fakerequestid}: test_grpc_traceable:" -A4 \
      | grep "INFO server{fakebadrequestid}: test_grpc_traceable:" -A1 \
      | grep -E "DEBUG server{fakebadrequestid}: test_grpc_traceable: Hotel/Rates (.*) -- ERR: status: NotFound, message:"
+# Serves over mutual TLS with certificates generated on the fly
+$COV ${TARGET_DIR}/test_grpc_tls
+# Serves over a Unix domain socket and a TCP address at once
+$COV ${TARGET_DIR}/test_grpc_uds
+# Shuts down gracefully on SIGTERM and SIGINT
+$COV ${TARGET_DIR}/test_grpc_shutdown TERM
+$COV ${TARGET_DIR}/test_grpc_shutdown INT
+# Starts in-process on a port left to the system, then shuts down
+$COV ${TARGET_DIR}/test_grpc_handle
+# Runs two services side by side with the mrbig_core::testing harness
+$COV ${TARGET_DIR}/test_grpc_testing
+# Chains the interceptors of the attributes and of the context
+$COV ${TARGET_DIR}/test_grpc_interceptor
+# Stacks tower layers in front of all services
+$COV ${TARGET_DIR}/test_grpc_layer
+# Reloads the configuration on SIGHUP, while starting too
+$COV ${TARGET_DIR}/test_grpc_reload
+# Authenticates the calls with JWT bearer tokens
+$COV ${TARGET_DIR}/test_grpc_auth
+# Serves gRPC-Web calls over HTTP/1.1, with CORS
+$COV ${TARGET_DIR}/test_grpc_web
+# Transcodes HTTP/JSON requests of the google.api.http annotations
+$COV ${TARGET_DIR}/test_grpc_transcoding
@@ -1,4 +1,4 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::configure().compile(&["health.proto", "helloworld.proto"], &["proto/"])?;
+    mrbig_build::compile_protos(&["helloworld.proto"], &["proto/"])?;
     Ok(())
 }
@@ -1,18 +1,18 @@
-use tonic_health::{ServingStatus, server::{health_reporter, HealthReporter}};
-use tonic::transport::Server;
-use futures::lock::Mutex;
-use std::sync::Arc;
-
 pub mod helloworld {
     tonic::include_proto!("helloworld");
 }
 
+use helloworld::greeter_client::GreeterClient;
 use helloworld::greeter_server::{Greeter, GreeterServer};
 use helloworld::{HelloReply, HelloRequest};
+use mrbig_core::context::{HealthReporter, WithContext};
+use mrbig_core::testing::health::health_check_response::ServingStatus;
+use mrbig_core::testing::health::{HealthCheckRequest, HealthCheckResponse};
+use mrbig_core::testing::TestServer;
 
 #[derive(Debug)]
 pub struct Welcome {
-    reporter: Arc<Mutex<HealthReporter>>,
+    reporter: HealthReporter,
 }
 
 #[tonic::async_trait]
@@ -22,12 +22,9 @@ impl Greeter for Welcome {
         request: tonic::Request<HelloRequest>,
     ) -> Result<tonic::Response<HelloReply>, tonic::Status> {
         let name = request.into_inner().name;
-	{
-	    let mut ptr = self.reporter.lock().await;
-            match name.as_str() {
-		"John Doe" => ptr.set_not_serving::<GreeterServer<Welcome>>().await,
-		_ => ptr.set_serving::<GreeterServer<Welcome>>().await
-	    }
+        match name.as_str() {
+            "John Doe" => self.reporter.set_not_serving().await,
+            _ => self.reporter.set_serving().await,
         }
 
         Ok(tonic::Response::new(HelloReply {
@@ -36,110 +33,80 @@ impl Greeter for Welcome {
     }
 }
 
-static BIND_ADDRESS: &str = "0.0.0.0:49999";
-static TEST_SERVICE_NAME: &str = "helloworld.Greeter";
-
-// This is pseudo main function
-async fn serve() {
-    let addr = BIND_ADDRESS.parse().unwrap();
-
-    let (mut reporter, health_server) = health_reporter();
-    reporter.set_service_status("", ServingStatus::Serving).await;
-    reporter.set_service_status("helloworld.Greeter", ServingStatus::Serving).await;
+use mrbig_derive::{Configurable, Run};
 
-    let hello = Welcome { reporter: Arc::new(Mutex::new(reporter)) };
-
-    Server::builder()
-        .add_service(health_server)
-        .add_service(GreeterServer::new(hello))
-        .serve(addr)
-        .await
-        .unwrap();
+#[derive(Run, Configurable, Default)]
+#[mrbig_register_grpc(service = "helloworld.Greeter")]
+pub struct Micro {
+    context: mrbig_core::Context,
 }
 
-use tokio::process::Command;
-
-fn gcli_basic() -> Command {
-    let mut ptr = Command::new("grpc_cli");
-    ptr.arg("--noremotedb").arg("-proto_path").arg("proto/");
-    ptr
-}
-
-fn gcli_health() -> Command {
-    let mut ptr = gcli_basic();
-    ptr.arg("-protofiles")
-        .arg("health.proto")
-        .arg("call")
-        .arg(BIND_ADDRESS);
-    ptr
-}
+static TEST_SERVICE_NAME: &str = "helloworld.Greeter";
 
-fn gcli_hello() -> Command {
-    let mut ptr = gcli_basic();
-    ptr.arg("-protofiles")
-        .arg("helloworld.proto")
-        .arg("call")
-        .arg(BIND_ADDRESS);
-    ptr
+async fn say_hello(server: &TestServer, name: &str) {
+    let request = HelloRequest { name: name.into() };
+    GreeterClient::new(server.channel())
+        .say_hello(request)
+        .await
+        .expect("failed to say hello");
 }
 
-fn gcli_hello_foo() -> Command {
-    let mut ptr = gcli_hello();
-    ptr.arg("SayHello").arg("name: 'Foobar'");
-    ptr
+async fn next_status(watcher: &mut tonic::Streaming<HealthCheckResponse>) -> i32 {
+    watcher
+        .message()
+        .await
+        .unwrap()
+        .expect("the watch ended")
+        .status
 }
 
 #[tokio::main]
 async fn main() {
-    tokio::spawn(async move { serve().await });
+    let micro = Micro::default();
+    let reporter = micro
+        .get_context()
+        .get_health_reporter(TEST_SERVICE_NAME)
+        .await;
+    let server = TestServer::with(micro, (Welcome { reporter },))
+        .await
+        .expect("failed to start service");
 
-    tokio::time::delay_for(std::time::Duration::new(1, 0)).await;
+    let request = || HealthCheckRequest {
+        service: TEST_SERVICE_NAME.into(),
+    };
 
-    // Test health server by calling Check service: ''
+    // Test health server by calling Check
     {
-        // read expected result to a string
-        let exp = std::fs::read_to_string("expected/check.txt").unwrap();
-        let output = gcli_health().arg("Check").arg(&format!("service: '{}'", TEST_SERVICE_NAME)).output().await;
-
-        assert_eq!(std::str::from_utf8(&output.unwrap().stdout).unwrap(), exp);
+        let response = server.health().check(request()).await.unwrap();
+        assert_eq!(response.into_inner().status, ServingStatus::Serving as i32);
     }
 
-    // Test health server by calling Watch service: ''
+    // Test health server by calling Watch
     {
-        let exp = std::fs::read_to_string("expected/watcher.txt").unwrap();
-        let mut watcher = gcli_health()
-            .arg("Watch")
-            .arg(&format!("service: '{}'", TEST_SERVICE_NAME))
-            .stdout(std::process::Stdio::piped())
-            .spawn()
-            .expect("failed to spawn watcher");
-
-        let mut stdout = watcher.stdout.take().expect("no handle to stdout");
-
-        use tokio::io::AsyncReadExt;
+        let mut watcher = server
+            .health()
+            .watch(request())
+            .await
+            .expect("failed to watch")
+            .into_inner();
+        assert_eq!(
+            next_status(&mut watcher).await,
+            ServingStatus::Serving as i32
+        );
 
         // Now call SayHello service with names
-        {
-	    tokio::time::delay_for(std::time::Duration::new(1, 0)).await;
-
-	    gcli_hello()
-                .arg("SayHello")
-                .arg("name: 'John Doe'")
-                .output().await.unwrap();
-
-	    gcli_hello_foo().output().await.unwrap();
-
-	    tokio::time::delay_for(std::time::Duration::new(1, 0)).await;
-
-	    watcher.kill().unwrap();
-
-            let mut res = String::new();
-            stdout.read_to_string(&mut res).await.unwrap();
-
-            // let mut res = "".to_string();
-            // stdout.read_to_string(&mut res).await.unwrap();
-
-            assert_eq!(exp, res);
-        }
+        say_hello(&server, "John Doe").await;
+        assert_eq!(
+            next_status(&mut watcher).await,
+            ServingStatus::NotServing as i32
+        );
+
+        say_hello(&server, "Foobar").await;
+        assert_eq!(
+            next_status(&mut watcher).await,
+            ServingStatus::Serving as i32
+        );
     }
+
+    server.stop().await.expect("failed to stop service");
 }